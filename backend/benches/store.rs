@@ -0,0 +1,163 @@
+// Measures store throughput with a growing number of threads, against a
+// single mutex guarded map as the store was before it was sharded.
+//
+//   cargo bench -p backend --bench store
+//
+// BENCH_SECONDS sets how long each run lasts, BENCH_WRITES the percentage of
+// writes in the workload.
+
+use std::{
+    collections::HashMap,
+    env, io,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, available_parallelism},
+    time::Duration,
+};
+
+use backend::store::Store;
+use futures_executor::block_on;
+use rand::Rng;
+
+const KEYS: usize = 100_000;
+
+trait Bench: Send + Sync {
+    fn get(&self, key: &str) -> Option<String>;
+    fn insert(&self, key: String, value: String);
+}
+
+impl Bench for Store {
+    fn get(&self, key: &str) -> Option<String> {
+        Store::get(self, key).map(|(value, _)| value)
+    }
+
+    fn insert(&self, key: String, value: String) {
+        // Without a log the insert is done once it's applied.
+        block_on(Store::insert(self, key, value, None, |_, _, _| {
+            Ok::<_, io::Error>(())
+        }))
+        .expect("Insert should succeed without limits.");
+    }
+}
+
+type NewStore = fn() -> Arc<dyn Bench>;
+
+#[derive(Default)]
+struct GlobalMutex(Mutex<HashMap<String, String>>);
+
+impl Bench for GlobalMutex {
+    fn get(&self, key: &str) -> Option<String> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+
+    fn insert(&self, key: String, value: String) {
+        self.0.lock().unwrap().insert(key, value);
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn key(index: usize) -> String {
+    format!("bench:key-{}", index)
+}
+
+// Returns operations per second over all threads.
+fn run(store: Arc<dyn Bench>, threads: usize, writes: u32, duration: Duration) -> f64 {
+    let stop = Arc::new(AtomicBool::new(false));
+    let operations = Arc::new(AtomicU64::new(0));
+
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let store = store.clone();
+            let stop = stop.clone();
+            let operations = operations.clone();
+
+            thread::spawn(move || {
+                let mut rng = rand::thread_rng();
+                let mut done = 0;
+
+                while !stop.load(Ordering::Relaxed) {
+                    let key = key(rng.gen_range(0..KEYS));
+                    if rng.gen_range(0..100) < writes {
+                        store.insert(key, "value".to_string());
+                    } else {
+                        store.get(&key);
+                    }
+                    done += 1;
+                }
+
+                operations.fetch_add(done, Ordering::Relaxed);
+            })
+        })
+        .collect();
+
+    thread::sleep(duration);
+    stop.store(true, Ordering::Relaxed);
+    for worker in workers {
+        worker.join().expect("Worker should not panic.");
+    }
+
+    operations.load(Ordering::Relaxed) as f64 / duration.as_secs_f64()
+}
+
+fn main() {
+    // Ignores the flags `cargo bench` passes to harness-less benchmarks.
+    if env::args().any(|argument| argument == "--list") {
+        return;
+    }
+
+    let duration = Duration::from_secs(env_or("BENCH_SECONDS", 2));
+    let writes = env_or("BENCH_WRITES", 10).min(100);
+    let cores = available_parallelism().map_or(1, |cores| cores.get());
+
+    let mut threads = vec![1];
+    while threads[threads.len() - 1] * 2 <= cores {
+        threads.push(threads[threads.len() - 1] * 2);
+    }
+    if threads[threads.len() - 1] != cores {
+        threads.push(cores);
+    }
+
+    let stores: [(&str, NewStore); 2] = [
+        ("global mutex", || Arc::new(GlobalMutex::default())),
+        ("sharded store", || Arc::new(Store::default())),
+    ];
+
+    println!(
+        "{} keys, {}% writes, {:?} per run, {} cores.",
+        KEYS, writes, duration, cores
+    );
+    println!(
+        "{:<15} {:>8} {:>15} {:>8}",
+        "store", "threads", "ops/sec", "scaling"
+    );
+
+    for (name, new) in stores {
+        let mut single = None;
+
+        for &count in &threads {
+            let store = new();
+            for index in 0..KEYS {
+                store.insert(key(index), "value".to_string());
+            }
+
+            let throughput = run(store, count, writes, duration);
+            let single = *single.get_or_insert(throughput);
+
+            println!(
+                "{:<15} {:>8} {:>15.0} {:>7.2}x",
+                name,
+                count,
+                throughput,
+                throughput / single
+            );
+        }
+    }
+}
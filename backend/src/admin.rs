@@ -0,0 +1,446 @@
+use std::{sync::Arc, time::Instant};
+
+use kv_common::policy::Right;
+use time::{error::Parse, format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{error, info, Span};
+
+use crate::audit::{AuditLog, AuditQuery};
+use crate::auth::{AccessControl, AccessError};
+use crate::backend_server::admin_server::Admin;
+use crate::backend_server::import_chunk::Mode;
+use crate::backend_server::{
+    BackupChunk, BackupRequest, ExportChunk, ExportRequest, GetQuotaUsageRequest,
+    GetQuotaUsageResponse, ImportChunk, ImportError, ImportResponse, QueryAuditRequest,
+    QueryAuditResponse, SetQuotaRequest, SetQuotaResponse,
+};
+use crate::backup::{self, ArchiveLine, BACKUP_PAGE, FORMAT_VERSION};
+use crate::bulk::{self, LineTooLong, Lines, EXPORT_PAGE, MAX_IMPORT_ERRORS, MAX_LINE_BYTES};
+use crate::{deadline, BackendService, Mutation};
+
+// Export chunks buffered ahead of a slow client.
+const EXPORT_BUFFER: usize = 4;
+
+#[derive(Default, Debug)]
+pub struct AdminService {
+    access: Arc<AccessControl>,
+    audit: Option<Arc<AuditLog>>,
+    backend: BackendService,
+}
+
+impl AdminService {
+    pub fn new(
+        access: Arc<AccessControl>,
+        audit: Option<Arc<AuditLog>>,
+        backend: BackendService,
+    ) -> Self {
+        AdminService {
+            access,
+            audit,
+            backend,
+        }
+    }
+
+    // Namespace wide operations need admin on the namespace, which is checked
+    // against a key in it.
+    fn authorize_namespace<T>(
+        &self,
+        request: &Request<T>,
+        namespace: &str,
+    ) -> Result<(), AccessError> {
+        let caller = self
+            .access
+            .authorize(request, Right::Admin, &format!("{}:", namespace))?;
+        Span::current().record("caller", caller.name.as_str());
+
+        Ok(())
+    }
+
+    async fn import_line(
+        &self,
+        response: &mut ImportResponse,
+        mutation: &Mutation,
+        first: &ImportChunk,
+        number: u64,
+        line: &[u8],
+    ) {
+        if bulk::is_blank(line) {
+            return;
+        }
+
+        let skip_existing = first.mode() == Mode::SkipExisting;
+        let imported = match bulk::parse_line(line, &first.prefix) {
+            Ok(entry) => {
+                let key = entry.key.clone();
+                self.backend
+                    .import_entry(mutation.clone(), entry, skip_existing)
+                    .await
+                    .map_err(|error| ImportError {
+                        line: 0,
+                        key,
+                        message: Status::from(error).message().to_string(),
+                    })
+            }
+            Err(error) => Err(error),
+        };
+
+        match imported {
+            Ok(true) => response.imported += 1,
+            Ok(false) => response.skipped += 1,
+            Err(error) => {
+                response.failed += 1;
+                if response.errors.len() < MAX_IMPORT_ERRORS {
+                    response.errors.push(ImportError {
+                        line: number,
+                        ..error
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    type ExportStream = ReceiverStream<Result<ExportChunk, Status>>;
+    type BackupStream = ReceiverStream<Result<BackupChunk, Status>>;
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(key = %request.get_ref().key, caller = tracing::field::Empty)
+    )]
+    async fn query_audit(
+        &self,
+        request: Request<QueryAuditRequest>,
+    ) -> Result<Response<QueryAuditResponse>, Status> {
+        let start = Instant::now();
+
+        let reply = async {
+            // Querying a single key needs admin on that key, anything wider
+            // needs admin on every key.
+            let caller = self
+                .access
+                .authorize(&request, Right::Admin, &request.get_ref().key)?;
+            Span::current().record("caller", caller.name.as_str());
+
+            let audit = self
+                .audit
+                .as_ref()
+                .ok_or_else(|| Status::failed_precondition("Audit log is disabled."))?;
+
+            let request = request.into_inner();
+            let query = AuditQuery {
+                key: Some(request.key).filter(|key| !key.is_empty()),
+                from: parse_time(&request.from).map_err(|e| invalid_time("from", e))?,
+                to: parse_time(&request.to).map_err(|e| invalid_time("to", e))?,
+                limit: Some(request.limit as usize).filter(|limit| *limit > 0),
+            };
+
+            let entries = audit.query(query).await.map_err(|e| {
+                error!("Failed to read audit log: {:?}", e);
+                Status::internal("Failed to read audit log.")
+            })?;
+
+            Ok(QueryAuditResponse {
+                entries: entries.into_iter().map(Into::into).collect(),
+            })
+        }
+        .await;
+        self.backend
+            .metrics
+            .observe_rpc("QueryAudit", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(namespace = %request.get_ref().namespace, caller = tracing::field::Empty)
+    )]
+    async fn set_quota(
+        &self,
+        request: Request<SetQuotaRequest>,
+    ) -> Result<Response<SetQuotaResponse>, Status> {
+        let start = Instant::now();
+
+        let reply = async {
+            self.authorize_namespace(&request, &request.get_ref().namespace)?;
+
+            let request = request.into_inner();
+            self.backend
+                .set_quota(&request.namespace, request.quota.unwrap_or_default().into());
+
+            Ok(SetQuotaResponse {})
+        }
+        .await;
+        self.backend.metrics.observe_rpc("SetQuota", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(namespace = %request.get_ref().namespace, caller = tracing::field::Empty)
+    )]
+    async fn get_quota_usage(
+        &self,
+        request: Request<GetQuotaUsageRequest>,
+    ) -> Result<Response<GetQuotaUsageResponse>, Status> {
+        let start = Instant::now();
+
+        let reply = async {
+            self.authorize_namespace(&request, &request.get_ref().namespace)?;
+
+            let namespace = request.into_inner().namespace;
+            let (quota, usage) = self.backend.quota_usage(&namespace);
+
+            Ok(GetQuotaUsageResponse {
+                namespace,
+                quota: quota.map(Into::into),
+                keys: usage.keys,
+                bytes: usage.bytes,
+            })
+        }
+        .await;
+        self.backend
+            .metrics
+            .observe_rpc("GetQuotaUsage", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    // Exports and imports are namespace wide operations, so they need admin
+    // on the prefix.
+    #[tracing::instrument(
+        skip(self, request),
+        fields(prefix = %request.get_ref().prefix, caller = tracing::field::Empty)
+    )]
+    async fn export(
+        &self,
+        request: Request<ExportRequest>,
+    ) -> Result<Response<Self::ExportStream>, Status> {
+        let start = Instant::now();
+
+        let reply = async {
+            let caller =
+                self.access
+                    .authorize(&request, Right::Admin, &request.get_ref().prefix)?;
+            Span::current().record("caller", caller.name.as_str());
+
+            let deadline = deadline::of(&request);
+            let prefix = request.into_inner().prefix;
+            let backend = self.backend.clone();
+            let (sender, receiver) = mpsc::channel(EXPORT_BUFFER);
+
+            tokio::spawn(async move {
+                let mut start_after = String::new();
+                let mut exported = 0;
+
+                loop {
+                    if deadline::expired(deadline) {
+                        info!("Export ran out of time after {} keys.", exported);
+                        let _ = sender.send(Err(deadline::exceeded())).await;
+                        return;
+                    }
+
+                    let items = backend.database.scan(&prefix, &start_after, EXPORT_PAGE);
+                    let Some(last) = items.last() else {
+                        break;
+                    };
+                    start_after = last.key.clone();
+                    exported += items.len();
+
+                    let chunk = ExportChunk {
+                        data: bulk::export_lines(&items),
+                    };
+                    if sender.send(Ok(chunk)).await.is_err() {
+                        info!("Export client went away after {} keys.", exported);
+                        return;
+                    }
+                    if items.len() < EXPORT_PAGE {
+                        break;
+                    }
+                }
+
+                info!("Exported {} keys with prefix: {}.", exported, prefix);
+            });
+
+            Ok(ReceiverStream::new(receiver))
+        }
+        .await;
+        self.backend.metrics.observe_rpc("Export", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    // Shards are copied one at a time while the changes meanwhile are
+    // captured, so writes only wait for the copy of their own shard. Captured
+    // changes are held in memory until the copy completes.
+    #[tracing::instrument(skip(self, request), fields(caller = tracing::field::Empty))]
+    async fn backup(
+        &self,
+        request: Request<BackupRequest>,
+    ) -> Result<Response<Self::BackupStream>, Status> {
+        let start = Instant::now();
+
+        let reply = async {
+            let caller = self.access.authorize(&request, Right::Admin, "")?;
+            Span::current().record("caller", caller.name.as_str());
+
+            let database = self.backend.database.clone();
+            let (sender, receiver) = mpsc::channel(EXPORT_BUFFER);
+
+            tokio::spawn(async move {
+                let capture = database.capture();
+                info!("Starting backup at revision {}.", capture.start);
+
+                let header = ArchiveLine::Header {
+                    format_version: FORMAT_VERSION,
+                    start_revision: capture.start,
+                    created_at: OffsetDateTime::now_utc(),
+                };
+                if !send_lines(&sender, &[header]).await {
+                    return;
+                }
+
+                let mut entries = 0;
+                for index in 0..database.shards() {
+                    let lines: Vec<_> = database
+                        .entries(index)
+                        .into_iter()
+                        .map(ArchiveLine::Entry)
+                        .collect();
+                    entries += lines.len();
+
+                    for page in lines.chunks(BACKUP_PAGE) {
+                        if !send_lines(&sender, page).await {
+                            info!("Backup client went away after {} keys.", entries);
+                            return;
+                        }
+                    }
+                }
+
+                let (revision, records) = database.finish(capture);
+                let mut lines: Vec<_> = records.into_iter().map(ArchiveLine::Record).collect();
+                let captured = lines.len();
+                lines.push(ArchiveLine::Footer {
+                    revision,
+                    completed_at: OffsetDateTime::now_utc(),
+                });
+                for page in lines.chunks(BACKUP_PAGE) {
+                    if !send_lines(&sender, page).await {
+                        return;
+                    }
+                }
+
+                info!(
+                    "Backed up {} keys and {} changes at revision {}.",
+                    entries, captured, revision
+                );
+            });
+
+            Ok(ReceiverStream::new(receiver))
+        }
+        .await;
+        self.backend.metrics.observe_rpc("Backup", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(prefix = tracing::field::Empty, caller = tracing::field::Empty)
+    )]
+    async fn import(
+        &self,
+        request: Request<Streaming<ImportChunk>>,
+    ) -> Result<Response<ImportResponse>, Status> {
+        let start = Instant::now();
+
+        let reply = async {
+            let mut request = request;
+            let first = request
+                .get_mut()
+                .message()
+                .await?
+                .ok_or_else(|| Status::invalid_argument("Import stream is empty."))?;
+            Span::current().record("prefix", first.prefix.as_str());
+
+            let caller = self
+                .access
+                .authorize(&request, Right::Admin, &first.prefix)?;
+            Span::current().record("caller", caller.name.as_str());
+            let mutation = Mutation::new(caller, &request);
+
+            let deadline = deadline::of(&request);
+            let mut chunks = request.into_inner();
+            let mut lines = Lines::default();
+            let mut response = ImportResponse::default();
+            let mut data = first.data.clone();
+
+            // Lines imported before the deadline stay imported.
+            deadline::within(deadline, async {
+                loop {
+                    let complete = lines.push(&data).map_err(|LineTooLong(line)| {
+                        Status::invalid_argument(format!(
+                            "Line {} is longer than {} bytes.",
+                            line, MAX_LINE_BYTES
+                        ))
+                    })?;
+                    for (number, line) in complete {
+                        self.import_line(&mut response, &mutation, &first, number, &line)
+                            .await;
+                    }
+
+                    match chunks.message().await? {
+                        Some(chunk) => data = chunk.data,
+                        None => break,
+                    }
+                }
+                if let Some((number, line)) = lines.finish() {
+                    self.import_line(&mut response, &mutation, &first, number, &line)
+                        .await;
+                }
+
+                Ok(())
+            })
+            .await?;
+
+            info!(
+                "Imported {} keys, skipped {} and {} failed.",
+                response.imported, response.skipped, response.failed
+            );
+
+            Ok(response)
+        }
+        .await;
+        self.backend.metrics.observe_rpc("Import", start, &reply);
+
+        reply.map(Response::new)
+    }
+}
+
+async fn send_lines(
+    sender: &mpsc::Sender<Result<BackupChunk, Status>>,
+    lines: &[ArchiveLine],
+) -> bool {
+    let chunk = BackupChunk {
+        data: backup::archive_lines(lines),
+    };
+
+    sender.send(Ok(chunk)).await.is_ok()
+}
+
+fn parse_time(value: &str) -> Result<Option<OffsetDateTime>, Parse> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    OffsetDateTime::parse(value, &Rfc3339).map(Some)
+}
+
+fn invalid_time(field: &str, error: Parse) -> Status {
+    Status::invalid_argument(format!("Invalid '{}' time: {}", field, error))
+}
@@ -0,0 +1,315 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::backend_server;
+use crate::commit::{self, Commit, Reply};
+use crate::config;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Insert,
+    Delete,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Insert => "insert",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub principal: String,
+    pub action: Action,
+    pub key: String,
+    pub old_version: Option<u64>,
+    pub new_version: Option<u64>,
+    pub request_id: String,
+}
+
+impl From<AuditEntry> for backend_server::AuditEntry {
+    fn from(entry: AuditEntry) -> Self {
+        backend_server::AuditEntry {
+            timestamp: entry.timestamp.format(&Rfc3339).unwrap_or_default(),
+            principal: entry.principal,
+            action: entry.action.as_str().to_string(),
+            key: entry.key,
+            old_version: entry.old_version,
+            new_version: entry.new_version,
+            request_id: entry.request_id,
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct AuditQuery {
+    pub key: Option<String>,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.key.as_ref().is_none_or(|key| *key == entry.key)
+            && self.from.is_none_or(|from| entry.timestamp >= from)
+            && self.to.is_none_or(|to| entry.timestamp <= to)
+    }
+}
+
+// Entries are appended to `path` and the file is rotated to `path.1`, `path.2`
+// and so on once it grows past `max_bytes`. Rotated files beyond `max_files`
+// are removed. A writer thread appends the entries queued meanwhile together
+// and syncs them at once.
+#[derive(Debug)]
+pub struct AuditLog {
+    sender: Sender<Op>,
+    failed: Arc<AtomicBool>,
+}
+
+type Files = Vec<(PathBuf, File)>;
+
+enum Op {
+    Record(Vec<u8>, Reply),
+    Flush(Reply),
+    // Opened by the writer, so no rotation gets in the way.
+    Open(oneshot::Sender<io::Result<Files>>),
+}
+
+impl AuditLog {
+    pub fn open(settings: &config::Audit) -> Result<Self, io::Error> {
+        let path = PathBuf::from(&settings.path);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        info!("Writing audit log to: {}.", path.display());
+
+        let (sender, receiver) = mpsc::channel();
+        let failed = Arc::new(AtomicBool::new(false));
+        let writer = Writer {
+            file: append(&path)?,
+            path: path.clone(),
+            max_bytes: settings.max_bytes,
+            max_files: settings.max_files,
+            failed: failed.clone(),
+        };
+        thread::Builder::new()
+            .name("audit-writer".to_string())
+            .spawn(move || writer.run(receiver))?;
+
+        Ok(AuditLog { sender, failed })
+    }
+
+    // Queues the entry, the returned commit resolves once it's synced. After
+    // a failed write every later entry is refused, so mutations aren't
+    // applied without being recorded.
+    pub fn record(&self, entry: &AuditEntry) -> Result<Commit, io::Error> {
+        self.check()?;
+
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let (reply, commit) = Commit::pending();
+        self.send(Op::Record(line, reply))?;
+        Ok(commit)
+    }
+
+    pub fn check(&self) -> Result<(), io::Error> {
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(io::Error::other("Audit log failed earlier."));
+        }
+
+        Ok(())
+    }
+
+    pub async fn flush(&self) -> Result<(), io::Error> {
+        let (reply, commit) = Commit::pending();
+        self.send(Op::Flush(reply))?;
+        commit.wait().await
+    }
+
+    // Reads through its own handles on a blocking thread, so writes carry on
+    // meanwhile. Entries recorded before the query are returned.
+    pub async fn query(&self, query: AuditQuery) -> Result<Vec<AuditEntry>, io::Error> {
+        let (reply, files) = oneshot::channel();
+        self.send(Op::Open(reply))?;
+        let files = files.await.map_err(|_| commit::stopped())??;
+
+        tokio::task::spawn_blocking(move || read(files, &query))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    fn send(&self, op: Op) -> Result<(), io::Error> {
+        self.sender.send(op).map_err(|_| commit::stopped())
+    }
+}
+
+struct Writer {
+    path: PathBuf,
+    file: File,
+    max_bytes: u64,
+    max_files: usize,
+    failed: Arc<AtomicBool>,
+}
+
+impl Writer {
+    fn run(mut self, receiver: Receiver<Op>) {
+        while let Some(batch) = commit::next_batch(&receiver) {
+            let mut lines = Vec::new();
+            let mut waiters = Vec::new();
+            let mut flushes = Vec::new();
+            let mut opens = Vec::new();
+
+            for op in batch {
+                match op {
+                    Op::Record(line, reply) => {
+                        lines.push(line);
+                        waiters.push(reply);
+                    }
+                    Op::Flush(reply) => flushes.push(reply),
+                    Op::Open(reply) => opens.push(reply),
+                }
+            }
+
+            if !lines.is_empty() {
+                let result = self.write(&lines);
+                if result.is_err() {
+                    self.failed.store(true, Ordering::SeqCst);
+                }
+                commit::reply(waiters, &result);
+            }
+            if !flushes.is_empty() {
+                commit::reply(flushes, &self.file.sync_all());
+            }
+            for reply in opens {
+                let _ = reply.send(self.open_files());
+            }
+        }
+    }
+
+    fn write(&mut self, lines: &[Vec<u8>]) -> Result<(), io::Error> {
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(io::Error::other("Audit log failed earlier."));
+        }
+
+        let mut size = self.file.metadata()?.len();
+        for line in lines {
+            if size > 0 && size + line.len() as u64 > self.max_bytes {
+                self.file.sync_data()?;
+                self.file = self.rotate()?;
+                size = 0;
+            }
+
+            self.file.write_all(line)?;
+            size += line.len() as u64;
+        }
+
+        self.file.sync_data()
+    }
+
+    fn rotate(&self) -> Result<File, io::Error> {
+        self.move_files()?;
+
+        info!("Rotated audit log: {}.", self.path.display());
+
+        append(&self.path)
+    }
+
+    // Oldest first. Open files stay readable when they are rotated.
+    fn open_files(&self) -> Result<Files, io::Error> {
+        let mut files = Vec::new();
+        for path in (1..=self.max_files)
+            .rev()
+            .map(|index| rotated(&self.path, index))
+            .chain([self.path.clone()])
+        {
+            match File::open(&path) {
+                Ok(file) => files.push((path, file)),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn move_files(&self) -> Result<(), io::Error> {
+        if self.max_files == 0 {
+            return remove_if_exists(&self.path);
+        }
+
+        remove_if_exists(&rotated(&self.path, self.max_files))?;
+        for index in (1..self.max_files).rev() {
+            let from = rotated(&self.path, index);
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))
+    }
+}
+
+fn read(files: Files, query: &AuditQuery) -> Result<Vec<AuditEntry>, io::Error> {
+    let mut entries = Vec::new();
+    for (path, file) in files {
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str::<AuditEntry>(&line?) {
+                Ok(entry) if query.matches(&entry) => entries.push(entry),
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "Skipping malformed audit entry in {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    if let Some(limit) = query.limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+
+    Ok(entries)
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(format!(".{}", index));
+    path.into()
+}
+
+fn append(path: &Path) -> Result<File, io::Error> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), io::Error> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use ::config::ConfigError;
+pub use kv_common::auth::hash_api_key;
+use kv_common::policy::{Policy, Right};
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tonic::{metadata::MetadataMap, Request, Status};
+use tracing::warn;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::config;
+use crate::tls::TlsConnectInfo;
+
+const ANONYMOUS: &str = "anonymous";
+const PRINCIPAL_METADATA: &str = "x-kv-principal";
+const ROLES_METADATA: &str = "x-kv-roles";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Caller {
+    pub name: String,
+    pub roles: Vec<String>,
+}
+
+impl Caller {
+    fn anonymous() -> Self {
+        Caller {
+            name: ANONYMOUS.to_string(),
+            roles: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AccessError {
+    MissingCredentials,
+    InvalidCredentials,
+    DelegationDenied,
+    Denied(String),
+}
+
+impl From<AccessError> for Status {
+    fn from(error: AccessError) -> Self {
+        match error {
+            AccessError::MissingCredentials => Status::unauthenticated("Missing bearer token."),
+            AccessError::InvalidCredentials => Status::unauthenticated("Invalid bearer token."),
+            AccessError::DelegationDenied => {
+                Status::permission_denied("Delegation is not permitted.")
+            }
+            AccessError::Denied(key) => {
+                Status::permission_denied(format!("Access to key: {} is denied.", key))
+            }
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct AccessControl {
+    api_keys: HashMap<String, String>,
+    policy: Option<Policy>,
+}
+
+impl AccessControl {
+    pub fn new(settings: &config::Auth) -> Result<Self, ConfigError> {
+        if settings.api_keys.is_empty() {
+            warn!("No api keys configured, callers are not authenticated.");
+        }
+
+        let api_keys = settings
+            .api_keys
+            .iter()
+            .map(|api_key| (api_key.key_hash.to_lowercase(), api_key.name.clone()))
+            .collect();
+
+        let policy = match &settings.policy_file {
+            Some(path) => Some(Policy::load(path)?),
+            None => {
+                warn!("No policy configured, callers may access every key.");
+                None
+            }
+        };
+
+        Ok(AccessControl { api_keys, policy })
+    }
+
+    pub fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        right: Right,
+        key: &str,
+    ) -> Result<Caller, AccessError> {
+        let peer_certificates = request
+            .extensions()
+            .get::<TlsConnectInfo>()
+            .and_then(|info| info.peer_certificates.clone());
+
+        let caller = match peer_certificates {
+            Some(certificates) => certificate_caller(&certificates)?,
+            None => self.authenticate(request.metadata())?,
+        };
+
+        let principal = match delegated(request.metadata()) {
+            Some(principal) if self.allows(&caller, Right::Admin, key) => principal,
+            Some(principal) => {
+                warn!(
+                    caller = %caller.name,
+                    principal = %principal.name,
+                    key = %key,
+                    "Caller may not act on behalf of other principals."
+                );
+                return Err(AccessError::DelegationDenied);
+            }
+            None => caller,
+        };
+
+        if !self.allows(&principal, right, key) {
+            warn!(
+                principal = %principal.name,
+                key = %key,
+                right = ?right,
+                "Access denied."
+            );
+            return Err(AccessError::Denied(key.to_string()));
+        }
+
+        Ok(principal)
+    }
+
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<Caller, AccessError> {
+        if self.api_keys.is_empty() {
+            return Ok(Caller::anonymous());
+        }
+
+        let token = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or(AccessError::MissingCredentials)?;
+
+        match self.api_keys.get(&hash_api_key(token)) {
+            Some(name) => Ok(Caller {
+                name: name.clone(),
+                roles: Vec::new(),
+            }),
+            None => {
+                warn!("Invalid api key presented.");
+                Err(AccessError::InvalidCredentials)
+            }
+        }
+    }
+
+    pub fn allows(&self, caller: &Caller, right: Right, key: &str) -> bool {
+        self.policy
+            .as_ref()
+            .is_none_or(|policy| policy.allows(&caller.name, &caller.roles, right, key))
+    }
+}
+
+// The peer certificate was already verified against the client CA during the
+// handshake, so its subject identifies the caller like an api key would.
+fn certificate_caller(certificates: &[CertificateDer]) -> Result<Caller, AccessError> {
+    let certificate = certificates
+        .first()
+        .ok_or(AccessError::MissingCredentials)?;
+
+    let (_, certificate) = X509Certificate::from_der(certificate).map_err(|e| {
+        warn!("Failed to parse client certificate: {:?}", e);
+        AccessError::InvalidCredentials
+    })?;
+
+    let subject = certificate.subject();
+    let name = subject
+        .iter_common_name()
+        .next()
+        .and_then(|common_name| common_name.as_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| subject.to_string());
+
+    Ok(Caller {
+        name,
+        roles: Vec::new(),
+    })
+}
+
+fn delegated(metadata: &MetadataMap) -> Option<Caller> {
+    let name = metadata.get(PRINCIPAL_METADATA)?.to_str().ok()?;
+    let roles = metadata
+        .get(ROLES_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|role| !role.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    Some(Caller {
+        name: name.to_string(),
+        roles,
+    })
+}
@@ -0,0 +1,347 @@
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::{error, info, warn};
+
+use crate::config;
+use crate::store::Store;
+use crate::wal::{self, Change, Record, Stored};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+// Entries per backup chunk.
+pub const BACKUP_PAGE: usize = 1000;
+
+// How often the write-ahead log is checked for a due checkpoint.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ArchiveLine {
+    Header {
+        format_version: u32,
+        start_revision: u64,
+        #[serde(with = "time::serde::rfc3339")]
+        created_at: OffsetDateTime,
+    },
+    Entry(Stored),
+    Record(Record),
+    Footer {
+        revision: u64,
+        #[serde(with = "time::serde::rfc3339")]
+        completed_at: OffsetDateTime,
+    },
+}
+
+pub fn archive_lines(lines: &[ArchiveLine]) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for line in lines {
+        serde_json::to_writer(&mut data, line).expect("Serializing archive lines can't fail.");
+        data.push(b'\n');
+    }
+
+    data
+}
+
+#[derive(Debug)]
+pub enum RestoreError {
+    Io(PathBuf, io::Error),
+    Archive(String),
+    Target(String),
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestoreError::Io(path, error) => {
+                write!(f, "Failed to read {}: {}", path.display(), error)
+            }
+            RestoreError::Archive(message) => write!(f, "Invalid backup archive: {}", message),
+            RestoreError::Target(message) => write!(f, "Can't restore to target: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> RestoreError + '_ {
+    move |error| RestoreError::Io(path.to_path_buf(), error)
+}
+
+// Where a restore stops replaying the write-ahead log, unset replays all of
+// it.
+#[derive(Default, Debug)]
+struct Target {
+    revision: Option<u64>,
+    timestamp: Option<OffsetDateTime>,
+}
+
+impl Target {
+    fn reached_by(&self, record: &Record) -> bool {
+        self.revision
+            .is_some_and(|revision| record.revision > revision)
+            || self
+                .timestamp
+                .is_some_and(|timestamp| record.timestamp > timestamp)
+    }
+}
+
+// Replays the write-ahead log into the empty `store` and starts appending
+// to it. With `restore` set, the store is rebuilt from the backup instead
+// and only the log after the backup is replayed, up to the target. The old
+// log is moved aside then, and the new one starts with the restored entries.
+// Returns the revision the store is at.
+pub fn recover(
+    store: &mut Store,
+    settings: &config::Wal,
+    restore: Option<&config::Restore>,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let path = PathBuf::from(&settings.path);
+
+    let Some(restore) = restore else {
+        if !settings.enabled {
+            warn!("Write-ahead log is disabled, data is lost on restart.");
+            return Ok(0);
+        }
+
+        let mut records = 0;
+        wal::replay(&path, |record| {
+            store.set_revision(record.revision);
+            store.apply(record.change);
+            records += 1;
+            true
+        })
+        .map_err(io_error(&path))?;
+        info!(
+            "Replayed {} records of {} up to revision {}.",
+            records,
+            path.display(),
+            store.revision()
+        );
+
+        store.set_wal(&path, settings.checkpoint_bytes)?;
+        check_budget(store);
+        return Ok(store.revision());
+    };
+
+    let target = Target {
+        revision: restore.until_revision,
+        timestamp: restore
+            .until_timestamp
+            .as_deref()
+            .map(|timestamp| OffsetDateTime::parse(timestamp, &Rfc3339))
+            .transpose()
+            .map_err(|e| RestoreError::Target(format!("Invalid until_timestamp: {}", e)))?,
+    };
+    restore_backup(store, Path::new(&restore.backup), &path, &target)?;
+
+    if settings.enabled {
+        start_timeline(store, &path, settings.checkpoint_bytes)?;
+    } else {
+        warn!("Write-ahead log is disabled, data is lost on restart.");
+    }
+    check_budget(store);
+
+    Ok(store.revision())
+}
+
+// Replayed entries skip the memory budget, the next inserts evict or fail
+// until the store is back under it.
+fn check_budget(store: &Store) {
+    if store
+        .memory_limit()
+        .is_some_and(|limit| store.bytes() > limit)
+    {
+        warn!(
+            "Store holds {} bytes after recovery, more than its memory budget.",
+            store.bytes()
+        );
+    }
+}
+
+fn restore_backup(
+    store: &Store,
+    backup: &Path,
+    log: &Path,
+    target: &Target,
+) -> Result<(), RestoreError> {
+    let (revision, completed_at) = load_archive(store, backup)?;
+
+    if target.revision.is_some_and(|until| until < revision) {
+        return Err(RestoreError::Target(format!(
+            "The backup is at revision {}, later than the target.",
+            revision
+        )));
+    }
+    if target.timestamp.is_some_and(|until| until < completed_at) {
+        return Err(RestoreError::Target(format!(
+            "The backup completed at {}, later than the target.",
+            completed_at
+        )));
+    }
+
+    // Records up to the backup's revision are already in it, the rest must
+    // follow without a gap. Segments overlap where a checkpoint copied the
+    // store, the records replayed already are skipped.
+    let mut last = revision;
+    let mut gap = None;
+    let mut stopped = false;
+    for segment in wal::segments(log).map_err(io_error(log))? {
+        wal::replay(&segment, |record| {
+            if record.revision <= last {
+                return true;
+            }
+            if record.revision != last + 1 {
+                gap = Some(record.revision);
+                stopped = true;
+                return false;
+            }
+            if target.reached_by(&record) {
+                stopped = true;
+                return false;
+            }
+
+            last = record.revision;
+            store.apply(record.change);
+            true
+        })
+        .map_err(io_error(&segment))?;
+
+        if stopped {
+            break;
+        }
+    }
+
+    if let Some(found) = gap {
+        return Err(RestoreError::Target(format!(
+            "The write-ahead log continues at revision {} instead of {}.",
+            found,
+            last + 1
+        )));
+    }
+    if target.revision.is_some_and(|until| until > last) {
+        warn!(
+            "Write-ahead log ends at revision {}, before the target.",
+            last
+        );
+    }
+
+    store.set_revision(last);
+    info!(
+        "Restored {} keys at revision {} from backup at revision {}.",
+        store.len(),
+        last,
+        revision
+    );
+
+    Ok(())
+}
+
+// Returns the revision and completion time of the backup.
+fn load_archive(store: &Store, path: &Path) -> Result<(u64, OffsetDateTime), RestoreError> {
+    let file = fs::File::open(path).map_err(io_error(path))?;
+    let mut start = None;
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(io_error(path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let line = serde_json::from_str(&line)
+            .map_err(|e| RestoreError::Archive(format!("Line {}: {}", index + 1, e)))?;
+        match (start, line) {
+            (
+                None,
+                ArchiveLine::Header {
+                    format_version,
+                    start_revision,
+                    ..
+                },
+            ) => {
+                if format_version != FORMAT_VERSION {
+                    return Err(RestoreError::Archive(format!(
+                        "Unsupported format version {}.",
+                        format_version
+                    )));
+                }
+                start = Some(start_revision);
+            }
+            (None, _) => {
+                return Err(RestoreError::Archive(
+                    "Archive doesn't start with a header.".to_string(),
+                ));
+            }
+            (Some(_), ArchiveLine::Entry(stored)) => store.apply(Change::Put(stored)),
+            (Some(_), ArchiveLine::Record(record)) => store.apply(record.change),
+            (
+                Some(_),
+                ArchiveLine::Footer {
+                    revision,
+                    completed_at,
+                },
+            ) => return Ok((revision, completed_at)),
+            (Some(_), ArchiveLine::Header { .. }) => {
+                return Err(RestoreError::Archive(format!(
+                    "Line {}: Second header.",
+                    index + 1
+                )));
+            }
+        }
+    }
+
+    Err(RestoreError::Archive(
+        "Archive has no footer, the backup didn't complete.".to_string(),
+    ))
+}
+
+// The replayed log and its archives may have records past the target,
+// which must not come back on the next start or restore.
+fn start_timeline(store: &mut Store, path: &Path, checkpoint_bytes: Option<u64>) -> io::Result<()> {
+    let moved_at = OffsetDateTime::now_utc().unix_timestamp();
+    for segment in wal::segments(path)? {
+        if !segment.exists() {
+            continue;
+        }
+
+        let mut aside = segment.clone().into_os_string();
+        aside.push(format!(".{}.old", moved_at));
+        fs::rename(&segment, &aside)?;
+        info!("Moved replaced write-ahead log to: {:?}.", aside);
+    }
+
+    // Every entry is written at the restored revision, a later restore from
+    // a backup after it doesn't replay them.
+    let entries = (0..store.shards()).flat_map(|index| store.entries(index));
+    wal::checkpoint(path, store.revision(), entries, || Ok(Vec::new()))?;
+
+    store.set_wal(path, checkpoint_bytes)
+}
+
+// Checkpoints the write-ahead log whenever enough was appended to it since
+// the last checkpoint.
+pub async fn compact_log(store: Arc<Store>) {
+    let mut interval = tokio::time::interval(CHECKPOINT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        if !store.checkpoint_due() {
+            continue;
+        }
+
+        let store = store.clone();
+        match tokio::task::spawn_blocking(move || store.checkpoint()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => error!("Failed to checkpoint write-ahead log: {:?}", error),
+            Err(error) => error!("Checkpoint of write-ahead log panicked: {:?}", error),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend_server::ImportError;
+use crate::store::Item;
+
+// Keys per export chunk, every chunk is a scan over the whole store.
+pub const EXPORT_PAGE: usize = 1000;
+
+// Only this many errors are listed in an import response.
+pub const MAX_IMPORT_ERRORS: usize = 100;
+
+// A line without newline growing past this fails the import, so a stream
+// that isn't JSON Lines can't buffer without bound.
+pub const MAX_LINE_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Serialize)]
+struct ExportLine<'a> {
+    key: &'a str,
+    value: &'a str,
+    version: u64,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct ImportLine {
+    pub key: String,
+    pub value: String,
+    #[serde(default)]
+    pub ttl_seconds: u64,
+}
+
+pub fn export_lines(items: &[Item]) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for item in items {
+        let line = ExportLine {
+            key: &item.key,
+            value: &item.value,
+            version: item.version,
+        };
+        serde_json::to_writer(&mut data, &line).expect("Serializing strings can't fail.");
+        data.push(b'\n');
+    }
+
+    data
+}
+
+// The line number of errors is left to the caller.
+pub fn parse_line(line: &[u8], prefix: &str) -> Result<ImportLine, ImportError> {
+    let error = |key: &str, message: String| ImportError {
+        line: 0,
+        key: key.to_string(),
+        message,
+    };
+
+    let line: ImportLine =
+        serde_json::from_slice(line).map_err(|e| error("", format!("Invalid JSON: {}", e)))?;
+
+    if line.key.trim().is_empty() {
+        return Err(error(&line.key, "Key can't be empty.".to_string()));
+    }
+    if line.value.trim().is_empty() {
+        return Err(error(&line.key, "Value can't be empty.".to_string()));
+    }
+    if !line.key.starts_with(prefix) {
+        return Err(error(
+            &line.key,
+            format!("Key is outside of the import prefix: {}", prefix),
+        ));
+    }
+
+    Ok(line)
+}
+
+// Blank lines are skipped but still counted, so line numbers match the
+// input.
+pub fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(u8::is_ascii_whitespace)
+}
+
+// Splits chunks into numbered lines, carrying a partial line over to the
+// next chunk.
+#[derive(Default, Debug)]
+pub struct Lines {
+    partial: Vec<u8>,
+    number: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineTooLong(pub u64);
+
+impl Lines {
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<(u64, Vec<u8>)>, LineTooLong> {
+        let mut lines = Vec::new();
+        let mut rest = data;
+
+        while let Some(end) = rest.iter().position(|byte| *byte == b'\n') {
+            self.partial.extend_from_slice(&rest[..end]);
+            self.number += 1;
+            lines.push((self.number, mem::take(&mut self.partial)));
+            rest = &rest[end + 1..];
+        }
+
+        self.partial.extend_from_slice(rest);
+        if self.partial.len() > MAX_LINE_BYTES {
+            return Err(LineTooLong(self.number + 1));
+        }
+
+        Ok(lines)
+    }
+
+    // The last line doesn't need a trailing newline.
+    pub fn finish(self) -> Option<(u64, Vec<u8>)> {
+        (!self.partial.is_empty()).then(|| (self.number + 1, self.partial))
+    }
+}
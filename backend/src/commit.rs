@@ -0,0 +1,65 @@
+use std::{io, iter::once, sync::mpsc::Receiver};
+
+use tokio::sync::oneshot;
+
+pub type Reply = oneshot::Sender<io::Result<()>>;
+
+// A write queued for a writer thread, which syncs the writes queued together
+// at once and then replies to each of them.
+#[must_use]
+#[derive(Debug)]
+pub struct Commit(Option<oneshot::Receiver<io::Result<()>>>);
+
+impl Commit {
+    // Nothing to wait for, like a change to a store without a log.
+    pub fn done() -> Self {
+        Commit(None)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn pending() -> (Reply, Self) {
+        let (reply, receiver) = oneshot::channel();
+        (reply, Commit(Some(receiver)))
+    }
+
+    // Resolves once the write is durable, without holding a runtime thread
+    // through the sync.
+    pub async fn wait(self) -> io::Result<()> {
+        let Some(receiver) = self.0 else {
+            return Ok(());
+        };
+
+        receiver.await.unwrap_or_else(|_| Err(stopped()))
+    }
+
+    // For threads outside the runtime, like a checkpoint.
+    pub fn wait_blocking(self) -> io::Result<()> {
+        let Some(receiver) = self.0 else {
+            return Ok(());
+        };
+
+        receiver.blocking_recv().unwrap_or_else(|_| Err(stopped()))
+    }
+}
+
+pub fn stopped() -> io::Error {
+    io::Error::other("Writer stopped before the write was durable.")
+}
+
+// Blocks for the next write, then takes everything queued behind it.
+pub fn next_batch<T>(receiver: &Receiver<T>) -> Option<Vec<T>> {
+    let first = receiver.recv().ok()?;
+    Some(once(first).chain(receiver.try_iter()).collect())
+}
+
+pub fn reply(waiters: Vec<Reply>, result: &io::Result<()>) {
+    for waiter in waiters {
+        let _ = waiter.send(match result {
+            Ok(()) => Ok(()),
+            Err(error) => Err(io::Error::new(error.kind(), error.to_string())),
+        });
+    }
+}
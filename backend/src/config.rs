@@ -1,10 +1,148 @@
 use config::Config;
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+use std::{collections::HashMap, num::NonZeroUsize};
+
+use crate::eviction::EvictionPolicy;
+use crate::quota::Quota;
+use crate::tls::TlsVersion;
+
+#[derive(Deserialize, Default)]
 pub struct Settings {
     pub application_port: u16,
+    pub metrics_port: u16,
     pub host: String,
+    pub otlp_endpoint: Option<String>,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub tls: Tls,
+    #[serde(default)]
+    pub audit: Audit,
+    #[serde(default)]
+    pub quotas: HashMap<String, Quota>,
+    #[serde(default)]
+    pub memory: Memory,
+    #[serde(default)]
+    pub wal: Wal,
+    pub restore: Option<Restore>,
+    #[serde(default)]
+    pub shutdown: Shutdown,
+    #[serde(default)]
+    pub idempotency: Idempotency,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Shutdown {
+    pub drain_delay_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown {
+            drain_delay_secs: 0,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Idempotency {
+    pub window_secs: u64,
+    pub max_keys: NonZeroUsize,
+}
+
+impl Default for Idempotency {
+    fn default() -> Self {
+        Idempotency {
+            window_secs: 300,
+            max_keys: NonZeroUsize::new(100_000).unwrap(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Memory {
+    pub max_bytes: Option<u64>,
+    pub eviction_policy: EvictionPolicy,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Tls {
+    pub enabled: bool,
+    pub cert_file: String,
+    pub key_file: String,
+    pub client_ca_file: Option<String>,
+    pub min_version: TlsVersion,
+    pub cipher_suites: Vec<String>,
+    pub reload_interval_secs: u64,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Audit {
+    pub enabled: bool,
+    pub path: String,
+    pub max_bytes: u64,
+    pub max_files: usize,
+}
+
+impl Default for Audit {
+    fn default() -> Self {
+        Audit {
+            enabled: false,
+            path: "audit/audit.jsonl".to_string(),
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Wal {
+    pub enabled: bool,
+    pub path: String,
+    // Unset never checkpoints the log.
+    pub checkpoint_bytes: Option<u64>,
+}
+
+impl Default for Wal {
+    fn default() -> Self {
+        Wal {
+            enabled: false,
+            path: "wal/wal.jsonl".to_string(),
+            checkpoint_bytes: Some(64 * 1024 * 1024),
+        }
+    }
+}
+
+// Rebuilds the store from a backup archive on start, replaying the
+// write-ahead log after it up to the first change past either bound.
+#[derive(Deserialize, Clone)]
+pub struct Restore {
+    pub backup: String,
+    pub until_revision: Option<u64>,
+    // RFC 3339.
+    pub until_timestamp: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Auth {
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    pub policy_file: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key_hash: String,
 }
 
 pub enum Environment {
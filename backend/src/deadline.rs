@@ -0,0 +1,67 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use tokio::time::timeout_at;
+use tonic::{Request, Status};
+
+const GRPC_TIMEOUT_METADATA: &str = "grpc-timeout";
+
+// tonic drops a unary call once its grpc-timeout expires, but reports that
+// as CANCELLED and doesn't stop work spawned for a streamed response, so
+// calls check their deadline themselves.
+pub fn of<T>(request: &Request<T>) -> Option<Instant> {
+    let value = request
+        .metadata()
+        .get(GRPC_TIMEOUT_METADATA)?
+        .to_str()
+        .ok()?;
+    Some(Instant::now() + parse_timeout(value)?)
+}
+
+// The value is at most 8 digits and a unit, see the gRPC over HTTP2
+// protocol.
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let (digits, unit) = value.split_at(value.len().checked_sub(1)?);
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+
+    let amount: u64 = digits.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+pub fn expired(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+pub fn exceeded() -> Status {
+    Status::deadline_exceeded("Deadline exceeded.")
+}
+
+// A call that's already past its deadline isn't started.
+pub async fn within<T>(
+    deadline: Option<Instant>,
+    call: impl Future<Output = Result<T, Status>>,
+) -> Result<T, Status> {
+    let Some(deadline) = deadline else {
+        return call.await;
+    };
+    if expired(Some(deadline)) {
+        return Err(exceeded());
+    }
+
+    match timeout_at(deadline.into(), call).await {
+        Ok(reply) => reply,
+        Err(_) => Err(exceeded()),
+    }
+}
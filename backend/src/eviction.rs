@@ -0,0 +1,41 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvictionPolicy {
+    #[default]
+    None,
+    AllkeysLru,
+    AllkeysLfu,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvictionPolicy::None => "none",
+            EvictionPolicy::AllkeysLru => "allkeys-lru",
+            EvictionPolicy::AllkeysLfu => "allkeys-lfu",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    // Lower ranks are evicted first, keys without a rank are never evicted.
+    pub fn rank(&self, recency: Recency) -> Option<(u64, u64)> {
+        match self {
+            EvictionPolicy::None => None,
+            EvictionPolicy::AllkeysLru => Some((recency.last_access, 0)),
+            EvictionPolicy::AllkeysLfu => Some((recency.hits, recency.last_access)),
+            EvictionPolicy::VolatileTtl => recency
+                .expires_at
+                .map(|expires_at| (expires_at, recency.last_access)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Recency {
+    pub last_access: u64,
+    pub hits: u64,
+    pub expires_at: Option<u64>,
+}
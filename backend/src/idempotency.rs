@@ -0,0 +1,242 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    num::NonZeroUsize,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tonic::{Request, Status};
+use tracing::info;
+
+use crate::auth::Caller;
+use crate::config;
+
+const IDEMPOTENCY_KEY_METADATA: &str = "idempotency-key";
+
+#[derive(Debug)]
+enum Entry {
+    InFlight {
+        fingerprint: [u8; 32],
+        at: Instant,
+    },
+    Done {
+        fingerprint: [u8; 32],
+        response: Vec<u8>,
+        at: Instant,
+    },
+}
+
+impl Entry {
+    fn at(&self) -> Instant {
+        match self {
+            Entry::InFlight { at, .. } | Entry::Done { at, .. } => *at,
+        }
+    }
+
+    fn fingerprint(&self) -> &[u8; 32] {
+        match self {
+            Entry::InFlight { fingerprint, .. } | Entry::Done { fingerprint, .. } => fingerprint,
+        }
+    }
+}
+
+// A write sent with an idempotency key, keys are scoped to the caller and
+// the method.
+pub struct Idempotent {
+    scope: [u8; 32],
+    fingerprint: [u8; 32],
+}
+
+// Returns None for requests without an idempotency key.
+pub fn of<T: Message>(method: &str, caller: &Caller, request: &Request<T>) -> Option<Idempotent> {
+    let key = request
+        .metadata()
+        .get(IDEMPOTENCY_KEY_METADATA)?
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty())?;
+
+    Some(Idempotent {
+        scope: scope(&[&caller.name, method, key]),
+        fingerprint: Sha256::digest(request.get_ref().encode_to_vec()).into(),
+    })
+}
+
+// Length prefixed, so no two lists of parts hash alike.
+fn scope(parts: &[&str]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_scope: HashMap<[u8; 32], Entry>,
+    // Scopes in the order their entries were made, entries removed meanwhile
+    // are skipped.
+    expiry: VecDeque<(Instant, [u8; 32])>,
+}
+
+impl Entries {
+    // Entries in flight leave the queue and join it again once they're done.
+    fn pop_oldest(&mut self) -> bool {
+        let Some((at, scope)) = self.expiry.pop_front() else {
+            return false;
+        };
+        let done = matches!(
+            self.by_scope.get(&scope),
+            Some(entry @ Entry::Done { .. }) if entry.at() == at
+        );
+        if done {
+            self.by_scope.remove(&scope);
+        }
+        done
+    }
+
+    // False if every entry is in flight.
+    fn evict_oldest(&mut self) -> bool {
+        while !self.expiry.is_empty() {
+            if self.pop_oldest() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// Remembers the responses of writes sent with an idempotency key for
+// `window_secs`, so a retried write is answered with the response of the
+// first one instead of being applied again. Entries expire in the order they
+// were made, the oldest finished ones are dropped first once `max_keys` is
+// reached.
+#[derive(Debug)]
+pub struct Idempotency {
+    window: Duration,
+    max_keys: NonZeroUsize,
+    entries: Mutex<Entries>,
+}
+
+impl Default for Idempotency {
+    fn default() -> Self {
+        Idempotency::new(&config::Idempotency::default())
+    }
+}
+
+impl Idempotency {
+    pub fn new(settings: &config::Idempotency) -> Self {
+        Idempotency {
+            window: Duration::from_secs(settings.window_secs),
+            max_keys: settings.max_keys,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    // Runs the call unless a write with the same key already succeeded, only
+    // successful responses are remembered so a failed write can be retried.
+    pub async fn once<T: Message + Default>(
+        &self,
+        idempotent: Option<Idempotent>,
+        call: impl Future<Output = Result<T, Status>>,
+    ) -> Result<T, Status> {
+        let Some(Idempotent { scope, fingerprint }) = idempotent else {
+            return call.await;
+        };
+
+        {
+            let mut entries = self.lock();
+            self.prune(&mut entries);
+
+            match entries.by_scope.get(&scope) {
+                Some(entry) if entry.fingerprint() != &fingerprint => {
+                    return Err(Status::invalid_argument(
+                        "Idempotency key was used for a different request.",
+                    ));
+                }
+                Some(Entry::InFlight { .. }) => {
+                    return Err(Status::aborted(
+                        "A request with this idempotency key is in progress.",
+                    ));
+                }
+                Some(Entry::Done { response, .. }) => {
+                    info!("Replaying response for idempotency key.");
+                    return T::decode(response.as_slice()).map_err(|error| {
+                        Status::internal(format!("Failed to decode stored response: {}", error))
+                    });
+                }
+                None => {}
+            }
+
+            while entries.by_scope.len() >= self.max_keys.get() {
+                if !entries.evict_oldest() {
+                    return Err(Status::unavailable(
+                        "Too many requests with an idempotency key are in progress.",
+                    ));
+                }
+            }
+            let at = Instant::now();
+            entries
+                .by_scope
+                .insert(scope, Entry::InFlight { fingerprint, at });
+            entries.expiry.push_back((at, scope));
+        }
+
+        // Dropped with the call when it's cut off by its deadline.
+        let mut claim = Claim {
+            idempotency: self,
+            scope,
+            done: false,
+        };
+        let reply = call.await?;
+
+        let mut entries = self.lock();
+        if let Some(entry) = entries.by_scope.get_mut(&claim.scope) {
+            let at = Instant::now();
+            *entry = Entry::Done {
+                fingerprint,
+                response: reply.encode_to_vec(),
+                at,
+            };
+            entries.expiry.push_back((at, claim.scope));
+            claim.done = true;
+        }
+        drop(entries);
+
+        Ok(reply)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .expect("Idempotency lock should not be poisoned.")
+    }
+
+    fn prune(&self, entries: &mut Entries) {
+        while entries
+            .expiry
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() >= self.window)
+        {
+            entries.pop_oldest();
+        }
+    }
+}
+
+struct Claim<'a> {
+    idempotency: &'a Idempotency,
+    scope: [u8; 32],
+    done: bool,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.idempotency.lock().by_scope.remove(&self.scope);
+        }
+    }
+}
@@ -1,113 +1,796 @@
-use tonic::transport::{Server, ServerTlsConfig};
-use tracing::{error, info};
+use kv_common::policy::Right;
+use time::OffsetDateTime;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic_health::server::health_reporter;
+use tracing::{error, info, warn, Instrument, Span};
 use uuid::Uuid;
 
+use backend_server::admin_server::AdminServer;
 use backend_server::kv_server::{Kv, KvServer};
-use backend_server::{GetValueRequest, GetValueResponse, InsertValueRequest, InsertValueResponse};
+use backend_server::watch_event::Kind;
+use backend_server::{
+    CompareAndSwapRequest, CompareAndSwapResponse, DeleteValueRequest, DeleteValueResponse,
+    GetValueRequest, GetValueResponse, InsertValueRequest, InsertValueResponse, KeyValue,
+    ScanRequest, ScanResponse, WatchEvent, WatchRequest,
+};
 
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::sync::Mutex;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
 use tonic::{Request, Response, Status};
 
-use tonic::transport::Identity;
+use crate::admin::AdminService;
+use crate::audit::{Action, AuditEntry, AuditLog};
+use crate::auth::{AccessControl, AccessError, Caller};
+use crate::bulk::ImportLine;
+use crate::config::Settings;
+use crate::idempotency::Idempotency;
+use crate::metrics::Metrics;
+use crate::quota::{Quota, Usage};
+use crate::shutdown::Shutdown;
+use crate::store::{InsertError, Store};
+
+pub mod admin;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod bulk;
+pub mod commit;
+pub mod config;
+pub mod deadline;
+pub mod eviction;
+pub mod idempotency;
+pub mod metrics;
+pub mod quota;
+pub mod shutdown;
+pub mod store;
+pub mod telemetry;
+pub mod tls;
+pub mod wal;
 
 pub mod backend_server {
     tonic::include_proto!("kv");
 }
 
-#[derive(Default, Debug)]
+const REQUEST_ID_METADATA: &str = "x-request-id";
+
+// Most keys returned by one Scan call.
+const MAX_SCAN_LIMIT: usize = 1000;
+
+// Changes buffered per watcher, a watcher falling further behind is
+// disconnected.
+const WATCH_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone)]
 pub struct BackendService {
-    database: Arc<Mutex<HashMap<String, String>>>,
+    database: Arc<Store>,
+    metrics: Metrics,
+    access: Arc<AccessControl>,
+    audit: Option<Arc<AuditLog>>,
+    changes: broadcast::Sender<WatchEvent>,
+    idempotency: Arc<Idempotency>,
+}
+
+impl Default for BackendService {
+    fn default() -> Self {
+        BackendService::new()
+    }
 }
 
 impl BackendService {
     pub fn new() -> Self {
+        let metrics = Metrics::new();
+        let mut database = Store::default();
+        database.observe_lock_wait(metrics.lock_wait.clone());
+
         BackendService {
-            database: Arc::new(Mutex::new(HashMap::new())),
+            database: Arc::new(database),
+            metrics,
+            access: Arc::new(AccessControl::default()),
+            audit: None,
+            changes: broadcast::channel(WATCH_BUFFER).0,
+            idempotency: Arc::new(Idempotency::default()),
         }
     }
-}
 
-#[tonic::async_trait]
-impl Kv for BackendService {
-    #[tracing::instrument(skip(self))]
-    async fn insert_value(
+    pub fn with_access_control(mut self, access: Arc<AccessControl>) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn with_idempotency(mut self, settings: &config::Idempotency) -> Self {
+        info!(
+            "Remembering idempotency keys for {} seconds, at most {}.",
+            settings.window_secs, settings.max_keys
+        );
+
+        self.idempotency = Arc::new(Idempotency::new(settings));
+        self
+    }
+
+    pub fn with_quotas(mut self, quotas: HashMap<String, Quota>) -> Self {
+        self.store_mut().set_quotas(quotas);
+        self
+    }
+
+    pub fn with_memory_limit(mut self, memory: &config::Memory) -> Self {
+        if let Some(max_bytes) = memory.max_bytes {
+            info!(
+                "Limiting store to {} bytes, evicting by {} policy.",
+                max_bytes,
+                memory.eviction_policy.as_str()
+            );
+        }
+
+        self.store_mut()
+            .set_memory_limit(memory.max_bytes, memory.eviction_policy);
+        self
+    }
+
+    // Replays the write-ahead log or restores a backup, see backup::recover.
+    pub fn with_recovery(
+        mut self,
+        wal: &config::Wal,
+        restore: Option<&config::Restore>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        backup::recover(self.store_mut(), wal, restore)?;
+        self.observe_storage();
+
+        Ok(self)
+    }
+
+    fn store_mut(&mut self) -> &mut Store {
+        Arc::get_mut(&mut self.database)
+            .expect("Store should be configured before the service is shared.")
+    }
+
+    // Changes are synced as they are written, this also syncs file metadata
+    // before exiting.
+    pub async fn flush(&self) -> Result<(), std::io::Error> {
+        self.database.flush().await?;
+        if let Some(audit) = &self.audit {
+            audit.flush().await?;
+        }
+
+        info!("Flushed storage at revision {}.", self.database.revision());
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    pub fn set_quota(&self, namespace: &str, quota: Quota) {
+        info!("Setting quota of namespace: {} to {:?}.", namespace, quota);
+        self.database.set_quota(namespace, quota);
+    }
+
+    pub fn quota_usage(&self, namespace: &str) -> (Option<Quota>, Usage) {
+        self.database.quota_usage(namespace)
+    }
+
+    fn observe_storage(&self) {
+        self.metrics.storage_bytes.set(self.database.bytes() as i64);
+        self.metrics.keys.set(self.database.len() as i64);
+    }
+
+    // Checked before a change is applied, so mutations are rejected once the
+    // audit log failed instead of going unrecorded.
+    fn audit_failed(&self) -> Result<(), std::io::Error> {
+        self.audit.as_ref().map_or(Ok(()), |audit| audit.check())
+    }
+
+    // Recorded once the change is logged, so failed writes aren't audited.
+    async fn audit(
         &self,
-        request: Request<InsertValueRequest>,
-    ) -> Result<Response<InsertValueResponse>, Status> {
-        let request = request.into_inner();
+        mutation: &Mutation,
+        action: Action,
+        key: &str,
+        old_version: Option<u64>,
+        new_version: Option<u64>,
+    ) -> Result<(), std::io::Error> {
+        let Some(audit) = &self.audit else {
+            return Ok(());
+        };
 
-        let mut database = self.database.lock().await;
+        let entry = AuditEntry {
+            timestamp: OffsetDateTime::now_utc(),
+            principal: mutation.caller.name.clone(),
+            action,
+            key: key.to_string(),
+            old_version,
+            new_version,
+            request_id: mutation.request_id.clone(),
+        };
+
+        audit.record(&entry)?.wait().await
+    }
 
+    async fn insert(
+        &self,
+        mutation: Mutation,
+        request: InsertValueRequest,
+    ) -> Result<InsertValueResponse, Status> {
         info!("Inserting data to database.");
 
-        database.insert(request.key, request.value);
+        let version = self
+            .write(
+                mutation,
+                request.key,
+                request.value,
+                request.ttl_seconds,
+                None,
+            )
+            .await
+            .map_err(rejected)?;
 
         info!("Data inserted succesfully");
 
-        let reply = InsertValueResponse { success: true };
+        Ok(InsertValueResponse {
+            success: true,
+            version,
+        })
+    }
+
+    async fn swap(
+        &self,
+        mutation: Mutation,
+        request: CompareAndSwapRequest,
+    ) -> Result<CompareAndSwapResponse, Status> {
+        info!(
+            "Swapping data in database at version: {}.",
+            request.expected_version
+        );
+
+        let expected = Some(request.expected_version).filter(|version| *version > 0);
+        let version = self
+            .write(
+                mutation,
+                request.key,
+                request.value,
+                request.ttl_seconds,
+                Some(expected),
+            )
+            .await
+            .map_err(rejected)?;
 
-        Ok(Response::new(reply))
+        Ok(CompareAndSwapResponse { version })
     }
 
-    #[tracing::instrument(skip(self))]
-    async fn get_value(
+    // Returns whether the entry was written, it's skipped instead of
+    // overwritten if `skip_existing` is set and the key exists.
+    async fn import_entry(
         &self,
-        request: Request<GetValueRequest>,
-    ) -> Result<Response<GetValueResponse>, Status> {
-        let request = request.into_inner();
+        mutation: Mutation,
+        entry: ImportLine,
+        skip_existing: bool,
+    ) -> Result<bool, InsertError> {
+        let expected = skip_existing.then_some(None);
+        let written = self
+            .write(
+                mutation,
+                entry.key,
+                entry.value,
+                entry.ttl_seconds,
+                expected,
+            )
+            .await;
+
+        match written {
+            Ok(_) => Ok(true),
+            Err(InsertError::VersionMismatch { .. }) if skip_existing => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Inserts unconditionally without an `expected` version, returns the
+    // version written. Runs on even if the call is cut off, so a change whose
+    // log record can't be synced is still undone.
+    async fn write(
+        &self,
+        mutation: Mutation,
+        key: String,
+        value: String,
+        ttl_seconds: u64,
+        expected: Option<Option<u64>>,
+    ) -> Result<u64, InsertError> {
+        let service = self.clone();
+        tokio::spawn(
+            async move {
+                service
+                    .write_now(mutation, key, value, ttl_seconds, expected)
+                    .await
+            }
+            .instrument(Span::current()),
+        )
+        .await
+        .expect("Write should not panic.")
+    }
+
+    async fn write_now(
+        &self,
+        mutation: Mutation,
+        key: String,
+        value: String,
+        ttl_seconds: u64,
+        expected: Option<Option<u64>>,
+    ) -> Result<u64, InsertError> {
+        let ttl = Some(ttl_seconds)
+            .filter(|ttl| *ttl > 0)
+            .map(Duration::from_secs);
+        let published = self.watched().then(|| value.clone());
+        let mut written = None;
+        let audit = |key: &str, old_version, version| {
+            self.audit_failed()?;
+            if let Some(value) = published {
+                self.publish(Kind::Put, key, value, version);
+            }
+            written = Some((key.to_string(), old_version, version));
+
+            Ok(())
+        };
+
+        let evicted = match expected {
+            Some(expected) => {
+                self.database
+                    .compare_and_swap(key, expected, value, ttl, audit)
+                    .await
+            }
+            None => self.database.insert(key, value, ttl, audit).await,
+        }?;
+        let (key, old_version, version) = written.expect("An insert should pass its version.");
+        self.audit(&mutation, Action::Insert, &key, old_version, Some(version))
+            .await
+            .map_err(InsertError::Audit)?;
+
+        self.metrics
+            .evictions
+            .with_label_values(&[self.database.eviction_policy().as_str()])
+            .inc_by(evicted as u64);
+        self.observe_storage();
+
+        Ok(version)
+    }
 
-        let database = self.database.lock().await;
+    fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        right: Right,
+        key: &str,
+    ) -> Result<Caller, AccessError> {
+        let caller = self.access.authorize(request, right, key)?;
+        Span::current().record("caller", caller.name.as_str());
+
+        Ok(caller)
+    }
+
+    // Runs on even if the call is cut off, like `write`.
+    async fn delete(
+        &self,
+        mutation: Mutation,
+        request: DeleteValueRequest,
+    ) -> Result<DeleteValueResponse, Status> {
+        let service = self.clone();
+        tokio::spawn(
+            async move { service.delete_now(mutation, request).await }.instrument(Span::current()),
+        )
+        .await
+        .expect("Delete should not panic.")
+    }
 
+    async fn delete_now(
+        &self,
+        mutation: Mutation,
+        request: DeleteValueRequest,
+    ) -> Result<DeleteValueResponse, Status> {
+        info!("Deleting data from database.");
+
+        let removed = self
+            .database
+            .remove(&request.key, |version| {
+                self.audit_failed()?;
+                if self.watched() {
+                    self.publish(Kind::Delete, &request.key, String::new(), version);
+                }
+
+                Ok(())
+            })
+            .await
+            .map_err(Status::from)?;
+        if let Some(version) = removed {
+            self.audit(&mutation, Action::Delete, &request.key, Some(version), None)
+                .await
+                .map_err(|error| Status::from(InsertError::Audit(error)))?;
+        }
+
+        match removed {
+            Some(_) => {
+                self.observe_storage();
+
+                Ok(DeleteValueResponse { success: true })
+            }
+            None => {
+                error!("Value for key: {} not found.", &request.key);
+
+                Err(Status::not_found(format!(
+                    "Value for key: {} not found.",
+                    &request.key
+                )))
+            }
+        }
+    }
+
+    fn watched(&self) -> bool {
+        self.changes.receiver_count() > 0
+    }
+
+    // Called while the key's shard is locked, so watchers see the changes of
+    // a key in the order they were applied.
+    fn publish(&self, kind: Kind, key: &str, value: String, version: u64) {
+        let _ = self.changes.send(WatchEvent {
+            kind: kind.into(),
+            key: key.to_string(),
+            value,
+            version,
+        });
+    }
+
+    // Entries the caller may not read are left out, so a page can come back
+    // with fewer keys than the limit while the scan continues.
+    fn scan_keys(&self, caller: &Caller, request: ScanRequest) -> ScanResponse {
+        let limit = match request.limit as usize {
+            0 => MAX_SCAN_LIMIT,
+            limit => limit.min(MAX_SCAN_LIMIT),
+        };
+
+        let items = self
+            .database
+            .scan(&request.prefix, &request.start_after, limit);
+        let next_start_after = match items.last() {
+            Some(last) if items.len() == limit => last.key.clone(),
+            _ => String::new(),
+        };
+
+        info!(
+            "Scanned {} keys with prefix: {}.",
+            items.len(),
+            request.prefix
+        );
+
+        let entries = items
+            .into_iter()
+            .filter(|item| self.access.allows(caller, Right::Read, &item.key))
+            .map(|item| KeyValue {
+                key: item.key,
+                value: item.value,
+                version: item.version,
+            })
+            .collect();
+
+        ScanResponse {
+            entries,
+            next_start_after,
+        }
+    }
+
+    fn subscribe(&self, caller: Caller, request: WatchRequest) -> WatchStream {
+        let access = self.access.clone();
+        let prefix = request.prefix;
+
+        info!("Watching keys with prefix: {}.", prefix);
+
+        let changes =
+            BroadcastStream::new(self.changes.subscribe()).filter_map(move |change| match change {
+                Ok(event) => (event.key.starts_with(&prefix)
+                    && access.allows(&caller, Right::Read, &event.key))
+                .then_some(Ok(event)),
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                    warn!("Watcher fell behind by {} changes.", skipped);
+                    Some(Err(Status::resource_exhausted(format!(
+                        "Watcher fell behind by {} changes.",
+                        skipped
+                    ))))
+                }
+            });
+
+        Box::pin(changes)
+    }
+
+    async fn get(&self, request: GetValueRequest) -> Result<GetValueResponse, Status> {
         info!("Retrieving data from database.");
 
-        match database.get(&request.key) {
-            Some(value) => {
+        match self.database.get(&request.key) {
+            Some((value, version)) => {
                 info!("Value from db: {:?}", value);
-                let reply = GetValueResponse {
-                    value: value.to_string(),
-                };
 
-                return Ok(Response::new(reply));
+                Ok(GetValueResponse { value, version })
             }
             None => {
                 error!("Value for key: {} not found.", &request.key);
-                let status =
-                    Status::not_found(format!("Value for key: {} not found.", &request.key));
 
-                return Err(status);
+                Err(Status::not_found(format!(
+                    "Value for key: {} not found.",
+                    &request.key
+                )))
+            }
+        }
+    }
+}
+
+fn rejected(error: InsertError) -> Status {
+    warn!("Insert rejected: {:?}", error);
+    Status::from(error)
+}
+
+type WatchStream = Pin<Box<dyn Stream<Item = Result<WatchEvent, Status>> + Send>>;
+
+// Who made a change and on behalf of which request, for the audit log.
+#[derive(Clone)]
+struct Mutation {
+    caller: Caller,
+    request_id: String,
+}
+
+impl Mutation {
+    fn new<T>(caller: Caller, request: &Request<T>) -> Self {
+        let request_id = request
+            .metadata()
+            .get(REQUEST_ID_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        Mutation { caller, request_id }
+    }
+}
+
+#[tonic::async_trait]
+impl Kv for BackendService {
+    type WatchStream = WatchStream;
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(key = %request.get_ref().key, caller = tracing::field::Empty)
+    )]
+    async fn insert_value(
+        &self,
+        request: Request<InsertValueRequest>,
+    ) -> Result<Response<InsertValueResponse>, Status> {
+        let start = Instant::now();
+        let deadline = deadline::of(&request);
+
+        let reply = match self.authorize(&request, Right::Write, &request.get_ref().key) {
+            Ok(caller) => {
+                let idempotent = idempotency::of("InsertValue", &caller, &request);
+                let mutation = Mutation::new(caller, &request);
+                let call = self.insert(mutation, request.into_inner());
+                deadline::within(deadline, self.idempotency.once(idempotent, call)).await
+            }
+            Err(error) => Err(error.into()),
+        };
+        self.metrics.observe_rpc("InsertValue", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(key = %request.get_ref().key, caller = tracing::field::Empty)
+    )]
+    async fn get_value(
+        &self,
+        request: Request<GetValueRequest>,
+    ) -> Result<Response<GetValueResponse>, Status> {
+        let start = Instant::now();
+        let deadline = deadline::of(&request);
+
+        let reply = match self.authorize(&request, Right::Read, &request.get_ref().key) {
+            Ok(_) => deadline::within(deadline, self.get(request.into_inner())).await,
+            Err(error) => Err(error.into()),
+        };
+        self.metrics.observe_rpc("GetValue", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(key = %request.get_ref().key, caller = tracing::field::Empty)
+    )]
+    async fn delete_value(
+        &self,
+        request: Request<DeleteValueRequest>,
+    ) -> Result<Response<DeleteValueResponse>, Status> {
+        let start = Instant::now();
+        let deadline = deadline::of(&request);
+
+        let reply = match self.authorize(&request, Right::Delete, &request.get_ref().key) {
+            Ok(caller) => {
+                let idempotent = idempotency::of("DeleteValue", &caller, &request);
+                let mutation = Mutation::new(caller, &request);
+                let call = self.delete(mutation, request.into_inner());
+                deadline::within(deadline, self.idempotency.once(idempotent, call)).await
+            }
+            Err(error) => Err(error.into()),
+        };
+        self.metrics.observe_rpc("DeleteValue", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(key = %request.get_ref().key, caller = tracing::field::Empty)
+    )]
+    async fn compare_and_swap(
+        &self,
+        request: Request<CompareAndSwapRequest>,
+    ) -> Result<Response<CompareAndSwapResponse>, Status> {
+        let start = Instant::now();
+        let deadline = deadline::of(&request);
+
+        let reply = match self.authorize(&request, Right::Write, &request.get_ref().key) {
+            Ok(caller) => {
+                let idempotent = idempotency::of("CompareAndSwap", &caller, &request);
+                let mutation = Mutation::new(caller, &request);
+                let call = self.swap(mutation, request.into_inner());
+                deadline::within(deadline, self.idempotency.once(idempotent, call)).await
+            }
+            Err(error) => Err(error.into()),
+        };
+        self.metrics.observe_rpc("CompareAndSwap", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    // Scans and watches need read access to the prefix, a prefix naming a
+    // namespace like "team-a:" is covered by a grant on that namespace.
+    #[tracing::instrument(
+        skip(self, request),
+        fields(prefix = %request.get_ref().prefix, caller = tracing::field::Empty)
+    )]
+    async fn scan(&self, request: Request<ScanRequest>) -> Result<Response<ScanResponse>, Status> {
+        let start = Instant::now();
+        let deadline = deadline::of(&request);
+
+        let reply = match self.authorize(&request, Right::Read, &request.get_ref().prefix) {
+            Ok(caller) => {
+                deadline::within(deadline, async {
+                    Ok(self.scan_keys(&caller, request.into_inner()))
+                })
+                .await
             }
+            Err(error) => Err(error.into()),
+        };
+        self.metrics.observe_rpc("Scan", start, &reply);
+
+        reply.map(Response::new)
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(prefix = %request.get_ref().prefix, caller = tracing::field::Empty)
+    )]
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let start = Instant::now();
+
+        let reply = match self.authorize(&request, Right::Read, &request.get_ref().prefix) {
+            Ok(caller) => Ok(self.subscribe(caller, request.into_inner())),
+            Err(error) => Err(error.into()),
         };
+        self.metrics.observe_rpc("Watch", start, &reply);
+
+        reply.map(Response::new)
     }
 }
 
 pub async fn run(
-    address: String,
-    identity: Option<Identity>,
+    listener: TcpListener,
+    metrics_listener: Option<TcpListener>,
+    settings: &Settings,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let address = address.parse()?;
-    let backend_service = BackendService::new();
+    let address = listener.local_addr()?;
 
-    tracing::info!(message = "Starting server.", %address);
+    let (mut health_reporter, health_service) = health_reporter();
+    health_reporter
+        .set_not_serving::<KvServer<BackendService>>()
+        .await;
 
-    let mut builder = Server::builder();
+    let access = Arc::new(AccessControl::new(&settings.auth)?);
+    let audit = if settings.audit.enabled {
+        Some(Arc::new(AuditLog::open(&settings.audit)?))
+    } else {
+        warn!("Audit log is disabled, mutations are not recorded.");
+        None
+    };
 
-    if let Some(identity) = identity {
-        builder = builder
-            .tls_config(ServerTlsConfig::new().identity(identity))
-            .expect("Should configure tls correctly.");
+    let mut backend_service = BackendService::new()
+        .with_access_control(access.clone())
+        .with_memory_limit(&settings.memory)
+        .with_quotas(settings.quotas.clone())
+        .with_idempotency(&settings.idempotency)
+        .with_recovery(&settings.wal, settings.restore.as_ref())?;
+    if let Some(audit) = &audit {
+        backend_service = backend_service.with_audit_log(audit.clone());
     }
 
-    builder
-        .trace_fn(|_| {
-            let request_id = Uuid::new_v4();
-            tracing::info_span!("Request span", %request_id)
+    if settings.wal.enabled {
+        tokio::spawn(backup::compact_log(backend_service.database.clone()));
+    }
+
+    if let Some(metrics_listener) = metrics_listener {
+        tokio::spawn(metrics::serve(metrics_listener, backend_service.metrics()));
+    }
+
+    let shutdown = Shutdown::on_signal(&settings.shutdown, health_reporter.clone())?;
+    health_reporter
+        .set_serving::<KvServer<BackendService>>()
+        .await;
+
+    tracing::info!(message = "Starting server.", %address);
+
+    let router = Server::builder()
+        .trace_fn(|request| {
+            let request_id = request
+                .headers()
+                .get(REQUEST_ID_METADATA)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            let span = tracing::info_span!(
+                "Request span",
+                %request_id,
+                path = %request.uri().path()
+            );
+            telemetry::set_remote_parent(&span, request.headers());
+            span
         })
-        .add_service(KvServer::new(backend_service))
-        .serve(address)
-        .await?;
+        .add_service(health_service)
+        .add_service(KvServer::new(backend_service.clone()))
+        .add_service(AdminServer::new(AdminService::new(
+            access,
+            audit,
+            backend_service.clone(),
+        )));
+
+    let stopping = shutdown.clone().stopping();
+    let serving: Pin<Box<dyn Future<Output = Result<(), tonic::transport::Error>> + Send>> =
+        if settings.tls.enabled {
+            let store = tls::CertificateStore::load(&settings.tls)?;
+            kv_common::tls::watch(store.clone(), settings.tls.reload_interval_secs)?;
+            let incoming = tls::incoming(listener, store, shutdown.clone().stopping());
+            Box::pin(router.serve_with_incoming_shutdown(incoming, stopping))
+        } else {
+            warn!("TLS is disabled, serving plaintext gRPC.");
+            Box::pin(
+                router.serve_with_incoming_shutdown(TcpListenerStream::new(listener), stopping),
+            )
+        };
+
+    // Calls still running at the timeout, like watches, are cut off.
+    tokio::select! {
+        served = serving => served?,
+        _ = shutdown.expired() => warn!("In-flight calls didn't finish in time, stopping anyway."),
+    }
+
+    backend_service.flush().await?;
+    info!("Shut down.");
 
     Ok(())
 }
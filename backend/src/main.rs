@@ -1,25 +1,25 @@
-use tracing_subscriber::{EnvFilter, FmtSubscriber};
+use backend::config::get_configuration;
+use backend::telemetry::{get_subscriber, init_subscriber};
 
-use config::get_configuration;
-
-use tonic::transport::Identity;
-
-mod config;
+use tokio::net::TcpListener;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    FmtSubscriber::builder().with_env_filter(filter).init();
-
     let configuration = get_configuration().expect("Failed to read configuration.");
-    let address = format!("{}:{}", configuration.host, configuration.application_port);
 
-    let cert = std::fs::read_to_string("cert2.pem").expect("cert2.pem should exist.");
-    let key = std::fs::read_to_string("key2.pem").expect("key2.pem should exist.");
+    let (subscriber, _tracer_provider) =
+        get_subscriber("backend", "info", configuration.otlp_endpoint.as_deref())?;
+    init_subscriber(subscriber);
+    let address = format!("{}:{}", configuration.host, configuration.application_port);
 
-    let identity = Identity::from_pem(cert, key);
+    let listener = TcpListener::bind(address).await?;
+    let metrics_listener = TcpListener::bind(format!(
+        "{}:{}",
+        configuration.host, configuration.metrics_port
+    ))
+    .await?;
 
-    backend::run(address, Some(identity)).await?;
+    backend::run(listener, Some(metrics_listener), &configuration).await?;
 
     Ok(())
 }
@@ -0,0 +1,174 @@
+use std::{convert::Infallible, time::Instant};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Response, StatusCode,
+};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use tokio::net::TcpListener;
+use tonic::Status;
+use tracing::{error, info};
+
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    registry: Registry,
+    grpc_requests: IntCounterVec,
+    grpc_request_duration: HistogramVec,
+    pub keys: IntGauge,
+    pub storage_bytes: IntGauge,
+    pub lock_wait: Histogram,
+    pub evictions: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let grpc_requests = IntCounterVec::new(
+            Opts::new("grpc_requests_total", "Number of handled gRPC requests."),
+            &["method", "code"],
+        )
+        .expect("Metric should be valid.");
+
+        let grpc_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "grpc_request_duration_seconds",
+                "Latency of handled gRPC requests.",
+            ),
+            &["method"],
+        )
+        .expect("Metric should be valid.");
+
+        let keys = IntGauge::new("kv_keys", "Number of keys in the store.")
+            .expect("Metric should be valid.");
+
+        let storage_bytes = IntGauge::new(
+            "kv_storage_bytes",
+            "Total size of stored keys and values in bytes.",
+        )
+        .expect("Metric should be valid.");
+
+        let lock_wait = Histogram::with_opts(
+            HistogramOpts::new(
+                "kv_lock_wait_seconds",
+                "Time spent waiting for a store shard lock.",
+            )
+            .buckets(vec![
+                0.000_001, 0.000_01, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5,
+            ]),
+        )
+        .expect("Metric should be valid.");
+
+        let evictions = IntCounterVec::new(
+            Opts::new(
+                "kv_evictions_total",
+                "Number of keys evicted to stay within the memory budget.",
+            ),
+            &["policy"],
+        )
+        .expect("Metric should be valid.");
+
+        registry
+            .register(Box::new(grpc_requests.clone()))
+            .expect("Metric should be registered once.");
+        registry
+            .register(Box::new(grpc_request_duration.clone()))
+            .expect("Metric should be registered once.");
+        registry
+            .register(Box::new(keys.clone()))
+            .expect("Metric should be registered once.");
+        registry
+            .register(Box::new(storage_bytes.clone()))
+            .expect("Metric should be registered once.");
+        registry
+            .register(Box::new(lock_wait.clone()))
+            .expect("Metric should be registered once.");
+        registry
+            .register(Box::new(evictions.clone()))
+            .expect("Metric should be registered once.");
+
+        Metrics {
+            registry,
+            grpc_requests,
+            grpc_request_duration,
+            keys,
+            storage_bytes,
+            lock_wait,
+            evictions,
+        }
+    }
+
+    pub fn observe_rpc<T>(&self, method: &str, start: Instant, result: &Result<T, Status>) {
+        let code = match result {
+            Ok(_) => tonic::Code::Ok,
+            Err(status) => status.code(),
+        };
+
+        self.grpc_requests
+            .with_label_values(&[method, &format!("{:?}", code)])
+            .inc();
+        self.grpc_request_duration
+            .with_label_values(&[method])
+            .observe(start.elapsed().as_secs_f64());
+    }
+
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Metrics should be encoded.");
+
+        String::from_utf8(buffer).expect("Metrics should be valid utf-8.")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+pub async fn serve(listener: TcpListener, metrics: Metrics) -> Result<(), hyper::Error> {
+    let address = listener.local_addr().ok();
+    info!(message = "Starting metrics server.", ?address);
+
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let metrics = metrics.clone();
+
+                async move {
+                    let response = match (request.method(), request.uri().path()) {
+                        (&Method::GET, "/metrics") => Response::builder()
+                            .header(CONTENT_TYPE, TextEncoder::new().format_type())
+                            .body(Body::from(metrics.encode())),
+                        _ => Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty()),
+                    };
+
+                    Ok::<_, Infallible>(response.expect("Response should be valid."))
+                }
+            }))
+        }
+    });
+
+    let listener = listener
+        .into_std()
+        .expect("Listener should convert to std listener.");
+
+    hyper::Server::from_tcp(listener)?
+        .serve(make_service)
+        .await
+        .map_err(|e| {
+            error!("Metrics server failed: {:?}", e);
+            e
+        })
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+use serde::Deserialize;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+use crate::backend_server;
+
+const NAMESPACE_SEPARATOR: char = ':';
+
+pub fn namespace_of(key: &str) -> &str {
+    key.split_once(NAMESPACE_SEPARATOR)
+        .map(|(namespace, _)| namespace)
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Quota {
+    pub max_keys: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub max_value_bytes: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub keys: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    ValueBytes { limit: u64, requested: u64 },
+    Keys { limit: u64 },
+    Bytes { limit: u64, requested: u64 },
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaExceeded::ValueBytes { limit, requested } => write!(
+                f,
+                "Value of {} bytes exceeds the limit of {} bytes.",
+                requested, limit
+            ),
+            QuotaExceeded::Keys { limit } => write!(f, "Namespace is limited to {} keys.", limit),
+            QuotaExceeded::Bytes { limit, requested } => write!(
+                f,
+                "Namespace would use {} bytes of its {} bytes.",
+                requested, limit
+            ),
+        }
+    }
+}
+
+impl QuotaExceeded {
+    pub fn into_status(self, namespace: &str) -> Status {
+        let subject = format!("namespace:{}", namespace);
+        let description = self.to_string();
+
+        Status::with_error_details(
+            Code::ResourceExhausted,
+            format!(
+                "Quota of namespace: {} exceeded. {}",
+                namespace, description
+            ),
+            ErrorDetails::with_quota_failure_violation(subject, description),
+        )
+    }
+}
+
+impl Quota {
+    // `replaced` is the size of the entry being overwritten, if any.
+    pub fn check(
+        &self,
+        usage: &Usage,
+        key_bytes: u64,
+        value_bytes: u64,
+        replaced: Option<u64>,
+    ) -> Result<(), QuotaExceeded> {
+        if let Some(limit) = self.max_value_bytes.filter(|limit| value_bytes > *limit) {
+            return Err(QuotaExceeded::ValueBytes {
+                limit,
+                requested: value_bytes,
+            });
+        }
+
+        if let Some(limit) = self.max_keys {
+            if replaced.is_none() && usage.keys >= limit {
+                return Err(QuotaExceeded::Keys { limit });
+            }
+        }
+
+        if let Some(limit) = self.max_bytes {
+            let requested = usage.bytes - replaced.unwrap_or_default() + key_bytes + value_bytes;
+            if requested > limit {
+                return Err(QuotaExceeded::Bytes { limit, requested });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<backend_server::Quota> for Quota {
+    fn from(quota: backend_server::Quota) -> Self {
+        Quota {
+            max_keys: quota.max_keys,
+            max_bytes: quota.max_bytes,
+            max_value_bytes: quota.max_value_bytes,
+        }
+    }
+}
+
+impl From<Quota> for backend_server::Quota {
+    fn from(quota: Quota) -> Self {
+        backend_server::Quota {
+            max_keys: quota.max_keys,
+            max_bytes: quota.max_bytes,
+            max_value_bytes: quota.max_value_bytes,
+        }
+    }
+}
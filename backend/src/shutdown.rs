@@ -0,0 +1,75 @@
+use std::{io, time::Duration};
+
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+    time::sleep,
+};
+use tonic_health::server::HealthReporter;
+use tracing::info;
+
+use crate::backend_server::kv_server::KvServer;
+use crate::config;
+use crate::BackendService;
+
+// Tells the server when to stop accepting calls and how long in-flight calls
+// may take after that.
+#[derive(Clone)]
+pub struct Shutdown {
+    stopping: watch::Receiver<bool>,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    // On SIGTERM or SIGINT the KV service reports not serving first, so
+    // clients checking health move away during the drain delay. Then the
+    // server stops accepting calls.
+    pub fn on_signal(
+        settings: &config::Shutdown,
+        mut health_reporter: HealthReporter,
+    ) -> Result<Self, io::Error> {
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let drain_delay = Duration::from_secs(settings.drain_delay_secs);
+        let (stop, stopping) = watch::channel(false);
+
+        tokio::spawn(async move {
+            let name = tokio::select! {
+                Some(()) = terminate.recv() => "SIGTERM",
+                Some(()) = interrupt.recv() => "SIGINT",
+                else => return,
+            };
+
+            info!(
+                "Received {}, draining for {:?} before shutting down.",
+                name, drain_delay
+            );
+            health_reporter
+                .set_not_serving::<KvServer<BackendService>>()
+                .await;
+            sleep(drain_delay).await;
+
+            info!("Refusing new calls and waiting for in-flight calls.");
+            let _ = stop.send(true);
+        });
+
+        Ok(Shutdown {
+            stopping,
+            timeout: Duration::from_secs(settings.timeout_secs),
+        })
+    }
+
+    pub async fn stopping(mut self) {
+        // A dropped sender means the signal task is gone, the server runs on.
+        if self.stopping.wait_for(|stopping| *stopping).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    // Resolves when in-flight calls are out of time.
+    pub async fn expired(self) {
+        let timeout = self.timeout;
+        self.stopping().await;
+        sleep(timeout).await;
+    }
+}
@@ -0,0 +1,932 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    hash::{BuildHasher, RandomState},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+    },
+    thread::available_parallelism,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use indexmap::{IndexMap, IndexSet};
+use prometheus::Histogram;
+use rand::Rng;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+use tracing::{error, info};
+
+use crate::commit::Commit;
+use crate::eviction::{EvictionPolicy, Recency};
+use crate::quota::{namespace_of, Quota, QuotaExceeded, Usage};
+use crate::wal::{self, Capture, Change, Record, Stored, Wal};
+
+// Shards per core when the shard count isn't given explicitly.
+const SHARDS_PER_CORE: usize = 4;
+
+// Keys sampled per shard when looking for an eviction victim, shards with
+// fewer keys are searched completely.
+const EVICTION_SAMPLES: usize = 5;
+
+pub const ERROR_DOMAIN: &str = "kv";
+pub const VERSION_MISMATCH: &str = "VERSION_MISMATCH";
+pub const CURRENT_VERSION: &str = "current_version";
+
+#[derive(Debug)]
+struct Entry {
+    value: String,
+    version: u64,
+    expires_at: Option<u64>,
+    // Revision of the change that wrote it, 0 when it was replayed.
+    revision: u64,
+    last_access: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl Entry {
+    fn recency(&self) -> Recency {
+        Recency {
+            last_access: self.last_access.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            expires_at: self.expires_at,
+        }
+    }
+
+    fn expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= now_millis())
+    }
+}
+
+fn size(key: &str, value: &str) -> u64 {
+    (key.len() + value.len()) as u64
+}
+
+fn check_version(
+    key: &str,
+    expected: Option<u64>,
+    current: Option<u64>,
+) -> Result<(), InsertError> {
+    if expected == current {
+        return Ok(());
+    }
+
+    Err(InsertError::VersionMismatch {
+        key: key.to_string(),
+        current,
+    })
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    pub key: String,
+    pub value: String,
+    pub version: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shard {
+    entries: IndexMap<String, Entry>,
+    // Keys with a ttl, the only eviction candidates under volatile-ttl.
+    volatile: IndexSet<String>,
+}
+
+// A change applied before its log record is synced.
+#[must_use]
+struct Pending {
+    commit: Commit,
+    // Unset without a log, the change can't fail then.
+    undo: Option<Undo>,
+}
+
+struct Undo {
+    key: String,
+    // The entry the change replaced or removed.
+    replaced: Option<Entry>,
+}
+
+#[derive(Debug, Default)]
+struct Namespaces {
+    quotas: HashMap<String, Quota>,
+    usage: HashMap<String, Usage>,
+}
+
+impl Namespaces {
+    fn adjust(&mut self, namespace: &str, keys: i64, bytes: i64) {
+        if !self.usage.contains_key(namespace) {
+            self.usage.insert(namespace.to_string(), Usage::default());
+        }
+
+        let usage = self
+            .usage
+            .get_mut(namespace)
+            .expect("Usage was just added.");
+        usage.keys = usage.keys.saturating_add_signed(keys);
+        usage.bytes = usage.bytes.saturating_add_signed(bytes);
+
+        if usage.keys == 0 {
+            self.usage.remove(namespace);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InsertError {
+    Quota(String, QuotaExceeded),
+    OutOfMemory { limit: u64, requested: u64 },
+    Audit(io::Error),
+    Log(io::Error),
+    VersionMismatch { key: String, current: Option<u64> },
+}
+
+impl From<InsertError> for Status {
+    fn from(error: InsertError) -> Self {
+        match error {
+            InsertError::Quota(namespace, exceeded) => exceeded.into_status(&namespace),
+            InsertError::OutOfMemory { limit, requested } => {
+                let description = format!(
+                    "Store would use {} bytes of its {} bytes memory budget.",
+                    requested, limit
+                );
+
+                Status::with_error_details(
+                    Code::ResourceExhausted,
+                    format!("Memory budget exceeded. {}", description),
+                    ErrorDetails::with_quota_failure_violation("memory", description),
+                )
+            }
+            InsertError::Audit(error) => {
+                error!("Failed to write audit entry: {:?}", error);
+                Status::internal("Failed to write audit entry.")
+            }
+            InsertError::Log(error) => {
+                error!("Failed to append to write-ahead log: {:?}", error);
+                Status::internal("Failed to append to write-ahead log.")
+            }
+            // Clients read the current version from the error info to retry
+            // without another get.
+            InsertError::VersionMismatch { key, current } => {
+                let message = match current {
+                    Some(version) => format!("Key: {} is at version {}.", key, version),
+                    None => format!("Key: {} doesn't exist.", key),
+                };
+                let metadata: HashMap<_, _> = current
+                    .map(|version| (CURRENT_VERSION.to_string(), version.to_string()))
+                    .into_iter()
+                    .collect();
+
+                Status::with_error_details(
+                    Code::FailedPrecondition,
+                    format!("Version mismatch. {}", message),
+                    ErrorDetails::with_error_info(VERSION_MISMATCH, ERROR_DOMAIN, metadata),
+                )
+            }
+        }
+    }
+}
+
+// Keys are spread over independently locked shards, so reads never block
+// each other and writes only contend with writes to the same shard. Reads
+// record recency in atomics, which is why a read lock is enough for them.
+//
+// Sizes count key and value bytes, quotas and the memory budget are checked
+// against the same numbers the storage metrics report.
+#[derive(Debug)]
+pub struct Store {
+    shards: Box<[RwLock<Shard>]>,
+    hasher: RandomState,
+    namespaces: Mutex<Namespaces>,
+    bytes: AtomicU64,
+    keys: AtomicU64,
+    max_bytes: Option<u64>,
+    policy: EvictionPolicy,
+    epoch: Instant,
+    lock_wait: Option<Histogram>,
+    wal: Wal,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        let cores = available_parallelism().map_or(1, |cores| cores.get());
+
+        Store::with_shards(cores * SHARDS_PER_CORE)
+    }
+}
+
+impl Store {
+    pub fn with_shards(shards: usize) -> Self {
+        Store {
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(Shard::default()))
+                .collect(),
+            hasher: RandomState::new(),
+            namespaces: Mutex::new(Namespaces::default()),
+            bytes: AtomicU64::new(0),
+            keys: AtomicU64::new(0),
+            max_bytes: None,
+            policy: EvictionPolicy::None,
+            epoch: Instant::now(),
+            lock_wait: None,
+            wal: Wal::default(),
+        }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn observe_lock_wait(&mut self, lock_wait: Histogram) {
+        self.lock_wait = Some(lock_wait);
+    }
+
+    pub fn set_memory_limit(&mut self, max_bytes: Option<u64>, policy: EvictionPolicy) {
+        self.max_bytes = max_bytes;
+        self.policy = policy;
+    }
+
+    // Changes are appended to the log at `path` from now on.
+    pub fn set_wal(&mut self, path: &Path, checkpoint_bytes: Option<u64>) -> io::Result<()> {
+        self.wal.start(path, checkpoint_bytes)
+    }
+
+    // Revision of the last change, changes before the write-ahead log was
+    // set count too.
+    pub fn revision(&self) -> u64 {
+        self.wal.revision()
+    }
+
+    pub fn memory_limit(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_quotas(&mut self, quotas: HashMap<String, Quota>) {
+        self.namespaces().quotas = quotas;
+    }
+
+    pub fn set_quota(&self, namespace: &str, quota: Quota) {
+        self.namespaces()
+            .quotas
+            .insert(namespace.to_string(), quota);
+    }
+
+    pub fn quota_usage(&self, namespace: &str) -> (Option<Quota>, Usage) {
+        let namespaces = self.namespaces();
+
+        (
+            namespaces.quotas.get(namespace).copied(),
+            namespaces.usage.get(namespace).copied().unwrap_or_default(),
+        )
+    }
+
+    // Expired entries are treated as absent here and dropped by the next
+    // write to them, or evicted before others.
+    pub fn get(&self, key: &str) -> Option<(String, u64)> {
+        let shard = self.read(key);
+        let entry = shard.entries.get(key).filter(|entry| !entry.expired())?;
+
+        // Recency only matters for eviction and reading the clock is a large
+        // part of a read, so it's skipped without a policy.
+        if self.policy != EvictionPolicy::None {
+            entry.last_access.store(self.ticks(), Ordering::Relaxed);
+            entry.hits.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Some((entry.value.clone(), entry.version))
+    }
+
+    // Returns up to `limit` keys starting with `prefix` that sort after
+    // `start_after`, in order. Shards are unordered, so every page is a pass
+    // over the whole store.
+    pub fn scan(&self, prefix: &str, start_after: &str, limit: usize) -> Vec<Item> {
+        let mut items = Vec::new();
+
+        for index in 0..self.shards.len() {
+            let shard = self.lock_read(index);
+            items.extend(
+                shard
+                    .entries
+                    .iter()
+                    .filter(|(key, entry)| {
+                        key.starts_with(prefix) && key.as_str() > start_after && !entry.expired()
+                    })
+                    .map(|(key, entry)| Item {
+                        key: key.clone(),
+                        value: entry.value.clone(),
+                        version: entry.version,
+                    }),
+            );
+            drop(shard);
+
+            if items.len() > limit {
+                items.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+                items.truncate(limit);
+            }
+        }
+
+        items.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        items
+    }
+
+    // Checks the namespace quota and the memory budget, evicting keys first if
+    // the policy allows it. `audit` gets the key, old and new version right
+    // before the change is applied, and an error from it aborts the insert.
+    // Returns how many keys were evicted.
+    pub async fn insert(
+        &self,
+        key: String,
+        value: String,
+        ttl: Option<Duration>,
+        audit: impl FnOnce(&str, Option<u64>, u64) -> io::Result<()>,
+    ) -> Result<usize, InsertError> {
+        let (evicted, pending) = self.put(key, value, ttl, None, audit)?;
+        self.settle(pending).await?;
+
+        Ok(evicted)
+    }
+
+    // Like insert, but only if the key is at the `expected` version, or
+    // absent for None.
+    pub async fn compare_and_swap(
+        &self,
+        key: String,
+        expected: Option<u64>,
+        value: String,
+        ttl: Option<Duration>,
+        audit: impl FnOnce(&str, Option<u64>, u64) -> io::Result<()>,
+    ) -> Result<usize, InsertError> {
+        let (evicted, pending) = self.put(key, value, ttl, Some(expected), audit)?;
+        self.settle(pending).await?;
+
+        Ok(evicted)
+    }
+
+    // Applies the change with the shard locked, it's settled after unlocking
+    // it.
+    fn put(
+        &self,
+        key: String,
+        value: String,
+        ttl: Option<Duration>,
+        expected: Option<Option<u64>>,
+        audit: impl FnOnce(&str, Option<u64>, u64) -> io::Result<()>,
+    ) -> Result<(usize, Pending), InsertError> {
+        // Checked before evicting too, so a stale swap doesn't evict keys.
+        if let Some(expected) = expected {
+            let shard = self.read(&key);
+            let current = shard
+                .entries
+                .get(&key)
+                .filter(|entry| !entry.expired())
+                .map(|entry| entry.version);
+            check_version(&key, expected, current)?;
+        }
+
+        let bytes = size(&key, &value);
+        let evicted = self.make_room(&key, bytes);
+
+        let mut shard = self.write(&key);
+        self.expire(&mut shard, &key);
+
+        let old_version = shard.entries.get(&key).map(|entry| entry.version);
+        if let Some(expected) = expected {
+            check_version(&key, expected, old_version)?;
+        }
+        let replaced = shard
+            .entries
+            .get(&key)
+            .map(|entry| size(&key, &entry.value));
+        let version = old_version.map_or(1, |version| version + 1);
+
+        let namespace = namespace_of(&key);
+        let keys = i64::from(replaced.is_none());
+        let delta = bytes as i64 - replaced.unwrap_or_default() as i64;
+
+        self.reserve_quota(namespace, key.len() as u64, value.len() as u64, replaced)?;
+
+        if let Err(error) = self.reserve_memory(delta) {
+            self.namespaces().adjust(namespace, -keys, -delta);
+            return Err(error);
+        }
+
+        let expires_at = ttl.map(|ttl| now_millis() + ttl.as_millis() as u64);
+        let commit = audit(&key, old_version, version)
+            .map_err(InsertError::Audit)
+            .and_then(|_| {
+                self.log(|| {
+                    Change::Put(Stored {
+                        key: key.clone(),
+                        value: value.clone(),
+                        version,
+                        expires_at,
+                    })
+                })
+                .map_err(InsertError::Log)
+            });
+        let (revision, commit) = match commit {
+            Ok(logged) => logged,
+            Err(error) => {
+                self.namespaces().adjust(namespace, -keys, -delta);
+                self.release_memory(delta);
+                return Err(error);
+            }
+        };
+
+        let entry = Entry {
+            value,
+            version,
+            expires_at,
+            revision,
+            last_access: AtomicU64::new(self.ticks()),
+            hits: AtomicU64::new(0),
+        };
+
+        if entry.expires_at.is_some() {
+            shard.volatile.insert(key.clone());
+        } else {
+            shard.volatile.swap_remove(&key);
+        }
+        let undo = (!commit.is_done()).then(|| key.clone());
+        let replaced = shard.entries.insert(key, entry);
+        if replaced.is_none() {
+            self.keys.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let undo = undo.map(|key| Undo { key, replaced });
+        Ok((evicted, Pending { commit, undo }))
+    }
+
+    // `audit` gets the version being removed and an error from it keeps the
+    // key. Returns the removed version, or None if the key isn't stored.
+    pub async fn remove(
+        &self,
+        key: &str,
+        audit: impl FnOnce(u64) -> io::Result<()>,
+    ) -> Result<Option<u64>, InsertError> {
+        let Some((version, pending)) = self.remove_logged(key, audit)? else {
+            return Ok(None);
+        };
+        self.settle(pending).await?;
+
+        Ok(Some(version))
+    }
+
+    fn remove_logged(
+        &self,
+        key: &str,
+        audit: impl FnOnce(u64) -> io::Result<()>,
+    ) -> Result<Option<(u64, Pending)>, InsertError> {
+        let mut shard = self.write(key);
+        self.expire(&mut shard, key);
+
+        let Some(version) = shard.entries.get(key).map(|entry| entry.version) else {
+            return Ok(None);
+        };
+
+        audit(version).map_err(InsertError::Audit)?;
+        let (_, commit) = self
+            .log(|| Change::Delete {
+                key: key.to_string(),
+            })
+            .map_err(InsertError::Log)?;
+        let removed = self.remove_locked(&mut shard, key);
+
+        let undo = (!commit.is_done()).then(|| Undo {
+            key: key.to_string(),
+            replaced: removed,
+        });
+        Ok(Some((version, Pending { commit, undo })))
+    }
+
+    // Waits for the change's log record, and undoes the change if it can't
+    // be synced.
+    async fn settle(&self, pending: Pending) -> Result<(), InsertError> {
+        let Err(error) = pending.commit.wait().await else {
+            return Ok(());
+        };
+
+        if let Some(undo) = pending.undo {
+            self.undo(undo);
+        }
+        Err(InsertError::Log(error))
+    }
+
+    // Every change from the first unsynced revision on failed, as the log
+    // refuses appends after a failure, so the key goes back to how the first
+    // of them found it. Only that change replaced a synced entry or none,
+    // which holds however the failed changes are undone in turn.
+    fn undo(&self, undo: Undo) {
+        let failed_at = self.wal.failed_at();
+        if undo
+            .replaced
+            .as_ref()
+            .is_some_and(|entry| entry.revision >= failed_at)
+        {
+            return;
+        }
+
+        let mut shard = self.write(&undo.key);
+        match shard.entries.get(&undo.key).map(|entry| entry.revision) {
+            Some(revision) if revision >= failed_at => {
+                self.remove_locked(&mut shard, &undo.key);
+            }
+            Some(_) => return,
+            None => {}
+        }
+        if let Some(entry) = undo.replaced {
+            self.insert_locked(&mut shard, undo.key, entry);
+        }
+    }
+
+    fn remove_locked(&self, shard: &mut Shard, key: &str) -> Option<Entry> {
+        let entry = shard.entries.swap_remove(key)?;
+        shard.volatile.swap_remove(key);
+
+        let bytes = size(key, &entry.value) as i64;
+        self.namespaces().adjust(namespace_of(key), -1, -bytes);
+        self.release_memory(bytes);
+        self.keys.fetch_sub(1, Ordering::Relaxed);
+
+        Some(entry)
+    }
+
+    // Adds an entry to an absent key without checking quotas or the memory
+    // budget.
+    fn insert_locked(&self, shard: &mut Shard, key: String, entry: Entry) {
+        let bytes = size(&key, &entry.value);
+        self.namespaces()
+            .adjust(namespace_of(&key), 1, bytes as i64);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.keys.fetch_add(1, Ordering::Relaxed);
+
+        if entry.expires_at.is_some() {
+            shard.volatile.insert(key.clone());
+        }
+        shard.entries.insert(key, entry);
+    }
+
+    fn expire(&self, shard: &mut Shard, key: &str) {
+        let expired = shard.entries.get(key).is_some_and(Entry::expired);
+
+        if expired {
+            info!("Key: {} expired.", key);
+            self.remove_locked(shard, key);
+        }
+    }
+
+    fn reserve_quota(
+        &self,
+        namespace: &str,
+        key_bytes: u64,
+        value_bytes: u64,
+        replaced: Option<u64>,
+    ) -> Result<(), InsertError> {
+        let mut namespaces = self.namespaces();
+
+        if let Some(quota) = namespaces.quotas.get(namespace) {
+            let usage = namespaces.usage.get(namespace).copied().unwrap_or_default();
+            quota
+                .check(&usage, key_bytes, value_bytes, replaced)
+                .map_err(|exceeded| InsertError::Quota(namespace.to_string(), exceeded))?;
+        }
+
+        let bytes = (key_bytes + value_bytes) as i64 - replaced.unwrap_or_default() as i64;
+        namespaces.adjust(namespace, i64::from(replaced.is_none()), bytes);
+
+        Ok(())
+    }
+
+    // Concurrent inserts into different shards reserve their bytes atomically,
+    // so the budget holds without a global lock.
+    fn reserve_memory(&self, bytes: i64) -> Result<(), InsertError> {
+        if bytes <= 0 {
+            self.release_memory(bytes);
+            return Ok(());
+        }
+
+        let requested = self.bytes.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+        match self.max_bytes {
+            Some(limit) if requested > limit => {
+                self.release_memory(bytes);
+                Err(InsertError::OutOfMemory { limit, requested })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn release_memory(&self, bytes: i64) {
+        if bytes >= 0 {
+            self.bytes.fetch_sub(bytes as u64, Ordering::Relaxed);
+        } else {
+            self.bytes
+                .fetch_add(bytes.unsigned_abs(), Ordering::Relaxed);
+        }
+    }
+
+    // Runs before the key's own shard is locked, evicting from other shards
+    // while holding it could deadlock with an insert doing the same there.
+    fn make_room(&self, key: &str, bytes: u64) -> usize {
+        let Some(limit) = self.max_bytes else {
+            return 0;
+        };
+        if self.policy == EvictionPolicy::None {
+            return 0;
+        }
+
+        let replaced = self
+            .read(key)
+            .entries
+            .get(key)
+            .map_or(0, |entry| size(key, &entry.value));
+
+        let mut evicted = 0;
+        while self.bytes() + bytes > limit + replaced {
+            let Some((index, victim)) = self.victim(key) else {
+                break;
+            };
+
+            let mut shard = self.lock_write(index);
+            if !shard.entries.contains_key(&victim) {
+                continue;
+            }
+            // Unlogged evictions would come back on replay, so the insert
+            // fails for lack of room instead. The insert's own commit is
+            // only durable after the eviction's.
+            if let Err(error) = self.log(|| Change::Delete {
+                key: victim.clone(),
+            }) {
+                error!("Failed to log eviction of key: {}: {:?}", victim, error);
+                break;
+            }
+            if self.remove_locked(&mut shard, &victim).is_some() {
+                info!(
+                    "Evicted key: {} by {} policy.",
+                    victim,
+                    self.policy.as_str()
+                );
+                evicted += 1;
+            }
+        }
+
+        evicted
+    }
+
+    // Applies a replayed change without checking quotas or the memory budget
+    // and without logging it again.
+    pub fn apply(&self, change: Change) {
+        match change {
+            Change::Put(stored) => {
+                let mut shard = self.write(&stored.key);
+                self.remove_locked(&mut shard, &stored.key);
+
+                let entry = Entry {
+                    value: stored.value,
+                    version: stored.version,
+                    expires_at: stored.expires_at,
+                    revision: 0,
+                    last_access: AtomicU64::new(self.ticks()),
+                    hits: AtomicU64::new(0),
+                };
+                if entry.expired() {
+                    return;
+                }
+
+                self.insert_locked(&mut shard, stored.key, entry);
+            }
+            Change::Delete { key } => {
+                let mut shard = self.write(&key);
+                self.remove_locked(&mut shard, &key);
+            }
+        }
+    }
+
+    // Replayed changes don't advance the revision by themselves.
+    pub fn set_revision(&self, revision: u64) {
+        self.wal.set_revision(revision);
+    }
+
+    pub async fn flush(&self) -> io::Result<()> {
+        self.wal.flush().await
+    }
+
+    // Starts collecting changes for a backup, see `entries`.
+    pub fn capture(&self) -> Capture {
+        self.wal.capture()
+    }
+
+    // Copies the unexpired entries of one shard, only blocking writes to it
+    // while they are cloned. Copies of different shards are taken at
+    // different revisions, replaying the changes captured meanwhile on top
+    // of them gives the store at the revision `finish` returns.
+    pub fn entries(&self, index: usize) -> Vec<Stored> {
+        self.lock_read(index)
+            .entries
+            .iter()
+            .filter(|(_, entry)| !entry.expired())
+            .map(|(key, entry)| Stored {
+                key: key.clone(),
+                value: entry.value.clone(),
+                version: entry.version,
+                expires_at: entry.expires_at,
+            })
+            .collect()
+    }
+
+    pub fn finish(&self, capture: Capture) -> (u64, Vec<Record>) {
+        self.wal.finish(capture)
+    }
+
+    pub fn checkpoint_due(&self) -> bool {
+        self.wal.checkpoint_due()
+    }
+
+    // Writes a copy of the store, taken like a backup, to a new log next to
+    // the current one. The log writer then appends the changes made since
+    // and replaces the current log with it, so replays start from the copy.
+    // The replaced log is archived for restores from older backups.
+    pub fn checkpoint(&self) -> io::Result<()> {
+        let Some(path) = self.wal.path() else {
+            return Ok(());
+        };
+        let mut staged = OsString::from(path);
+        staged.push(".checkpoint");
+        let staged = PathBuf::from(staged);
+
+        let capture = self.capture();
+        let start = capture.start;
+        let mut revision = start;
+        let entries = (0..self.shards()).flat_map(|index| self.entries(index));
+        let written = wal::checkpoint(&staged, start, entries, || {
+            let (marked, records) = self.wal.mark(capture)?;
+            revision = marked;
+            Ok(records)
+        });
+
+        match written.and_then(|_| self.wal.swap(&staged, start)) {
+            Ok(()) => {
+                info!("Checkpointed write-ahead log at revision {}.", revision);
+                Ok(())
+            }
+            Err(error) => {
+                self.wal.cancel();
+                Err(error)
+            }
+        }
+    }
+
+    // Called with the changed key's shard locked, so the log has the changes
+    // of a key in the order they were applied. The change is only queued,
+    // callers wait for the commit after unlocking the shard.
+    fn log(&self, change: impl FnOnce() -> Change) -> io::Result<(u64, Commit)> {
+        self.wal.append(change)
+    }
+
+    // Approximates the policy by sampling a few keys of every shard instead of
+    // keeping all keys ordered, which would turn every read into a write.
+    // Expired keys go first under every policy.
+    fn victim(&self, except: &str) -> Option<(usize, String)> {
+        let volatile = self.policy == EvictionPolicy::VolatileTtl;
+        let mut rng = rand::thread_rng();
+        let mut best: Option<((u64, u64), usize, String)> = None;
+
+        for index in 0..self.shards.len() {
+            let shard = self.lock_read(index);
+            let candidates = if volatile {
+                shard.volatile.len()
+            } else {
+                shard.entries.len()
+            };
+
+            let samples: Vec<usize> = if candidates <= EVICTION_SAMPLES {
+                (0..candidates).collect()
+            } else {
+                (0..EVICTION_SAMPLES)
+                    .map(|_| rng.gen_range(0..candidates))
+                    .collect()
+            };
+
+            for sample in samples {
+                let key = if volatile {
+                    &shard.volatile[sample]
+                } else {
+                    shard.entries.get_index(sample).map(|(key, _)| key).unwrap()
+                };
+                if key == except {
+                    continue;
+                }
+
+                let entry = &shard.entries[key];
+                let rank = if entry.expired() {
+                    Some((0, 0))
+                } else {
+                    self.policy.rank(entry.recency())
+                };
+
+                if let Some(rank) = rank {
+                    if best.as_ref().is_none_or(|(best, _, _)| rank < *best) {
+                        best = Some((rank, index, key.clone()));
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, index, key)| (index, key))
+    }
+
+    // Microseconds since the store was created, ordering accesses for LRU.
+    fn ticks(&self) -> u64 {
+        self.epoch.elapsed().as_micros() as u64
+    }
+
+    fn shard_of(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize % self.shards.len()
+    }
+
+    fn read(&self, key: &str) -> RwLockReadGuard<'_, Shard> {
+        self.lock_read(self.shard_of(key))
+    }
+
+    fn write(&self, key: &str) -> RwLockWriteGuard<'_, Shard> {
+        self.lock_write(self.shard_of(key))
+    }
+
+    fn lock_read(&self, index: usize) -> RwLockReadGuard<'_, Shard> {
+        let Some(lock_wait) = &self.lock_wait else {
+            return self.shards[index]
+                .read()
+                .expect("Store lock should not be poisoned.");
+        };
+
+        // Only a contended lock is timed, an uncontended one waited for nothing.
+        match self.shards[index].try_read() {
+            Ok(shard) => {
+                lock_wait.observe(0.0);
+                shard
+            }
+            Err(TryLockError::WouldBlock) => {
+                let start = Instant::now();
+                let shard = self.shards[index]
+                    .read()
+                    .expect("Store lock should not be poisoned.");
+                lock_wait.observe(start.elapsed().as_secs_f64());
+                shard
+            }
+            Err(TryLockError::Poisoned(_)) => panic!("Store lock should not be poisoned."),
+        }
+    }
+
+    fn lock_write(&self, index: usize) -> RwLockWriteGuard<'_, Shard> {
+        let Some(lock_wait) = &self.lock_wait else {
+            return self.shards[index]
+                .write()
+                .expect("Store lock should not be poisoned.");
+        };
+
+        match self.shards[index].try_write() {
+            Ok(shard) => {
+                lock_wait.observe(0.0);
+                shard
+            }
+            Err(TryLockError::WouldBlock) => {
+                let start = Instant::now();
+                let shard = self.shards[index]
+                    .write()
+                    .expect("Store lock should not be poisoned.");
+                lock_wait.observe(start.elapsed().as_secs_f64());
+                shard
+            }
+            Err(TryLockError::Poisoned(_)) => panic!("Store lock should not be poisoned."),
+        }
+    }
+
+    fn namespaces(&self) -> MutexGuard<'_, Namespaces> {
+        self.namespaces
+            .lock()
+            .expect("Namespace lock should not be poisoned.")
+    }
+}
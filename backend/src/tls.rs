@@ -0,0 +1,308 @@
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufReader, ErrorKind},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::{sleep, timeout},
+};
+use tokio_rustls::{
+    rustls::{
+        crypto::{ring, CryptoProvider},
+        pki_types::{CertificateDer, PrivateKeyDer},
+        server::WebPkiClientVerifier,
+        version::{TLS12, TLS13},
+        RootCertStore, ServerConfig, SupportedProtocolVersion,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::Connected;
+use tracing::{info, warn};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use kv_common::tls::Reload;
+pub use kv_common::tls::TlsVersion;
+
+use crate::config;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Persistent accept errors, like running out of file descriptors, would
+// otherwise spin.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(500);
+
+static TLS12_AND_LATER: &[&SupportedProtocolVersion] = &[&TLS13, &TLS12];
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&TLS13];
+
+fn protocol_versions(version: TlsVersion) -> &'static [&'static SupportedProtocolVersion] {
+    match version {
+        TlsVersion::Tls12 => TLS12_AND_LATER,
+        TlsVersion::Tls13 => TLS13_ONLY,
+    }
+}
+
+// Every handshake picks up the current config, so a reload applies to new
+// connections while established ones keep the certificate they started with.
+pub struct CertificateStore {
+    settings: config::Tls,
+    config: RwLock<Arc<ServerConfig>>,
+}
+
+impl CertificateStore {
+    pub fn load(settings: &config::Tls) -> Result<Arc<Self>, io::Error> {
+        let config = server_config(settings)?;
+        log_expiry(&settings.cert_file);
+
+        Ok(Arc::new(CertificateStore {
+            settings: settings.clone(),
+            config: RwLock::new(config),
+        }))
+    }
+
+    pub fn current(&self) -> Arc<ServerConfig> {
+        self.config
+            .read()
+            .expect("Certificate store lock should not be poisoned.")
+            .clone()
+    }
+}
+
+impl Reload for CertificateStore {
+    fn reload(&self) -> Result<(), io::Error> {
+        let config = server_config(&self.settings)?;
+
+        *self
+            .config
+            .write()
+            .expect("Certificate store lock should not be poisoned.") = config;
+
+        info!("Reloaded certificate from: {}.", &self.settings.cert_file);
+        log_expiry(&self.settings.cert_file);
+
+        Ok(())
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [
+            Some(&self.settings.cert_file),
+            Some(&self.settings.key_file),
+            self.settings.client_ca_file.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+    }
+}
+
+fn log_expiry(cert_file: &str) {
+    let expiry = load_certificates(cert_file).ok().and_then(|certificates| {
+        let certificate = certificates.first()?;
+        let (_, certificate) = X509Certificate::from_der(certificate).ok()?;
+
+        certificate.validity().not_after.to_rfc2822().ok()
+    });
+
+    match expiry {
+        Some(expiry) => info!(
+            "Serving certificate from {} expiring at {}.",
+            cert_file, expiry
+        ),
+        None => warn!("Failed to read expiry of certificate: {}.", cert_file),
+    }
+}
+
+pub fn server_config(settings: &config::Tls) -> Result<Arc<ServerConfig>, io::Error> {
+    let provider = Arc::new(crypto_provider(&settings.cipher_suites)?);
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(protocol_versions(settings.min_version))
+        .map_err(invalid_input)?;
+
+    let builder = match &settings.client_ca_file {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in load_certificates(path)? {
+                roots.add(certificate).map_err(invalid_input)?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(invalid_input)?;
+
+            info!("Requiring client certificates signed by: {}.", path);
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(
+            load_certificates(&settings.cert_file)?,
+            load_private_key(&settings.key_file)?,
+        )
+        .map_err(invalid_input)?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+fn crypto_provider(cipher_suites: &[String]) -> Result<CryptoProvider, io::Error> {
+    let mut provider = ring::default_provider();
+
+    if cipher_suites.is_empty() {
+        return Ok(provider);
+    }
+
+    provider.cipher_suites.retain(|suite| {
+        suite
+            .suite()
+            .as_str()
+            .is_some_and(|name| cipher_suites.iter().any(|allowed| allowed == name))
+    });
+
+    if provider.cipher_suites.len() != cipher_suites.len() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unsupported cipher suite in: {:?}", cipher_suites),
+        ));
+    }
+
+    Ok(provider)
+}
+
+pub fn load_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    rustls_pemfile::certs(&mut reader).collect()
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>, io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("No private key found in: {}", path),
+        )
+    })
+}
+
+fn invalid_input(error: impl std::fmt::Display) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, error.to_string())
+}
+
+// Handshakes run in their own tasks so a slow or failing client can't hold
+// up accepting other connections. Stops accepting once `stopping` resolves.
+pub fn incoming(
+    listener: TcpListener,
+    store: Arc<CertificateStore>,
+    stopping: impl Future<Output = ()> + Send + 'static,
+) -> ReceiverStream<Result<TlsConnection, io::Error>> {
+    let (sender, receiver) = mpsc::channel(128);
+
+    tokio::spawn(async move {
+        tokio::pin!(stopping);
+
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = &mut stopping => break,
+                _ = sender.closed() => break,
+            };
+            let (stream, address) = match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to accept connection: {:?}", e);
+                    tokio::select! {
+                        _ = sleep(ACCEPT_ERROR_BACKOFF) => continue,
+                        _ = &mut stopping => break,
+                    }
+                }
+            };
+
+            let acceptor = TlsAcceptor::from(store.current());
+            let connections = sender.clone();
+
+            tokio::spawn(async move {
+                match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = connections.send(Ok(TlsConnection { inner: stream })).await;
+                    }
+                    Ok(Err(e)) => warn!("TLS handshake with {} failed: {:?}", address, e),
+                    Err(_) => warn!("TLS handshake with {} timed out.", address),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+pub struct TlsConnection {
+    inner: TlsStream<TcpStream>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConnectInfo {
+    pub remote_addr: Option<SocketAddr>,
+    pub peer_certificates: Option<Arc<Vec<CertificateDer<'static>>>>,
+}
+
+impl Connected for TlsConnection {
+    type ConnectInfo = TlsConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        let (stream, connection) = self.inner.get_ref();
+
+        TlsConnectInfo {
+            remote_addr: stream.peer_addr().ok(),
+            peer_certificates: connection.peer_certificates().map(|certificates| {
+                Arc::new(
+                    certificates
+                        .iter()
+                        .map(|certificate| certificate.clone().into_owned())
+                        .collect(),
+                )
+            }),
+        }
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,556 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, MutexGuard, Weak,
+    },
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::commit::{self, Commit, Reply};
+
+const ARCHIVE_SUFFIX: &str = ".archive";
+
+// A stored entry, expiry in milliseconds since the epoch.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Stored {
+    pub key: String,
+    pub value: String,
+    pub version: u64,
+    pub expires_at: Option<u64>,
+}
+
+// Changes carry the whole resulting entry, so applying a record twice or on
+// top of a newer copy of the key ends in the same state.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Change {
+    Put(Stored),
+    Delete { key: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub revision: u64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+type Records = Arc<Mutex<Vec<Record>>>;
+
+// Every change to the store gets the next revision, and is queued for the
+// log writer if there is a log. The writer appends the changes queued
+// meanwhile together and syncs them at once. Expiry isn't logged, replayed
+// entries expire by their timestamp.
+#[derive(Debug, Default)]
+pub struct Wal {
+    revision: AtomicU64,
+    // Only taken while there is a log or a backup captures changes, so the
+    // log and the captures get changes in revision order.
+    captures: Mutex<Vec<Weak<Mutex<Vec<Record>>>>>,
+    capturing: Arc<AtomicUsize>,
+    log: Option<Log>,
+}
+
+#[derive(Debug)]
+struct Log {
+    path: PathBuf,
+    sender: Sender<Op>,
+    // The first revision that couldn't be synced, the log refuses every
+    // change after it.
+    failed_at: Arc<AtomicU64>,
+    checkpoint_due: Arc<AtomicBool>,
+}
+
+enum Op {
+    Append(Record, Reply),
+    Flush(Reply),
+    // Keeps the changes appended from here on for the next checkpoint.
+    Mark,
+    // The staged checkpoint and the revision it was started at.
+    Swap(PathBuf, u64, Reply),
+    Cancel,
+}
+
+// Collects the records appended after `start` until it's finished or
+// dropped.
+#[derive(Debug)]
+pub struct Capture {
+    pub start: u64,
+    records: Records,
+    capturing: Arc<AtomicUsize>,
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.capturing.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Wal {
+    // Appends to the log at `path` from now on. A checkpoint is due once
+    // `checkpoint_bytes` were appended since the last one, see
+    // `Store::checkpoint`.
+    pub fn start(&mut self, path: &Path, checkpoint_bytes: Option<u64>) -> io::Result<()> {
+        let file = open(path)?;
+        let bytes = file.metadata()?.len();
+        let (sender, receiver) = mpsc::channel();
+        let failed_at = Arc::new(AtomicU64::new(u64::MAX));
+        let checkpoint_due = Arc::new(AtomicBool::new(false));
+
+        let writer = Writer {
+            path: path.to_path_buf(),
+            file,
+            bytes,
+            checkpoint_bytes,
+            failed_at: failed_at.clone(),
+            checkpoint_due: checkpoint_due.clone(),
+            checkpointed: 0,
+            tail: None,
+        };
+        writer.check_size();
+        thread::Builder::new()
+            .name("wal-writer".to_string())
+            .spawn(move || writer.run(receiver))?;
+
+        self.log = Some(Log {
+            path: path.to_path_buf(),
+            sender,
+            failed_at,
+            checkpoint_due,
+        });
+        Ok(())
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
+    pub fn set_revision(&self, revision: u64) {
+        self.revision.store(revision, Ordering::SeqCst);
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.log.as_ref().map(|log| log.path.as_path())
+    }
+
+    // u64::MAX while every change was synced.
+    pub fn failed_at(&self) -> u64 {
+        self.log
+            .as_ref()
+            .map_or(u64::MAX, |log| log.failed_at.load(Ordering::SeqCst))
+    }
+
+    pub fn checkpoint_due(&self) -> bool {
+        self.log
+            .as_ref()
+            .is_some_and(|log| log.checkpoint_due.load(Ordering::Relaxed))
+    }
+
+    // Called with the changed key's shard locked, the returned commit is
+    // waited for after unlocking it. Returns the change's revision, the
+    // record is only built if it's logged or captured somewhere.
+    pub fn append(&self, change: impl FnOnce() -> Change) -> io::Result<(u64, Commit)> {
+        if self.log.is_none() && self.capturing.load(Ordering::SeqCst) == 0 {
+            let revision = self.revision.fetch_add(1, Ordering::SeqCst) + 1;
+            return Ok((revision, Commit::done()));
+        }
+
+        // A failed append may have been applied but not logged, the changes
+        // after it are rejected so the log doesn't silently lose it.
+        if self.failed_at() != u64::MAX {
+            return Err(io::Error::other("Write-ahead log failed earlier."));
+        }
+
+        let mut captures = self.captures();
+        let revision = self.revision.fetch_add(1, Ordering::SeqCst) + 1;
+        let record = Record {
+            revision,
+            timestamp: OffsetDateTime::now_utc(),
+            change: change(),
+        };
+
+        captures.retain(|capture| capture.strong_count() > 0);
+        for capture in captures.iter().filter_map(Weak::upgrade) {
+            lock(&capture).push(record.clone());
+        }
+
+        let Some(log) = &self.log else {
+            return Ok((revision, Commit::done()));
+        };
+        let (reply, commit) = Commit::pending();
+        log.send(Op::Append(record, reply))?;
+
+        Ok((revision, commit))
+    }
+
+    pub async fn flush(&self) -> io::Result<()> {
+        let Some(log) = &self.log else {
+            return Ok(());
+        };
+
+        let (reply, commit) = Commit::pending();
+        log.send(Op::Flush(reply))?;
+        commit.wait().await
+    }
+
+    pub fn capture(&self) -> Capture {
+        let mut captures = self.captures();
+        let records = Records::default();
+        captures.push(Arc::downgrade(&records));
+        self.capturing.fetch_add(1, Ordering::SeqCst);
+
+        Capture {
+            start: self.revision(),
+            records,
+            capturing: self.capturing.clone(),
+        }
+    }
+
+    // Returns the current revision and the records captured up to it.
+    pub fn finish(&self, capture: Capture) -> (u64, Vec<Record>) {
+        let _captures = self.captures();
+        let records = std::mem::take(&mut *lock(&capture.records));
+
+        (self.revision(), records)
+    }
+
+    // Like finish, the writer keeps the changes after the returned revision
+    // for the checkpoint from then on.
+    pub fn mark(&self, capture: Capture) -> io::Result<(u64, Vec<Record>)> {
+        let _captures = self.captures();
+        if let Some(log) = &self.log {
+            log.send(Op::Mark)?;
+        }
+        let records = std::mem::take(&mut *lock(&capture.records));
+
+        Ok((self.revision(), records))
+    }
+
+    // Replaces the log with the checkpoint at `staged` started at `revision`,
+    // after the writer appended the changes since the mark to it. The
+    // replaced log is archived, see `segments`.
+    pub fn swap(&self, staged: &Path, revision: u64) -> io::Result<()> {
+        let Some(log) = &self.log else {
+            return Ok(());
+        };
+
+        let (reply, commit) = Commit::pending();
+        log.send(Op::Swap(staged.to_path_buf(), revision, reply))?;
+        commit.wait_blocking()
+    }
+
+    pub fn cancel(&self) {
+        if let Some(log) = &self.log {
+            let _ = log.send(Op::Cancel);
+        }
+    }
+
+    fn captures(&self) -> MutexGuard<'_, Vec<Weak<Mutex<Vec<Record>>>>> {
+        self.captures
+            .lock()
+            .expect("Capture lock should not be poisoned.")
+    }
+}
+
+impl Log {
+    fn send(&self, op: Op) -> io::Result<()> {
+        self.sender.send(op).map_err(|_| commit::stopped())
+    }
+}
+
+struct Writer {
+    path: PathBuf,
+    file: File,
+    bytes: u64,
+    checkpoint_bytes: Option<u64>,
+    failed_at: Arc<AtomicU64>,
+    checkpoint_due: Arc<AtomicBool>,
+    // Size of the log right after the last checkpoint.
+    checkpointed: u64,
+    // Changes appended since the mark of a running checkpoint.
+    tail: Option<Vec<u8>>,
+}
+
+impl Writer {
+    fn run(mut self, receiver: Receiver<Op>) {
+        let mut records = Vec::new();
+        let mut waiters = Vec::new();
+
+        while let Some(batch) = commit::next_batch(&receiver) {
+            // Other requests apply to the changes queued before them.
+            for op in batch {
+                match op {
+                    Op::Append(record, reply) => {
+                        records.push(record);
+                        waiters.push(reply);
+                    }
+                    Op::Flush(reply) => {
+                        self.commit(&mut records, &mut waiters);
+                        let _ = reply.send(self.file.sync_all());
+                    }
+                    Op::Mark => {
+                        self.commit(&mut records, &mut waiters);
+                        self.tail = Some(Vec::new());
+                    }
+                    Op::Swap(staged, revision, reply) => {
+                        self.commit(&mut records, &mut waiters);
+                        let _ = reply.send(self.swap(&staged, revision));
+                    }
+                    Op::Cancel => self.tail = None,
+                }
+            }
+
+            self.commit(&mut records, &mut waiters);
+        }
+    }
+
+    fn commit(&mut self, records: &mut Vec<Record>, waiters: &mut Vec<Reply>) {
+        if records.is_empty() {
+            return;
+        }
+
+        let result = self.append(records);
+        records.clear();
+        commit::reply(std::mem::take(waiters), &result);
+    }
+
+    // A failed write is truncated away, so the log never has a torn line
+    // before a good one.
+    fn append(&mut self, records: &[Record]) -> io::Result<()> {
+        if self.failed_at.load(Ordering::SeqCst) != u64::MAX {
+            return Err(io::Error::other("Write-ahead log failed earlier."));
+        }
+
+        let mut data = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut data, record)?;
+            data.push(b'\n');
+        }
+
+        if let Err(error) = self
+            .file
+            .write_all(&data)
+            .and_then(|_| self.file.sync_data())
+        {
+            self.failed_at
+                .fetch_min(records[0].revision, Ordering::SeqCst);
+            let _ = self.file.set_len(self.bytes);
+            return Err(error);
+        }
+
+        self.bytes += data.len() as u64;
+        if let Some(tail) = &mut self.tail {
+            tail.extend_from_slice(&data);
+        }
+        self.check_size();
+
+        Ok(())
+    }
+
+    fn check_size(&self) {
+        if self
+            .checkpoint_bytes
+            .is_some_and(|limit| self.bytes.saturating_sub(self.checkpointed) > limit)
+        {
+            self.checkpoint_due.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // The log stays in place until the checkpoint replaces it, a crash in
+    // between leaves it archived twice.
+    fn swap(&mut self, staged: &Path, revision: u64) -> io::Result<()> {
+        let tail = self.tail.take().unwrap_or_default();
+        let mut file = OpenOptions::new().append(true).open(staged)?;
+        file.write_all(&tail)?;
+        file.sync_data()?;
+
+        let archived = archived(&self.path, revision);
+        match fs::hard_link(&self.path, &archived) {
+            Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        fs::rename(staged, &self.path)?;
+        let directory = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        File::open(directory)?.sync_all()?;
+
+        self.bytes = file.metadata()?.len();
+        self.checkpointed = self.bytes;
+        self.file = file;
+        self.checkpoint_due.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+fn lock(records: &Mutex<Vec<Record>>) -> MutexGuard<'_, Vec<Record>> {
+    records
+        .lock()
+        .expect("Capture lock should not be poisoned.")
+}
+
+pub fn open(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// Starts a new log at `path` with `entries` as puts at `revision`, followed
+// by the records `records` returns once the entries are written.
+pub fn checkpoint(
+    path: &Path,
+    revision: u64,
+    entries: impl Iterator<Item = Stored>,
+    records: impl FnOnce() -> io::Result<Vec<Record>>,
+) -> io::Result<()> {
+    let file = open(path)?;
+    file.set_len(0)?;
+    let mut file = io::BufWriter::new(file);
+    let timestamp = OffsetDateTime::now_utc();
+
+    for stored in entries {
+        let record = Record {
+            revision,
+            timestamp,
+            change: Change::Put(stored),
+        };
+        serde_json::to_writer(&mut file, &record)?;
+        file.write_all(b"\n")?;
+    }
+
+    for record in records()? {
+        serde_json::to_writer(&mut file, &record)?;
+        file.write_all(b"\n")?;
+    }
+
+    file.into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_data()
+}
+
+fn archived(path: &Path, revision: u64) -> PathBuf {
+    let mut archived = path.to_path_buf().into_os_string();
+    archived.push(format!(".{:020}{}", revision, ARCHIVE_SUFFIX));
+    archived.into()
+}
+
+// The logs replaced by checkpoints, oldest first, followed by the log at
+// `path`. Replayed in turn they hold every change since the log was started.
+pub fn segments(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut segments = archives(path)?;
+    segments.push(path.to_path_buf());
+
+    Ok(segments)
+}
+
+// Zero padded revisions sort the archives oldest first.
+pub fn archives(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(Vec::new());
+    };
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut archives = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let is_archive = file_name.to_str().is_some_and(|file_name| {
+            file_name
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('.'))
+                .and_then(|rest| rest.strip_suffix(ARCHIVE_SUFFIX))
+                .is_some_and(|revision| {
+                    !revision.is_empty() && revision.bytes().all(|byte| byte.is_ascii_digit())
+                })
+        });
+        if is_archive {
+            archives.push(entry.path());
+        }
+    }
+    archives.sort();
+
+    Ok(archives)
+}
+
+// Calls `each` with the records of the log at `path` in order, until it
+// returns false. A torn last line left by a crash is cut off, a bad line
+// anywhere else fails the replay. A missing log has no records.
+pub fn replay(path: &Path, mut each: impl FnMut(Record) -> bool) -> io::Result<()> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    let mut valid = 0;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+
+        let record = match serde_json::from_slice::<Record>(&line) {
+            Ok(record) if line.ends_with(b"\n") => record,
+            result => {
+                if !reader.fill_buf()?.is_empty() {
+                    let message = match result {
+                        Err(e) => e.to_string(),
+                        Ok(_) => "missing newline".to_string(),
+                    };
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Bad record after byte {} of {}: {}",
+                            valid,
+                            path.display(),
+                            message
+                        ),
+                    ));
+                }
+
+                warn!(
+                    "Cutting off torn record at byte {} of {}.",
+                    valid,
+                    path.display()
+                );
+                OpenOptions::new().write(true).open(path)?.set_len(valid)?;
+                return Ok(());
+            }
+        };
+
+        valid += line.len() as u64;
+        if !each(record) {
+            info!("Stopped replaying {} at byte {}.", path.display(), valid);
+            return Ok(());
+        }
+    }
+}
@@ -1,12 +1,44 @@
 use backend::{
+    admin::AdminService,
+    audit::{Action, AuditEntry, AuditLog, AuditQuery},
+    auth::hash_api_key,
+    backend_server::DeleteValueRequest,
     backend_server::{
-        kv_client::KvClient, kv_server::KvServer, GetValueRequest, InsertValueRequest,
+        admin_client::AdminClient, admin_server::Admin, import_chunk::Mode, kv_client::KvClient,
+        kv_server::Kv, kv_server::KvServer, watch_event::Kind, BackupRequest,
+        CompareAndSwapRequest, ExportRequest, GetQuotaUsageRequest, GetValueRequest, ImportChunk,
+        InsertValueRequest, QueryAuditRequest, ScanRequest, SetQuotaRequest, WatchRequest,
     },
+    backup,
+    config::{ApiKey, Audit, Idempotency, Memory, Restore, Settings, Tls, Wal},
+    eviction::EvictionPolicy,
+    metrics::Metrics,
+    quota::Quota,
+    store::{InsertError, Store, CURRENT_VERSION, VERSION_MISMATCH},
+    tls::{self, CertificateStore, TlsVersion},
+    wal::{Change, Stored},
     BackendService,
 };
+use opentelemetry_proto::tonic::collector::trace::v1::{
+    trace_service_server::{TraceService, TraceServiceServer},
+    ExportTraceServiceRequest, ExportTraceServiceResponse,
+};
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::net::TcpListener;
-use tokio_stream::wrappers::TcpListenerStream;
-use tonic::{transport::Server, Code};
+use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+use tonic::{
+    metadata::MetadataValue,
+    transport::{Certificate, Channel, ClientTlsConfig, Identity, Server},
+    Code, Request, Response, Status,
+};
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+use tonic_types::StatusExt;
 
 #[tokio::test]
 async fn get_value_request_should_return_not_found_when_invalid_key() {
@@ -61,6 +93,7 @@ async fn insert_value_should_return_success() {
     let request = InsertValueRequest {
         key: key.clone(),
         value,
+        ..Default::default()
     };
     let response = client.insert_value(request).await.unwrap();
 
@@ -71,3 +104,1765 @@ async fn insert_value_should_return_success() {
 
     assert_eq!("value1", response.into_inner().value);
 }
+
+#[tokio::test]
+async fn health_check_should_report_kv_service_serving() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &Settings::default())
+            .await
+            .unwrap();
+    });
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HealthClient::new(channel);
+
+    let request = HealthCheckRequest {
+        service: "kv.KV".to_string(),
+    };
+    let response = client.check(request).await.unwrap();
+
+    assert_eq!(ServingStatus::Serving as i32, response.into_inner().status);
+}
+
+#[tokio::test]
+async fn metrics_should_expose_rpc_counts_and_storage_usage() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let metrics_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_addr = metrics_listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        backend::run(listener, Some(metrics_listener), &Settings::default())
+            .await
+            .unwrap();
+    });
+
+    let mut client = KvClient::connect(format!("http://{}", addr)).await.unwrap();
+
+    let request = InsertValueRequest {
+        key: "key1".to_string(),
+        value: "value1".to_string(),
+        ..Default::default()
+    };
+    client.insert_value(request).await.unwrap();
+
+    let request = GetValueRequest {
+        key: "invalid_key".to_string(),
+    };
+    let _ = client.get_value(request).await;
+
+    let mut admin = AdminClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let request = GetQuotaUsageRequest {
+        namespace: "team".to_string(),
+    };
+    admin.get_quota_usage(request).await.unwrap();
+    let _ = admin.query_audit(QueryAuditRequest::default()).await;
+
+    let body = reqwest::get(format!("http://{}/metrics", metrics_addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(body.contains(r#"grpc_requests_total{code="Ok",method="InsertValue"} 1"#));
+    assert!(body.contains(r#"grpc_requests_total{code="NotFound",method="GetValue"} 1"#));
+    assert!(body.contains("grpc_request_duration_seconds_count{method=\"GetValue\"} 1"));
+    assert!(body.contains(r#"grpc_requests_total{code="Ok",method="GetQuotaUsage"} 1"#));
+    assert!(
+        body.contains(r#"grpc_requests_total{code="FailedPrecondition",method="QueryAudit"} 1"#)
+    );
+    assert!(body.contains("kv_keys 1"));
+    assert!(body.contains("kv_storage_bytes 10"));
+    assert!(body.contains("kv_lock_wait_seconds_count 2"));
+}
+
+#[derive(Clone, Default)]
+struct InMemoryCollector {
+    requests: Arc<Mutex<Vec<ExportTraceServiceRequest>>>,
+}
+
+#[tonic::async_trait]
+impl TraceService for InMemoryCollector {
+    async fn export(
+        &self,
+        request: Request<ExportTraceServiceRequest>,
+    ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+        self.requests.lock().unwrap().push(request.into_inner());
+
+        Ok(Response::new(ExportTraceServiceResponse {
+            partial_success: None,
+        }))
+    }
+}
+
+#[tokio::test]
+async fn rpc_spans_should_continue_incoming_trace_and_be_exported() {
+    let collector = InMemoryCollector::default();
+    let collector_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let collector_addr = collector_listener.local_addr().unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(TraceServiceServer::new(collector.clone()))
+            .serve_with_incoming(TcpListenerStream::new(collector_listener)),
+    );
+
+    let (subscriber, tracer_provider) = backend::telemetry::get_subscriber(
+        "backend",
+        "info",
+        Some(&format!("http://{}", collector_addr)),
+    )
+    .unwrap();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &Settings::default())
+            .await
+            .unwrap();
+    });
+
+    let mut client = KvClient::connect(format!("http://{}", addr)).await.unwrap();
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let mut request = Request::new(GetValueRequest {
+        key: "key1".to_string(),
+    });
+    request.metadata_mut().insert(
+        "traceparent",
+        MetadataValue::try_from(format!("00-{}-00f067aa0ba902b7-01", trace_id)).unwrap(),
+    );
+    let _ = client.get_value(request).await;
+
+    tokio::task::spawn_blocking(move || tracer_provider.force_flush())
+        .await
+        .unwrap();
+
+    let mut exported = Vec::new();
+    for _ in 0..50 {
+        exported = collector
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|request| &request.resource_spans)
+            .flat_map(|resource_spans| &resource_spans.scope_spans)
+            .flat_map(|scope_spans| &scope_spans.spans)
+            .map(|span| (span.name.clone(), span.trace_id.clone()))
+            .collect();
+
+        if !exported.is_empty() {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let expected_trace_id: Vec<u8> = (0..trace_id.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&trace_id[i..i + 2], 16).unwrap())
+        .collect();
+
+    let names: Vec<_> = exported
+        .iter()
+        .filter(|(_, span_trace_id)| *span_trace_id == expected_trace_id)
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    assert!(names.contains(&"Request span"));
+    assert!(names.contains(&"get_value"));
+}
+
+#[tokio::test]
+async fn delete_value_should_remove_key() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &Settings::default())
+            .await
+            .unwrap();
+    });
+
+    let mut client = KvClient::connect(format!("http://{}", addr)).await.unwrap();
+
+    let request = InsertValueRequest {
+        key: "key1".to_string(),
+        value: "value1".to_string(),
+        ..Default::default()
+    };
+    client.insert_value(request).await.unwrap();
+
+    let request = DeleteValueRequest {
+        key: "key1".to_string(),
+    };
+    let response = client.delete_value(request).await.unwrap();
+
+    assert!(response.into_inner().success);
+
+    let request = GetValueRequest {
+        key: "key1".to_string(),
+    };
+    let status = client.get_value(request).await.unwrap_err();
+
+    assert_eq!(Code::NotFound, status.code());
+
+    let request = DeleteValueRequest {
+        key: "key1".to_string(),
+    };
+    let status = client.delete_value(request).await.unwrap_err();
+
+    assert_eq!(Code::NotFound, status.code());
+}
+
+#[tokio::test]
+async fn compare_and_swap_should_only_write_expected_version() {
+    let channel = spawn_backend(Settings::default()).await;
+    let mut client = KvClient::new(channel);
+
+    let swap = |expected_version: u64, value: &str| CompareAndSwapRequest {
+        key: "key1".to_string(),
+        expected_version,
+        value: value.to_string(),
+        ..Default::default()
+    };
+
+    let response = client.compare_and_swap(swap(0, "value1")).await.unwrap();
+    assert_eq!(1, response.into_inner().version);
+
+    for (expected_version, current) in [(0, "1"), (2, "1")] {
+        let status = client
+            .compare_and_swap(swap(expected_version, "value2"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(Code::FailedPrecondition, status.code());
+        let info = status.get_details_error_info().unwrap();
+        assert_eq!(VERSION_MISMATCH, info.reason);
+        assert_eq!(current, info.metadata[CURRENT_VERSION]);
+    }
+
+    let response = client.compare_and_swap(swap(1, "value2")).await.unwrap();
+    assert_eq!(2, response.into_inner().version);
+
+    let request = GetValueRequest {
+        key: "key1".to_string(),
+    };
+    let response = client.get_value(request).await.unwrap().into_inner();
+    assert_eq!("value2", response.value);
+    assert_eq!(2, response.version);
+
+    let request = DeleteValueRequest {
+        key: "key1".to_string(),
+    };
+    client.delete_value(request).await.unwrap();
+    let status = client
+        .compare_and_swap(swap(2, "value3"))
+        .await
+        .unwrap_err();
+    let info = status.get_details_error_info().unwrap();
+    assert!(!info.metadata.contains_key(CURRENT_VERSION));
+}
+
+#[tokio::test]
+async fn calls_past_their_deadline_should_fail_without_changes() {
+    let service = BackendService::new();
+
+    let mut request = Request::new(InsertValueRequest {
+        key: "key1".to_string(),
+        value: "value1".to_string(),
+        ..Default::default()
+    });
+    request.set_timeout(Duration::ZERO);
+    let status = service.insert_value(request).await.unwrap_err();
+    assert_eq!(Code::DeadlineExceeded, status.code());
+
+    let status = service
+        .get_value(Request::new(GetValueRequest {
+            key: "key1".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(Code::NotFound, status.code());
+
+    // Streamed exports end with the error instead.
+    let admin = AdminService::new(Arc::default(), None, service);
+    let mut request = Request::new(ExportRequest {
+        prefix: String::new(),
+    });
+    request.set_timeout(Duration::ZERO);
+    let mut chunks = admin.export(request).await.unwrap().into_inner();
+    let status = chunks.next().await.unwrap().unwrap_err();
+    assert_eq!(Code::DeadlineExceeded, status.code());
+}
+
+#[tokio::test]
+async fn retried_writes_with_idempotency_key_should_apply_once() {
+    let service = BackendService::new();
+    let insert = |value: &str| InsertValueRequest {
+        key: "key1".to_string(),
+        value: value.to_string(),
+        ..Default::default()
+    };
+
+    for _ in 0..2 {
+        let response = service
+            .insert_value(with_idempotency_key(insert("value1"), "insert-1"))
+            .await
+            .unwrap();
+        assert_eq!(1, response.into_inner().version);
+    }
+
+    let status = service
+        .insert_value(with_idempotency_key(insert("value2"), "insert-1"))
+        .await
+        .unwrap_err();
+    assert_eq!(Code::InvalidArgument, status.code());
+
+    let response = service
+        .get_value(Request::new(GetValueRequest {
+            key: "key1".to_string(),
+        }))
+        .await
+        .unwrap();
+    assert_eq!(1, response.into_inner().version);
+
+    // A replayed delete succeeds again instead of finding the key gone.
+    for _ in 0..2 {
+        let request = DeleteValueRequest {
+            key: "key1".to_string(),
+        };
+        let response = service
+            .delete_value(with_idempotency_key(request, "delete-1"))
+            .await
+            .unwrap();
+        assert!(response.into_inner().success);
+    }
+
+    // Writes without a key are applied every time.
+    for version in [1, 2] {
+        let response = service
+            .insert_value(Request::new(insert("value1")))
+            .await
+            .unwrap();
+        assert_eq!(version, response.into_inner().version);
+    }
+}
+
+#[tokio::test]
+async fn oldest_idempotency_keys_should_be_forgotten_past_max_keys() {
+    let service = BackendService::new().with_idempotency(&Idempotency {
+        window_secs: 300,
+        max_keys: NonZeroUsize::new(1).unwrap(),
+    });
+    let insert = |key: &'static str| {
+        with_idempotency_key(
+            InsertValueRequest {
+                key: "key1".to_string(),
+                value: "value1".to_string(),
+                ..Default::default()
+            },
+            key,
+        )
+    };
+
+    // The second key evicts the first, so its retry is applied again.
+    for (key, version) in [("insert-1", 1), ("insert-2", 2), ("insert-1", 3)] {
+        let response = service.insert_value(insert(key)).await.unwrap();
+        assert_eq!(version, response.into_inner().version);
+    }
+}
+
+#[tokio::test]
+async fn exported_keys_should_import_into_another_backend() {
+    let insert = |key: &str, value: &str| InsertValueRequest {
+        key: key.to_string(),
+        value: value.to_string(),
+        ..Default::default()
+    };
+    let source = spawn_backend(Settings::default()).await;
+    let mut client = KvClient::new(source.clone());
+    for (key, value) in [
+        ("team-a:key1", "value1"),
+        ("team-a:key2", "value2"),
+        ("team-b:key3", "value3"),
+    ] {
+        client.insert_value(insert(key, value)).await.unwrap();
+    }
+
+    let request = ExportRequest {
+        prefix: "team-a:".to_string(),
+    };
+    let mut chunks = AdminClient::new(source)
+        .export(request)
+        .await
+        .unwrap()
+        .into_inner();
+    let mut data = Vec::new();
+    while let Some(chunk) = chunks.message().await.unwrap() {
+        data.extend(chunk.data);
+    }
+    assert_eq!(
+        "{\"key\":\"team-a:key1\",\"value\":\"value1\",\"version\":1}\n\
+         {\"key\":\"team-a:key2\",\"value\":\"value2\",\"version\":1}\n",
+        String::from_utf8(data.clone()).unwrap()
+    );
+
+    let target = spawn_backend(Settings::default()).await;
+    let mut client = KvClient::new(target.clone());
+    let mut admin = AdminClient::new(target);
+    client
+        .insert_value(insert("team-a:key1", "kept"))
+        .await
+        .unwrap();
+
+    // Chunks split lines anywhere and the last line has no newline.
+    let mut rest = data.split_off(10);
+    rest.extend(b"\nnot json\n{\"key\": \"team-b:key3\", \"value\": \"value3\"}");
+    let chunks = vec![
+        ImportChunk {
+            prefix: "team-a:".to_string(),
+            mode: Mode::SkipExisting.into(),
+            data,
+        },
+        ImportChunk {
+            data: rest,
+            ..Default::default()
+        },
+    ];
+    let response = admin
+        .import(tokio_stream::iter(chunks))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(
+        (1, 1, 2),
+        (response.imported, response.skipped, response.failed)
+    );
+    assert_eq!(4, response.errors[0].line);
+    assert!(response.errors[0].message.starts_with("Invalid JSON"));
+    assert_eq!(5, response.errors[1].line);
+    assert_eq!("team-b:key3", response.errors[1].key);
+
+    let value = |key: &str| GetValueRequest {
+        key: key.to_string(),
+    };
+    let kept = client.get_value(value("team-a:key1")).await.unwrap();
+    assert_eq!("kept", kept.into_inner().value);
+    let imported = client.get_value(value("team-a:key2")).await.unwrap();
+    assert_eq!("value2", imported.into_inner().value);
+
+    let chunks = vec![ImportChunk {
+        prefix: "team-a:".to_string(),
+        mode: Mode::Overwrite.into(),
+        data: b"{\"key\": \"team-a:key1\", \"value\": \"value1\"}\n".to_vec(),
+    }];
+    let response = admin
+        .import(tokio_stream::iter(chunks))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(1, response.imported);
+    let overwritten = client
+        .get_value(value("team-a:key1"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!("value1", overwritten.value);
+    assert_eq!(2, overwritten.version);
+}
+
+#[tokio::test]
+async fn bulk_operations_should_need_admin_on_prefix() {
+    let mut admin = AdminClient::new(spawn_backend(policy_settings()).await);
+
+    let export = |api_key| {
+        authorized(
+            ExportRequest {
+                prefix: "team-a:".to_string(),
+            },
+            Some(api_key),
+        )
+    };
+    let status = admin.export(export("batch-key")).await.unwrap_err();
+    assert_eq!(Code::PermissionDenied, status.code());
+    admin.export(export("frontend-key")).await.unwrap();
+
+    let chunks = vec![ImportChunk {
+        prefix: "team-a:".to_string(),
+        ..Default::default()
+    }];
+    let request = authorized(tokio_stream::iter(chunks), Some("batch-key"));
+    let status = admin.import(request).await.unwrap_err();
+    assert_eq!(Code::PermissionDenied, status.code());
+
+    let request = authorized(BackupRequest {}, Some("batch-key"));
+    let status = admin.backup(request).await.unwrap_err();
+    assert_eq!(Code::PermissionDenied, status.code());
+}
+
+#[tokio::test]
+async fn write_ahead_log_should_recover_store_on_restart() {
+    let directory = temp_directory();
+    let wal = Wal {
+        enabled: true,
+        path: directory.join("wal.jsonl").display().to_string(),
+        ..Wal::default()
+    };
+    let insert = |key: &str, value: &str, ttl_seconds| {
+        Request::new(InsertValueRequest {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl_seconds,
+        })
+    };
+    let get = |key: &str| {
+        Request::new(GetValueRequest {
+            key: key.to_string(),
+        })
+    };
+
+    let backend = BackendService::new().with_recovery(&wal, None).unwrap();
+    backend
+        .insert_value(insert("key1", "value1", 0))
+        .await
+        .unwrap();
+    backend
+        .insert_value(insert("key1", "value2", 0))
+        .await
+        .unwrap();
+    backend
+        .insert_value(insert("key2", "value", 3600))
+        .await
+        .unwrap();
+    backend
+        .insert_value(insert("key3", "value", 0))
+        .await
+        .unwrap();
+    let request = Request::new(DeleteValueRequest {
+        key: "key3".to_string(),
+    });
+    backend.delete_value(request).await.unwrap();
+    drop(backend);
+
+    // A crash while appending leaves a torn record behind.
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&wal.path)
+        .unwrap();
+    std::io::Write::write_all(&mut file, b"{\"revision\":6,\"timest").unwrap();
+
+    let backend = BackendService::new().with_recovery(&wal, None).unwrap();
+    let value = backend.get_value(get("key1")).await.unwrap().into_inner();
+    assert_eq!(("value2", 2), (value.value.as_str(), value.version));
+    backend.get_value(get("key2")).await.unwrap();
+    let status = backend.get_value(get("key3")).await.unwrap_err();
+    assert_eq!(Code::NotFound, status.code());
+
+    backend
+        .insert_value(insert("key3", "value", 0))
+        .await
+        .unwrap();
+    drop(backend);
+    let backend = BackendService::new().with_recovery(&wal, None).unwrap();
+    backend.get_value(get("key3")).await.unwrap();
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn checkpoint_should_compact_log_while_writes_continue() {
+    let directory = temp_directory();
+    let wal = Wal {
+        enabled: true,
+        path: directory.join("wal.jsonl").display().to_string(),
+        checkpoint_bytes: Some(4096),
+    };
+    let mut store = Store::with_shards(4);
+    backup::recover(&mut store, &wal, None).unwrap();
+    let store = Arc::new(store);
+    async fn insert(store: &Store, key: String, value: &str) {
+        store
+            .insert(key, value.to_string(), None, |_, _, _| Ok(()))
+            .await
+            .unwrap();
+    }
+
+    for index in 0..100 {
+        insert(&store, "key1".to_string(), &format!("value{}", index)).await;
+    }
+    assert!(store.checkpoint_due());
+
+    // Changes made while the store is copied end up in the new log too.
+    let writer = {
+        let store = store.clone();
+        tokio::spawn(async move {
+            for index in 0..1000 {
+                insert(&store, format!("key-{}", index), "value").await;
+            }
+        })
+    };
+    let checkpoint = {
+        let store = store.clone();
+        tokio::task::spawn_blocking(move || store.checkpoint())
+    };
+    checkpoint.await.unwrap().unwrap();
+    writer.await.unwrap();
+
+    // Keys written while their shard was copied can be logged twice.
+    let lines = std::fs::read_to_string(&wal.path).unwrap().lines().count();
+    assert!((1001..1100).contains(&lines), "{} lines", lines);
+    let revision = store.revision();
+    drop(store);
+
+    let mut recovered = Store::with_shards(4);
+    assert_eq!(
+        revision,
+        backup::recover(&mut recovered, &wal, None).unwrap()
+    );
+    assert_eq!(1001, recovered.len());
+    assert_eq!(Some(("value99".to_string(), 100)), recovered.get("key1"));
+    assert!(recovered.get("key-999").is_some());
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn changes_should_be_undone_when_log_sync_fails() {
+    let mut store = Store::with_shards(4);
+    store.apply(Change::Put(Stored {
+        key: "key1".to_string(),
+        value: "value1".to_string(),
+        version: 1,
+        expires_at: None,
+    }));
+    store
+        .set_wal(std::path::Path::new("/dev/full"), None)
+        .unwrap();
+    let store = Arc::new(store);
+
+    // Changes stacked on one another while the log fails are undone in any
+    // order.
+    let writers: Vec<_> = (0..16)
+        .map(|index| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                if index % 2 == 0 {
+                    store
+                        .insert("key1".to_string(), "value2".to_string(), None, |_, _, _| {
+                            Ok(())
+                        })
+                        .await
+                        .map(|_| ())
+                } else {
+                    store.remove("key1", |_| Ok(())).await.map(|_| ())
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        let error = writer.await.unwrap().unwrap_err();
+        assert!(matches!(error, InsertError::Log(_)));
+    }
+    let error = store
+        .insert("key2".to_string(), "value".to_string(), None, |_, _, _| {
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, InsertError::Log(_)));
+
+    assert_eq!(Some(("value1".to_string(), 1)), store.get("key1"));
+    assert_eq!(None, store.get("key2"));
+    assert_eq!(1, store.len());
+    assert_eq!("key1value1".len() as u64, store.bytes());
+}
+
+#[tokio::test]
+async fn backup_should_restore_to_requested_revision() {
+    let directory = temp_directory();
+    let settings = Settings {
+        wal: Wal {
+            enabled: true,
+            path: directory.join("wal.jsonl").display().to_string(),
+            ..Wal::default()
+        },
+        ..Settings::default()
+    };
+    let channel = spawn_backend(settings).await;
+    let mut client = KvClient::new(channel.clone());
+    let insert = |key: &str, value: &str| InsertValueRequest {
+        key: key.to_string(),
+        value: value.to_string(),
+        ..Default::default()
+    };
+    let get = |key: &str| {
+        Request::new(GetValueRequest {
+            key: key.to_string(),
+        })
+    };
+
+    client.insert_value(insert("key1", "value1")).await.unwrap();
+    client.insert_value(insert("key2", "value2")).await.unwrap();
+
+    let mut chunks = AdminClient::new(channel)
+        .backup(BackupRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    let mut archive = Vec::new();
+    while let Some(chunk) = chunks.message().await.unwrap() {
+        archive.extend(chunk.data);
+    }
+    let archive = String::from_utf8(archive).unwrap();
+    let lines: Vec<serde_json::Value> = archive
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(4, lines.len());
+    assert_eq!("header", lines[0]["type"]);
+    assert_eq!(2, lines[0]["start_revision"]);
+    assert_eq!("footer", lines[3]["type"]);
+    assert_eq!(2, lines[3]["revision"]);
+
+    // Revisions 3 to 5.
+    client.insert_value(insert("key1", "value3")).await.unwrap();
+    let request = DeleteValueRequest {
+        key: "key2".to_string(),
+    };
+    client.delete_value(request).await.unwrap();
+    client.insert_value(insert("key3", "value3")).await.unwrap();
+
+    let backup = directory.join("backup.jsonl");
+    std::fs::write(&backup, archive).unwrap();
+    let restored = Wal {
+        enabled: true,
+        path: directory.join("restored.jsonl").display().to_string(),
+        ..Wal::default()
+    };
+    std::fs::copy(directory.join("wal.jsonl"), &restored.path).unwrap();
+    let restore = Restore {
+        backup: backup.display().to_string(),
+        until_revision: Some(4),
+        until_timestamp: None,
+    };
+
+    let backend = BackendService::new()
+        .with_recovery(&restored, Some(&restore))
+        .unwrap();
+    let value = backend.get_value(get("key1")).await.unwrap().into_inner();
+    assert_eq!(("value3", 2), (value.value.as_str(), value.version));
+    for key in ["key2", "key3"] {
+        let status = backend.get_value(get(key)).await.unwrap_err();
+        assert_eq!(Code::NotFound, status.code());
+    }
+    drop(backend);
+
+    // The restored backend continues on a new log without the later
+    // changes.
+    let backend = BackendService::new()
+        .with_recovery(&restored, None)
+        .unwrap();
+    let value = backend.get_value(get("key1")).await.unwrap().into_inner();
+    assert_eq!("value3", value.value);
+    let status = backend.get_value(get("key3")).await.unwrap_err();
+    assert_eq!(Code::NotFound, status.code());
+
+    let restore = Restore {
+        until_revision: Some(1),
+        ..restore
+    };
+    assert!(BackendService::new()
+        .with_recovery(&restored, Some(&restore))
+        .is_err());
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backup_should_restore_across_checkpoints() {
+    let directory = temp_directory();
+    let wal = Wal {
+        enabled: true,
+        path: directory.join("wal.jsonl").display().to_string(),
+        checkpoint_bytes: None,
+    };
+    let mut store = Store::with_shards(4);
+    backup::recover(&mut store, &wal, None).unwrap();
+    let store = Arc::new(store);
+    let insert = |key: &str, value: &str| {
+        store.insert(key.to_string(), value.to_string(), None, |_, _, _| Ok(()))
+    };
+
+    insert("key1", "value1").await.unwrap();
+    insert("key2", "value2").await.unwrap();
+
+    let capture = store.capture();
+    let mut lines = vec![backup::ArchiveLine::Header {
+        format_version: backup::FORMAT_VERSION,
+        start_revision: capture.start,
+        created_at: time::OffsetDateTime::now_utc(),
+    }];
+    for index in 0..store.shards() {
+        lines.extend(
+            store
+                .entries(index)
+                .into_iter()
+                .map(backup::ArchiveLine::Entry),
+        );
+    }
+    let (revision, records) = store.finish(capture);
+    lines.extend(records.into_iter().map(backup::ArchiveLine::Record));
+    lines.push(backup::ArchiveLine::Footer {
+        revision,
+        completed_at: time::OffsetDateTime::now_utc(),
+    });
+    let backup = directory.join("backup.jsonl");
+    std::fs::write(&backup, backup::archive_lines(&lines)).unwrap();
+
+    // Revisions 3 and 4 end up in an archived log, 5 in the checkpoint.
+    insert("key1", "value3").await.unwrap();
+    store.remove("key2", |_| Ok(())).await.unwrap();
+    let checkpoint = {
+        let store = store.clone();
+        tokio::task::spawn_blocking(move || store.checkpoint())
+    };
+    checkpoint.await.unwrap().unwrap();
+    insert("key3", "value3").await.unwrap();
+    drop(store);
+
+    let restore = Restore {
+        backup: backup.display().to_string(),
+        until_revision: Some(4),
+        until_timestamp: None,
+    };
+    let mut restored = Store::with_shards(4);
+    assert_eq!(
+        4,
+        backup::recover(&mut restored, &wal, Some(&restore)).unwrap()
+    );
+    assert_eq!(Some(("value3".to_string(), 2)), restored.get("key1"));
+    assert_eq!(None, restored.get("key2"));
+    assert_eq!(None, restored.get("key3"));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn requests_without_valid_api_key_should_be_unauthenticated() {
+    let mut client = spawn_backend_with_policy().await;
+
+    for api_key in [None, Some("wrong-key")] {
+        let request = authorized(
+            GetValueRequest {
+                key: "team-a:key1".to_string(),
+            },
+            api_key,
+        );
+        let status = client.get_value(request).await.unwrap_err();
+
+        assert_eq!(Code::Unauthenticated, status.code());
+    }
+}
+
+#[tokio::test]
+async fn policy_should_limit_callers_to_granted_keys() {
+    let mut client = spawn_backend_with_policy().await;
+
+    let request = authorized(
+        InsertValueRequest {
+            key: "team-a:key1".to_string(),
+            value: "value1".to_string(),
+            ..Default::default()
+        },
+        Some("batch-key"),
+    );
+    client.insert_value(request).await.unwrap();
+
+    let request = authorized(
+        InsertValueRequest {
+            key: "team-b:key1".to_string(),
+            value: "value1".to_string(),
+            ..Default::default()
+        },
+        Some("batch-key"),
+    );
+    let status = client.insert_value(request).await.unwrap_err();
+
+    assert_eq!(Code::PermissionDenied, status.code());
+}
+
+#[tokio::test]
+async fn only_admins_should_act_on_behalf_of_principals() {
+    let mut client = spawn_backend_with_policy().await;
+
+    let mut request = authorized(
+        GetValueRequest {
+            key: "team-a:key1".to_string(),
+        },
+        Some("batch-key"),
+    );
+    request
+        .metadata_mut()
+        .insert("x-kv-principal", MetadataValue::from_static("alice"));
+    let status = client.get_value(request).await.unwrap_err();
+
+    assert_eq!(Code::PermissionDenied, status.code());
+
+    for (key, expected) in [
+        ("team-a:key1", Code::NotFound),
+        ("team-b:key1", Code::PermissionDenied),
+    ] {
+        let mut request = authorized(
+            GetValueRequest {
+                key: key.to_string(),
+            },
+            Some("frontend-key"),
+        );
+        request
+            .metadata_mut()
+            .insert("x-kv-principal", MetadataValue::from_static("alice"));
+        request
+            .metadata_mut()
+            .insert("x-kv-roles", MetadataValue::from_static("team-a-writer"));
+        let status = client.get_value(request).await.unwrap_err();
+
+        assert_eq!(
+            expected,
+            status.code(),
+            "Unexpected status for key: {}",
+            key
+        );
+    }
+}
+
+#[tokio::test]
+async fn scan_should_page_through_readable_keys_in_order() {
+    let mut client = spawn_backend_with_policy().await;
+
+    for key in [
+        "team-a:key3",
+        "team-a:key1",
+        "team-b:key1",
+        "team-a:key2",
+        "team-a:key5",
+        "team-a:key4",
+    ] {
+        let request = authorized(
+            InsertValueRequest {
+                key: key.to_string(),
+                value: "value1".to_string(),
+                ..Default::default()
+            },
+            Some("frontend-key"),
+        );
+        client.insert_value(request).await.unwrap();
+    }
+
+    let mut pages = Vec::new();
+    let mut start_after = String::new();
+    loop {
+        let request = authorized(
+            ScanRequest {
+                prefix: "team-a:".to_string(),
+                start_after,
+                limit: 2,
+            },
+            Some("batch-key"),
+        );
+        let response = client.scan(request).await.unwrap().into_inner();
+
+        pages.push(
+            response
+                .entries
+                .into_iter()
+                .map(|entry| entry.key)
+                .collect::<Vec<_>>(),
+        );
+        if response.next_start_after.is_empty() {
+            break;
+        }
+        start_after = response.next_start_after;
+    }
+
+    assert_eq!(
+        vec![
+            vec!["team-a:key1", "team-a:key2"],
+            vec!["team-a:key3", "team-a:key4"],
+            vec!["team-a:key5"],
+        ],
+        pages
+    );
+
+    let request = authorized(ScanRequest::default(), Some("batch-key"));
+    let status = client.scan(request).await.unwrap_err();
+    assert_eq!(Code::PermissionDenied, status.code());
+
+    let request = authorized(ScanRequest::default(), Some("frontend-key"));
+    let response = client.scan(request).await.unwrap().into_inner();
+    assert_eq!(6, response.entries.len());
+    assert_eq!(1, response.entries[0].version);
+}
+
+#[tokio::test]
+async fn watch_should_stream_changes_of_readable_keys_under_prefix() {
+    let mut client = spawn_backend_with_policy().await;
+
+    let request = authorized(
+        WatchRequest {
+            prefix: "team-a:".to_string(),
+        },
+        Some("batch-key"),
+    );
+    let mut changes = client.watch(request).await.unwrap().into_inner();
+
+    for key in ["team-b:key1", "team-a:key1", "team-a:key1"] {
+        let request = authorized(
+            InsertValueRequest {
+                key: key.to_string(),
+                value: "value1".to_string(),
+                ..Default::default()
+            },
+            Some("frontend-key"),
+        );
+        client.insert_value(request).await.unwrap();
+    }
+    let request = authorized(
+        DeleteValueRequest {
+            key: "team-a:key1".to_string(),
+        },
+        Some("frontend-key"),
+    );
+    client.delete_value(request).await.unwrap();
+
+    let mut events = Vec::new();
+    for _ in 0..3 {
+        let event = changes.message().await.unwrap().unwrap();
+        events.push((event.kind(), event.key, event.value, event.version));
+    }
+
+    assert_eq!(
+        vec![
+            (
+                Kind::Put,
+                "team-a:key1".to_string(),
+                "value1".to_string(),
+                1
+            ),
+            (
+                Kind::Put,
+                "team-a:key1".to_string(),
+                "value1".to_string(),
+                2
+            ),
+            (Kind::Delete, "team-a:key1".to_string(), String::new(), 2),
+        ],
+        events
+    );
+
+    let request = authorized(WatchRequest::default(), Some("batch-key"));
+    let status = client.watch(request).await.unwrap_err();
+    assert_eq!(Code::PermissionDenied, status.code());
+}
+
+async fn spawn_backend_with_policy() -> KvClient<Channel> {
+    KvClient::new(spawn_backend(policy_settings()).await)
+}
+
+fn policy_settings() -> Settings {
+    let mut settings = Settings::default();
+    settings.auth.policy_file = Some("tests/fixtures/policy.yml".to_string());
+    settings.auth.api_keys = [("frontend", "frontend-key"), ("batch-job", "batch-key")]
+        .into_iter()
+        .map(|(name, key)| ApiKey {
+            name: name.to_string(),
+            key_hash: hash_api_key(key),
+        })
+        .collect();
+
+    settings
+}
+
+async fn spawn_backend(settings: Settings) -> Channel {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &settings).await.unwrap();
+    });
+
+    Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+fn authorized<T>(message: T, api_key: Option<&str>) -> Request<T> {
+    let mut request = Request::new(message);
+
+    if let Some(api_key) = api_key {
+        request.metadata_mut().insert(
+            "authorization",
+            MetadataValue::try_from(format!("Bearer {}", api_key)).unwrap(),
+        );
+    }
+
+    request
+}
+
+#[tokio::test]
+async fn client_certificate_subject_should_identify_caller() {
+    let address = spawn_backend_with_mtls().await;
+
+    let mut client = KvClient::new(
+        connect_with_identity(&address, Some(("client.pem", "client-key.pem")))
+            .await
+            .expect("Client with certificate signed by the CA should connect."),
+    );
+
+    let request = InsertValueRequest {
+        key: "team-a:key1".to_string(),
+        value: "value1".to_string(),
+        ..Default::default()
+    };
+    client.insert_value(request).await.unwrap();
+
+    let request = InsertValueRequest {
+        key: "team-b:key1".to_string(),
+        value: "value1".to_string(),
+        ..Default::default()
+    };
+    let status = client.insert_value(request).await.unwrap_err();
+
+    assert_eq!(Code::PermissionDenied, status.code());
+}
+
+#[tokio::test]
+async fn clients_without_trusted_certificate_should_be_rejected() {
+    let address = spawn_backend_with_mtls().await;
+
+    for identity in [None, Some(("rogue-client.pem", "rogue-client-key.pem"))] {
+        let result = match connect_with_identity(&address, identity).await {
+            Ok(channel) => KvClient::new(channel)
+                .get_value(GetValueRequest {
+                    key: "team-a:key1".to_string(),
+                })
+                .await
+                .map(|_| ()),
+            Err(_) => continue,
+        };
+
+        assert!(
+            result.is_err(),
+            "Client with identity {:?} should be rejected.",
+            identity
+        );
+    }
+}
+
+async fn spawn_backend_with_mtls() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut settings = Settings::default();
+    settings.auth.policy_file = Some("tests/fixtures/policy.yml".to_string());
+    settings.tls = tls_settings();
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &settings).await.unwrap();
+    });
+
+    format!("https://{}", addr)
+}
+
+async fn connect_with_identity(
+    address: &str,
+    identity: Option<(&str, &str)>,
+) -> Result<Channel, tonic::transport::Error> {
+    let mut tls = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(fixture("server.pem")))
+        .domain_name("localhost");
+
+    if let Some((cert, key)) = identity {
+        tls = tls.identity(Identity::from_pem(fixture(cert), fixture(key)));
+    }
+
+    Channel::from_shared(address.to_string())
+        .unwrap()
+        .tls_config(tls)?
+        .connect()
+        .await
+}
+
+fn tls_settings() -> Tls {
+    Tls {
+        enabled: true,
+        cert_file: "tests/fixtures/server.pem".to_string(),
+        key_file: "tests/fixtures/server-key.pem".to_string(),
+        client_ca_file: Some("tests/fixtures/ca.pem".to_string()),
+        ..Tls::default()
+    }
+}
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("tests/fixtures/{}", name)).unwrap()
+}
+
+#[tokio::test]
+async fn tls_should_enforce_configured_minimum_version() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let settings = Settings {
+        tls: Tls {
+            min_version: TlsVersion::Tls13,
+            cipher_suites: vec!["TLS13_AES_256_GCM_SHA384".to_string()],
+            ..tls_settings()
+        },
+        ..Settings::default()
+    };
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &settings).await.unwrap();
+    });
+
+    let output = tokio::process::Command::new("openssl")
+        .args(["s_client", "-tls1_2", "-connect"])
+        .arg(addr.to_string())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await;
+
+    // The check needs the openssl binary, skip it where that's missing.
+    let Ok(output) = output else { return };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        !output.status.success() || stdout.contains("Cipher is (NONE)"),
+        "TLS 1.2 handshake should be refused."
+    );
+
+    let mut client = KvClient::new(
+        connect_with_identity(
+            &format!("https://{}", addr),
+            Some(("client.pem", "client-key.pem")),
+        )
+        .await
+        .expect("TLS 1.3 client should connect."),
+    );
+    let status = client
+        .get_value(GetValueRequest {
+            key: "key1".to_string(),
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(Code::NotFound, status.code());
+}
+
+#[tokio::test]
+async fn tls_listener_should_stop_accepting_on_shutdown() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let store = CertificateStore::load(&tls_settings()).unwrap();
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+    let mut incoming = tls::incoming(listener, store, async {
+        let _ = stopped.await;
+    });
+    stop.send(()).unwrap();
+
+    assert!(incoming.next().await.is_none());
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn rotated_certificate_should_be_served_to_new_connections() {
+    let directory = std::env::temp_dir().join(format!("kv-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let cert_file = directory.join("cert.pem");
+    let key_file = directory.join("key.pem");
+    std::fs::write(&cert_file, fixture("server.pem")).unwrap();
+    std::fs::write(&key_file, fixture("server-key.pem")).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("https://{}", listener.local_addr().unwrap());
+
+    let settings = Settings {
+        tls: Tls {
+            cert_file: cert_file.to_string_lossy().to_string(),
+            key_file: key_file.to_string_lossy().to_string(),
+            reload_interval_secs: 1,
+            ..tls_settings()
+        },
+        ..Settings::default()
+    };
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &settings).await.unwrap();
+    });
+
+    let mut established = KvClient::new(
+        connect_with_identity(&address, Some(("client.pem", "client-key.pem")))
+            .await
+            .unwrap(),
+    );
+
+    std::fs::write(&key_file, fixture("server-rotated-key.pem")).unwrap();
+    std::fs::write(&cert_file, fixture("server-rotated.pem")).unwrap();
+
+    let mut rotated = None;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let tls = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(fixture("server-rotated.pem")))
+            .domain_name("localhost")
+            .identity(Identity::from_pem(
+                fixture("client.pem"),
+                fixture("client-key.pem"),
+            ));
+
+        if let Ok(channel) = Channel::from_shared(address.clone())
+            .unwrap()
+            .tls_config(tls)
+            .unwrap()
+            .connect()
+            .await
+        {
+            rotated = Some(KvClient::new(channel));
+            break;
+        }
+    }
+
+    let mut rotated = rotated.expect("New connections should be served the rotated certificate.");
+
+    for client in [&mut established, &mut rotated] {
+        let status = client
+            .get_value(GetValueRequest {
+                key: "key1".to_string(),
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(Code::NotFound, status.code());
+    }
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn mutations_should_be_recorded_in_audit_log() {
+    let directory = temp_directory();
+    let mut settings = policy_settings();
+    settings.audit = Audit {
+        enabled: true,
+        path: directory.join("audit.jsonl").display().to_string(),
+        ..Audit::default()
+    };
+    let channel = spawn_backend(settings).await;
+    let mut client = KvClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+
+    for value in ["value1", "value2"] {
+        let request = InsertValueRequest {
+            key: "team-a:key1".to_string(),
+            value: value.to_string(),
+            ..Default::default()
+        };
+        client
+            .insert_value(with_request_id(authorized(request, Some("batch-key"))))
+            .await
+            .unwrap();
+    }
+    let request = DeleteValueRequest {
+        key: "team-a:key1".to_string(),
+    };
+    client
+        .delete_value(with_request_id(authorized(request, Some("batch-key"))))
+        .await
+        .unwrap();
+
+    let request = QueryAuditRequest {
+        key: "team-a:key1".to_string(),
+        ..Default::default()
+    };
+    let entries = admin
+        .query_audit(authorized(request, Some("frontend-key")))
+        .await
+        .unwrap()
+        .into_inner()
+        .entries;
+
+    let versions: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.action.as_str(), entry.old_version, entry.new_version))
+        .collect();
+    assert_eq!(
+        vec![
+            ("insert", None, Some(1)),
+            ("insert", Some(1), Some(2)),
+            ("delete", Some(2), None),
+        ],
+        versions
+    );
+    assert!(entries
+        .iter()
+        .all(|entry| entry.principal == "batch-job" && entry.request_id == "request-1"));
+
+    let request = QueryAuditRequest {
+        from: "2999-01-01T00:00:00Z".to_string(),
+        ..Default::default()
+    };
+    let entries = admin
+        .query_audit(authorized(request, Some("frontend-key")))
+        .await
+        .unwrap()
+        .into_inner()
+        .entries;
+    assert!(entries.is_empty());
+
+    let request = QueryAuditRequest {
+        key: "team-a:key1".to_string(),
+        ..Default::default()
+    };
+    let status = admin
+        .query_audit(authorized(request, Some("batch-key")))
+        .await
+        .unwrap_err();
+    assert_eq!(Code::PermissionDenied, status.code());
+}
+
+#[tokio::test]
+async fn audit_log_should_rotate_and_drop_oldest_files() {
+    let directory = temp_directory();
+    let path = directory.join("audit.jsonl");
+    let audit = AuditLog::open(&Audit {
+        enabled: true,
+        path: path.display().to_string(),
+        max_bytes: 1,
+        max_files: 2,
+    })
+    .unwrap();
+
+    for index in 0..4 {
+        audit
+            .record(&AuditEntry {
+                timestamp: time::OffsetDateTime::now_utc(),
+                principal: "batch-job".to_string(),
+                action: Action::Insert,
+                key: format!("key{}", index),
+                old_version: None,
+                new_version: Some(1),
+                request_id: format!("request-{}", index),
+            })
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+    }
+
+    assert!(directory.join("audit.jsonl.2").exists());
+    assert!(!directory.join("audit.jsonl.3").exists());
+
+    let keys: Vec<_> = audit
+        .query(AuditQuery::default())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.key)
+        .collect();
+    assert_eq!(vec!["key1", "key2", "key3"], keys);
+
+    let keys: Vec<_> = audit
+        .query(AuditQuery {
+            limit: Some(1),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.key)
+        .collect();
+    assert_eq!(vec!["key3"], keys);
+}
+
+fn with_request_id<T>(mut request: Request<T>) -> Request<T> {
+    request
+        .metadata_mut()
+        .insert("x-request-id", MetadataValue::from_static("request-1"));
+    request
+}
+
+fn with_idempotency_key<T>(message: T, key: &'static str) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert("idempotency-key", MetadataValue::from_static(key));
+    request
+}
+
+fn temp_directory() -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("backend-audit-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[tokio::test]
+async fn inserts_over_namespace_quota_should_be_rejected() {
+    let mut settings = policy_settings();
+    settings.quotas.insert(
+        "team-a".to_string(),
+        Quota {
+            max_keys: Some(2),
+            max_value_bytes: Some(8),
+            ..Quota::default()
+        },
+    );
+    let channel = spawn_backend(settings).await;
+    let mut client = KvClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+
+    let insert = |key: &str, value: &str| {
+        authorized(
+            InsertValueRequest {
+                key: key.to_string(),
+                value: value.to_string(),
+                ..Default::default()
+            },
+            Some("batch-key"),
+        )
+    };
+
+    for (key, value) in [
+        ("team-a:key1", "value1"),
+        ("team-a:key2", "value2"),
+        ("team-a:key2", "value3"),
+    ] {
+        client.insert_value(insert(key, value)).await.unwrap();
+    }
+
+    for (key, value) in [("team-a:key3", "value1"), ("team-a:key1", "too-large")] {
+        let status = client.insert_value(insert(key, value)).await.unwrap_err();
+
+        assert_eq!(Code::ResourceExhausted, status.code());
+        let violations = status.get_details_quota_failure().unwrap().violations;
+        assert_eq!("namespace:team-a", violations[0].subject);
+    }
+
+    let usage = admin
+        .get_quota_usage(authorized(
+            GetQuotaUsageRequest {
+                namespace: "team-a".to_string(),
+            },
+            Some("frontend-key"),
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(2, usage.keys);
+    assert_eq!(34, usage.bytes);
+    assert_eq!(Some(2), usage.quota.unwrap().max_keys);
+
+    let set_quota = |api_key| {
+        authorized(
+            SetQuotaRequest {
+                namespace: "team-a".to_string(),
+                quota: Some(Quota::default().into()),
+            },
+            Some(api_key),
+        )
+    };
+    let status = admin.set_quota(set_quota("batch-key")).await.unwrap_err();
+    assert_eq!(Code::PermissionDenied, status.code());
+
+    admin.set_quota(set_quota("frontend-key")).await.unwrap();
+    client
+        .insert_value(insert("team-a:key3", "value1"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn full_store_without_eviction_should_reject_inserts() {
+    let (mut client, _) = spawn_cache(EvictionPolicy::None).await;
+
+    for key in ["key1", "key2", "key1"] {
+        client.insert_value(cache_insert(key, 0)).await.unwrap();
+    }
+
+    let status = client
+        .insert_value(cache_insert("key3", 0))
+        .await
+        .unwrap_err();
+
+    assert_eq!(Code::ResourceExhausted, status.code());
+}
+
+#[tokio::test]
+async fn full_store_should_evict_by_configured_policy() {
+    for (policy, evicted) in [
+        (EvictionPolicy::AllkeysLru, "key2"),
+        (EvictionPolicy::AllkeysLfu, "key1"),
+    ] {
+        let (mut client, metrics) = spawn_cache(policy).await;
+
+        client.insert_value(cache_insert("key1", 0)).await.unwrap();
+        client.insert_value(cache_insert("key2", 0)).await.unwrap();
+        for key in ["key2", "key2", "key1"] {
+            client.get_value(cache_get(key)).await.unwrap();
+        }
+        client.insert_value(cache_insert("key3", 0)).await.unwrap();
+
+        let status = client.get_value(cache_get(evicted)).await.unwrap_err();
+        assert_eq!(Code::NotFound, status.code(), "Policy: {:?}", policy);
+        assert!(metrics.encode().contains(&format!(
+            r#"kv_evictions_total{{policy="{}"}} 1"#,
+            policy.as_str()
+        )));
+    }
+}
+
+#[tokio::test]
+async fn volatile_ttl_should_evict_keys_expiring_first() {
+    let (mut client, _) = spawn_cache(EvictionPolicy::VolatileTtl).await;
+
+    client.insert_value(cache_insert("key1", 60)).await.unwrap();
+    client.insert_value(cache_insert("key2", 30)).await.unwrap();
+    client.insert_value(cache_insert("key3", 0)).await.unwrap();
+
+    let status = client.get_value(cache_get("key2")).await.unwrap_err();
+    assert_eq!(Code::NotFound, status.code());
+
+    client.insert_value(cache_insert("key4", 0)).await.unwrap();
+
+    let status = client
+        .insert_value(cache_insert("key5", 0))
+        .await
+        .unwrap_err();
+    assert_eq!(Code::ResourceExhausted, status.code());
+}
+
+#[tokio::test]
+async fn expired_keys_should_not_be_served() {
+    let (mut client, _) = spawn_cache(EvictionPolicy::None).await;
+
+    client.insert_value(cache_insert("key1", 1)).await.unwrap();
+    client.get_value(cache_get("key1")).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let status = client.get_value(cache_get("key1")).await.unwrap_err();
+    assert_eq!(Code::NotFound, status.code());
+}
+
+// Every entry is 10 bytes, the store has room for two of them.
+async fn spawn_cache(eviction_policy: EvictionPolicy) -> (KvClient<Channel>, Metrics) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let service = BackendService::new().with_memory_limit(&Memory {
+        max_bytes: Some(20),
+        eviction_policy,
+    });
+    let metrics = service.metrics();
+
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(KvServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    let client = KvClient::connect(format!("http://{}", addr)).await.unwrap();
+
+    (client, metrics)
+}
+
+fn cache_insert(key: &str, ttl_seconds: u64) -> InsertValueRequest {
+    InsertValueRequest {
+        key: key.to_string(),
+        value: "value1".to_string(),
+        ttl_seconds,
+    }
+}
+
+fn cache_get(key: &str) -> GetValueRequest {
+    GetValueRequest {
+        key: key.to_string(),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_writers_should_keep_store_consistent() {
+    let store = Arc::new(Store::with_shards(4));
+    store.set_quota("shared", Quota::default());
+
+    let writers: Vec<_> = (0..8)
+        .map(|writer| {
+            let store = store.clone();
+
+            tokio::spawn(async move {
+                let mut versions = Vec::new();
+
+                for index in 0..500 {
+                    let own = format!("writer-{}:key-{}", writer, index);
+                    store
+                        .insert(own.clone(), "v".to_string(), None, |_, _, _| Ok(()))
+                        .await
+                        .unwrap();
+                    assert_eq!(
+                        Some("v".to_string()),
+                        store.get(&own).map(|(value, _)| value)
+                    );
+
+                    store
+                        .insert(
+                            "shared:key".to_string(),
+                            "v".to_string(),
+                            None,
+                            |_, _, version| {
+                                versions.push(version);
+                                Ok(())
+                            },
+                        )
+                        .await
+                        .unwrap();
+                }
+
+                versions
+            })
+        })
+        .collect();
+
+    let mut versions = Vec::new();
+    for writer in writers {
+        versions.extend(writer.await.unwrap());
+    }
+    versions.sort();
+
+    // Every write to the shared key saw the previous one.
+    assert_eq!((1..=4000).collect::<Vec<u64>>(), versions);
+    assert_eq!(4001, store.len());
+    let own_bytes: usize = (0..8)
+        .flat_map(|writer| (0..500).map(move |index| format!("writer-{}:key-{}v", writer, index)))
+        .map(|entry| entry.len())
+        .sum();
+    assert_eq!((own_bytes + "shared:keyv".len()) as u64, store.bytes());
+    assert_eq!(1, store.quota_usage("shared").1.keys);
+}
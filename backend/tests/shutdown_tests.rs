@@ -0,0 +1,112 @@
+// Signals reach the whole test process, so shutdown tests live in their own
+// test binary where no other servers run.
+use backend::{
+    backend_server::{
+        admin_client::AdminClient, kv_client::KvClient, GetValueRequest, ImportChunk,
+    },
+    config::{Settings, Shutdown, Wal},
+};
+use std::{process::Command, time::Duration};
+use tokio::{net::TcpListener, sync::mpsc, time::sleep};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Channel;
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+
+#[tokio::test]
+async fn sigterm_should_drain_in_flight_calls_and_flush_before_exiting() {
+    let directory = std::env::temp_dir().join(format!("backend-shutdown-{}", uuid::Uuid::new_v4()));
+    let wal = directory.join("wal.jsonl");
+    let settings = Settings {
+        wal: Wal {
+            enabled: true,
+            path: wal.display().to_string(),
+            ..Wal::default()
+        },
+        shutdown: Shutdown {
+            drain_delay_secs: 1,
+            timeout_secs: 10,
+        },
+        ..Settings::default()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        backend::run(listener, None, &settings)
+            .await
+            .map_err(|e| e.to_string())
+    });
+
+    let channel = Channel::from_shared(url.clone())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let (chunks, receiver) = mpsc::channel(4);
+    let line = |key: &str| format!("{{\"key\": \"{}\", \"value\": \"value\"}}\n", key);
+    chunks
+        .send(ImportChunk {
+            data: line("key1").into_bytes(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let mut admin = AdminClient::new(channel.clone());
+    let import = tokio::spawn(async move { admin.import(ReceiverStream::new(receiver)).await });
+    sleep(Duration::from_millis(200)).await;
+
+    terminate();
+    sleep(Duration::from_millis(200)).await;
+
+    // During the drain delay the service reports not serving but still
+    // answers.
+    let request = HealthCheckRequest {
+        service: "kv.KV".to_string(),
+    };
+    let status = HealthClient::new(channel.clone())
+        .check(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .status;
+    assert_eq!(ServingStatus::NotServing as i32, status);
+    let request = GetValueRequest {
+        key: "key1".to_string(),
+    };
+    KvClient::new(channel).get_value(request).await.unwrap();
+
+    // Afterwards new connections are refused while the import goes on.
+    sleep(Duration::from_millis(1200)).await;
+    let connected = Channel::from_shared(url).unwrap().connect().await;
+    assert!(connected.is_err());
+
+    chunks
+        .send(ImportChunk {
+            data: line("key2").into_bytes(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    drop(chunks);
+    let response = import.await.unwrap().unwrap().into_inner();
+    assert_eq!((2, 0), (response.imported, response.failed));
+
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("Backend should exit after draining.")
+        .unwrap()
+        .unwrap();
+    let records = std::fs::read_to_string(&wal).unwrap();
+    assert_eq!(2, records.lines().count());
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+fn terminate() {
+    let status = Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE},
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+pub use kv_common::auth::hash_api_key;
+use tracing::{info, warn};
+
+use crate::config;
+use crate::jwt::JwtValidator;
+
+const ANONYMOUS: &str = "anonymous";
+const NAMESPACE_SEPARATOR: char = ':';
+const ANY_NAMESPACE: &str = "*";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Principal {
+    pub name: String,
+    pub namespaces: Option<Vec<String>>,
+    pub roles: Vec<String>,
+}
+
+impl Principal {
+    pub fn anonymous() -> Self {
+        Principal {
+            name: ANONYMOUS.to_string(),
+            namespaces: None,
+            roles: Vec::new(),
+        }
+    }
+
+    pub fn permits(&self, key: &str) -> bool {
+        match &self.namespaces {
+            None => true,
+            Some(namespaces) => {
+                let namespace = namespace_of(key);
+                namespaces
+                    .iter()
+                    .any(|permitted| permitted == ANY_NAMESPACE || permitted == namespace)
+            }
+        }
+    }
+}
+
+pub fn namespace_of(key: &str) -> &str {
+    key.split_once(NAMESPACE_SEPARATOR)
+        .map(|(namespace, _)| namespace)
+        .unwrap_or_default()
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl FromRequest for Principal {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let principal = request
+            .extensions()
+            .get::<Principal>()
+            .cloned()
+            .unwrap_or_else(Principal::anonymous);
+
+        ready(Ok(principal))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthError {
+    MissingCredentials,
+    InvalidCredentials,
+}
+
+pub struct Authenticator {
+    enabled: bool,
+    api_keys: HashMap<String, String>,
+    jwt: Option<JwtValidator>,
+}
+
+impl Authenticator {
+    pub async fn new(settings: &config::Auth) -> Result<Self, std::io::Error> {
+        if !settings.enabled {
+            warn!("Authentication is disabled, all requests are served as anonymous.");
+        }
+
+        let api_keys = settings
+            .api_keys
+            .iter()
+            .map(|api_key| (api_key.key_hash.to_lowercase(), api_key.name.clone()))
+            .collect();
+
+        let jwt = match &settings.jwt {
+            Some(jwt) => Some(JwtValidator::new(jwt).await?),
+            None => None,
+        };
+
+        Ok(Authenticator {
+            enabled: settings.enabled,
+            api_keys,
+            jwt,
+        })
+    }
+
+    pub async fn authenticate(&self, header: Option<&HeaderValue>) -> Result<Principal, AuthError> {
+        if !self.enabled {
+            return Ok(Principal::anonymous());
+        }
+
+        let token = header
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or(AuthError::MissingCredentials)?;
+
+        if let Some(jwt) = &self.jwt {
+            if is_jwt(token) {
+                return jwt.validate(token).await;
+            }
+        }
+
+        match self.api_keys.get(&hash_api_key(token)) {
+            Some(name) => Ok(Principal {
+                name: name.clone(),
+                namespaces: None,
+                roles: Vec::new(),
+            }),
+            None => Err(AuthError::InvalidCredentials),
+        }
+    }
+}
+
+fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+pub struct Authentication {
+    authenticator: Arc<Authenticator>,
+}
+
+impl Authentication {
+    pub fn new(authenticator: Arc<Authenticator>) -> Self {
+        Authentication { authenticator }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+            authenticator: self.authenticator.clone(),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+    authenticator: Arc<Authenticator>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let authenticator = self.authenticator.clone();
+
+        Box::pin(async move {
+            let principal = authenticator
+                .authenticate(request.headers().get(AUTHORIZATION))
+                .await;
+
+            match principal {
+                Ok(principal) => {
+                    info!("Request authenticated as: {}", &principal);
+                    request.extensions_mut().insert(principal);
+
+                    let response = service.call(request).await?;
+                    Ok(response.map_into_left_body())
+                }
+                Err(error) => {
+                    warn!("Authentication failed: {:?}", &error);
+
+                    let response = match error {
+                        AuthError::MissingCredentials => HttpResponse::Unauthorized()
+                            .insert_header((WWW_AUTHENTICATE, "Bearer"))
+                            .body("Missing bearer token."),
+                        AuthError::InvalidCredentials => HttpResponse::Unauthorized()
+                            .insert_header((WWW_AUTHENTICATE, r#"Bearer error="invalid_token""#))
+                            .body("Invalid bearer token."),
+                    };
+
+                    Ok(request.into_response(response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    future::ready,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use prometheus::{core::Collector, IntCounter, IntGauge, Opts};
+use tonic::{
+    codegen::{http, BoxFuture, Service, StdError},
+    metadata::{MetadataMap, MetadataValue},
+    Code, Status,
+};
+use tracing::{info, warn};
+
+use crate::config;
+use crate::health::is_backend_failure;
+
+const CIRCUIT_OPEN_METADATA: &str = "x-kv-circuit-open";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl State {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Closed => "closed",
+            State::Open => "open",
+            State::HalfOpen => "half_open",
+        }
+    }
+
+    // Value of the state gauge.
+    fn value(&self) -> i64 {
+        match self {
+            State::Closed => 0,
+            State::HalfOpen => 1,
+            State::Open => 2,
+        }
+    }
+}
+
+struct Inner {
+    settings: config::CircuitBreaker,
+    state: State,
+    failures: u32,
+    opened_at: Instant,
+}
+
+// Opens after `failure_threshold` failed calls in a row to one backend, and
+// fails calls fast while open. After `open_ms` a single call goes through as
+// a probe, which closes the breaker again or keeps it open for another
+// `open_ms`.
+pub struct CircuitBreaker {
+    name: String,
+    inner: Mutex<Inner>,
+    state: IntGauge,
+    rejected: IntCounter,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &str, settings: &config::CircuitBreaker) -> Self {
+        let state = IntGauge::with_opts(
+            Opts::new(
+                "backend_circuit_state",
+                "State of the circuit breaker, 0 closed, 1 half open and 2 open.",
+            )
+            .const_label("backend", name),
+        )
+        .expect("Metric should be valid.");
+        let rejected = IntCounter::with_opts(
+            Opts::new(
+                "backend_circuit_rejected_total",
+                "Number of backend calls failed fast by an open circuit breaker.",
+            )
+            .const_label("backend", name),
+        )
+        .expect("Metric should be valid.");
+
+        CircuitBreaker {
+            name: name.to_string(),
+            inner: Mutex::new(Inner {
+                settings: settings.clone(),
+                state: State::Closed,
+                failures: 0,
+                opened_at: Instant::now(),
+            }),
+            state,
+            rejected,
+        }
+    }
+
+    pub fn configure(&self, settings: &config::CircuitBreaker) {
+        self.lock().settings = settings.clone();
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> State {
+        self.lock().state
+    }
+
+    // Open and still within `open_ms`. A breaker past it lets the next call
+    // through as a probe, so it doesn't hold traffic off any longer.
+    pub fn is_rejecting(&self) -> bool {
+        let inner = self.lock();
+        inner.settings.enabled
+            && inner.state != State::Closed
+            && inner.opened_at.elapsed() < Duration::from_millis(inner.settings.open_ms)
+    }
+
+    pub fn collectors(&self) -> Vec<Box<dyn Collector>> {
+        vec![
+            Box::new(self.state.clone()),
+            Box::new(self.rejected.clone()),
+        ]
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .expect("Circuit breaker lock should not be poisoned.")
+    }
+
+    fn set_state(&self, inner: &mut Inner, state: State) {
+        inner.state = state;
+        self.state.set(state.value());
+    }
+
+    // Returns how long the breaker stays open when the call should fail fast.
+    fn allow(&self) -> Result<(), Duration> {
+        let mut inner = self.lock();
+        if !inner.settings.enabled || inner.state == State::Closed {
+            return Ok(());
+        }
+
+        let open = Duration::from_millis(inner.settings.open_ms);
+        let elapsed = inner.opened_at.elapsed();
+        if elapsed < open {
+            self.rejected.inc();
+            return Err(open - elapsed);
+        }
+
+        info!("Probing backend {} through half open circuit.", self.name);
+        inner.opened_at = Instant::now();
+        self.set_state(&mut inner, State::HalfOpen);
+        Ok(())
+    }
+
+    fn record(&self, success: bool) {
+        let mut inner = self.lock();
+        if !inner.settings.enabled {
+            return;
+        }
+
+        if success {
+            if inner.state != State::Closed {
+                info!("Backend {} recovered, closing circuit.", self.name);
+                self.set_state(&mut inner, State::Closed);
+            }
+            inner.failures = 0;
+            return;
+        }
+
+        inner.failures = inner.failures.saturating_add(1);
+        let tripped = inner.state == State::HalfOpen
+            || (inner.state == State::Closed && inner.failures >= inner.settings.failure_threshold);
+        if tripped {
+            warn!(
+                "Opening circuit to backend {} after {} failed calls.",
+                self.name, inner.failures
+            );
+            inner.opened_at = Instant::now();
+            self.set_state(&mut inner, State::Open);
+        }
+    }
+}
+
+pub fn is_circuit_open(status: &Status) -> bool {
+    status.metadata().contains_key(CIRCUIT_OPEN_METADATA)
+}
+
+// Wraps a channel so every call goes through the backend's circuit breaker.
+// Calls failing at the transport or with a status counted as a backend
+// failure count against it.
+#[derive(Clone)]
+pub struct Breaker<S> {
+    inner: S,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<S> Breaker<S> {
+    pub fn new(inner: S, breaker: Arc<CircuitBreaker>) -> Self {
+        Breaker { inner, breaker }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Breaker<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Error: Into<StdError>,
+    S::Future: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = http::Response<ResBody>;
+    type Error = StdError;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        if let Err(wait) = self.breaker.allow() {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(CIRCUIT_OPEN_METADATA, MetadataValue::from_static("true"));
+            let status = Status::with_metadata(
+                Code::Unavailable,
+                format!(
+                    "Circuit to backend {} is open for another {:?}.",
+                    self.breaker.name, wait
+                ),
+                metadata,
+            );
+
+            return Box::pin(ready(Err(Box::new(status) as StdError)));
+        }
+
+        let breaker = self.breaker.clone();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await.map_err(Into::into);
+
+            // Errors returned by the backend come back as trailers only, with
+            // the status in the headers.
+            let success = match &response {
+                Ok(response) => response
+                    .headers()
+                    .get("grpc-status")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<i32>().ok())
+                    .is_none_or(|code| !is_backend_failure(Code::from(code))),
+                Err(_) => false,
+            };
+            breaker.record(success);
+
+            response
+        })
+    }
+}
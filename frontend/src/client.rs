@@ -1,54 +1,325 @@
-use std::{io::ErrorKind, time::Duration};
+use std::{
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use frontend::backend_server::kv_client::KvClient;
-use tokio::time::sleep;
-use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
-use tracing::{error, info, warn};
+use tonic::{
+    metadata::MetadataValue,
+    transport::{Channel, ClientTlsConfig, Endpoint},
+    Request, Response, Status,
+};
+use tonic_health::pb::health_client::HealthClient;
+use tracing::{info, warn};
+use tracing_actix_web::RequestId;
 
-pub async fn get_client(address: String) -> Result<KvClient<Channel>, std::io::Error> {
-    let pem = std::fs::read_to_string("cert2.pem").expect("cert2.pem should exist.");
-    let ca = Certificate::from_pem(pem);
+use crate::auth::Principal;
+use crate::backend_server::admin_client::AdminClient;
+use crate::backend_server::kv_client::KvClient;
+use crate::backend_server::{GetValueRequest, GetValueResponse};
+use crate::breaker::{Breaker, CircuitBreaker};
+use crate::config::{self, ReadBalance};
+use crate::telemetry::with_trace_context;
+use crate::timeout::Deadline;
+use crate::tls;
 
-    let tls = ClientTlsConfig::new()
-        .ca_certificate(ca)
-        .domain_name("localhost");
+const PRINCIPAL_METADATA: &str = "x-kv-principal";
+const ROLES_METADATA: &str = "x-kv-roles";
+const REQUEST_ID_METADATA: &str = "x-request-id";
+// Also bounds reconnects, a backend that doesn't answer fails the call.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
 
-    info!("Connecting to grpc server with address: {}", address);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consistency {
+    Strong,
+    Eventual,
+}
+
+// Calls to a backend go through its circuit breaker, health checks don't.
+pub type BackendChannel = Breaker<Channel>;
+
+#[derive(Clone)]
+pub struct KvClients {
+    primary: KvClient<BackendChannel>,
+    admin: AdminClient<BackendChannel>,
+    health: HealthClient<Channel>,
+    breaker: Arc<CircuitBreaker>,
+    breaker_settings: config::CircuitBreaker,
+    retry: config::Retry,
+    replicas: Arc<Vec<Replica>>,
+    read_balance: ReadBalance,
+    next_replica: Arc<AtomicUsize>,
+    api_key: Option<Arc<str>>,
+}
+
+struct Replica {
+    client: KvClient<BackendChannel>,
+    breaker: Arc<CircuitBreaker>,
+    outstanding: Arc<AtomicUsize>,
+}
+
+pub struct ReadClient {
+    client: KvClient<BackendChannel>,
+    _outstanding: Option<OutstandingGuard>,
+}
 
-    let channel = Channel::from_shared(address)
-        .unwrap()
-        .tls_config(tls)
-        .unwrap();
+impl ReadClient {
+    // Takes the client along so the read counts as outstanding until it's
+    // answered.
+    pub async fn get_value(
+        mut self,
+        request: Request<GetValueRequest>,
+    ) -> Result<Response<GetValueResponse>, Status> {
+        self.client.get_value(request).await
+    }
+}
 
-    let kv_client = try_connect(channel)
-        .await
-        .map_err(|e| std::io::Error::new(ErrorKind::ConnectionRefused, e.to_string()))?;
+struct OutstandingGuard(Arc<AtomicUsize>);
 
-    Ok(kv_client)
+impl Drop for OutstandingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
-async fn try_connect(channel: Endpoint) -> Result<KvClient<Channel>, tonic::transport::Error> {
-    let mut attempt = 0;
-    let max_attempts = 5;
-    let base_delay = 500;
+impl KvClients {
+    pub fn new(primary: Channel) -> Self {
+        let breaker_settings = config::CircuitBreaker::default();
+        let breaker = Arc::new(CircuitBreaker::new("primary", &breaker_settings));
+        let channel = Breaker::new(primary.clone(), breaker.clone());
+
+        KvClients {
+            primary: KvClient::new(channel.clone()),
+            admin: AdminClient::new(channel),
+            health: HealthClient::new(primary),
+            breaker,
+            breaker_settings,
+            retry: config::Retry::default(),
+            replicas: Arc::new(Vec::new()),
+            read_balance: ReadBalance::default(),
+            next_replica: Arc::new(AtomicUsize::new(0)),
+            api_key: None,
+        }
+    }
+
+    pub fn with_api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key.map(Arc::from);
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, settings: &config::CircuitBreaker) -> Self {
+        for breaker in self.breakers() {
+            breaker.configure(settings);
+        }
+
+        self.breaker_settings = settings.clone();
+        self
+    }
+
+    pub fn with_retry(mut self, settings: &config::Retry) -> Self {
+        self.retry = settings.clone();
+        self
+    }
+
+    pub fn with_replicas(mut self, replicas: Vec<Channel>, read_balance: ReadBalance) -> Self {
+        let replicas = replicas
+            .into_iter()
+            .enumerate()
+            .map(|(index, channel)| {
+                let name = format!("replica-{}", index);
+                let breaker = Arc::new(CircuitBreaker::new(&name, &self.breaker_settings));
+
+                Replica {
+                    client: KvClient::new(Breaker::new(channel, breaker.clone())),
+                    breaker,
+                    outstanding: Arc::new(AtomicUsize::new(0)),
+                }
+            })
+            .collect();
+
+        self.replicas = Arc::new(replicas);
+        self.read_balance = read_balance;
+        self
+    }
+
+    pub fn primary(&self) -> KvClient<BackendChannel> {
+        self.primary.clone()
+    }
+
+    pub fn admin(&self) -> AdminClient<BackendChannel> {
+        self.admin.clone()
+    }
+
+    pub fn health(&self) -> HealthClient<Channel> {
+        self.health.clone()
+    }
+
+    pub fn retry(&self) -> &config::Retry {
+        &self.retry
+    }
 
-    loop {
-        match channel.connect().await {
-            Ok(channel) => {
-                info!("Connection estabilished.");
-                return Ok(KvClient::new(channel));
+    // The primary's breaker comes first.
+    pub fn breakers(&self) -> Vec<Arc<CircuitBreaker>> {
+        std::iter::once(self.breaker.clone())
+            .chain(self.replicas.iter().map(|replica| replica.breaker.clone()))
+            .collect()
+    }
+
+    pub fn request<T>(
+        &self,
+        message: T,
+        principal: &Principal,
+        request_id: &RequestId,
+        deadline: Deadline,
+    ) -> Request<T> {
+        let mut request = with_trace_context(message);
+
+        // Sent as grpc-timeout, so the backend gives up on the call when the
+        // HTTP request does.
+        if let Some(remaining) = deadline.remaining() {
+            request.set_timeout(remaining);
+        }
+
+        let metadata = request.metadata_mut();
+
+        if let Ok(value) = MetadataValue::try_from(request_id.to_string()) {
+            metadata.insert(REQUEST_ID_METADATA, value);
+        }
+
+        if let Some(api_key) = &self.api_key {
+            match MetadataValue::try_from(format!("Bearer {}", api_key)) {
+                Ok(value) => {
+                    metadata.insert("authorization", value);
+                }
+                Err(_) => warn!("Backend api key is not valid metadata."),
             }
-            Err(_) if attempt < max_attempts => {
-                let delay = Duration::from_millis(base_delay * 2_u64.pow(attempt));
-                warn!("Failed to connect to backend. Retry in {:?}...", delay);
+        }
 
-                sleep(delay).await;
-                attempt += 1;
+        match (
+            MetadataValue::try_from(principal.name.as_str()),
+            MetadataValue::try_from(principal.roles.join(",")),
+        ) {
+            (Ok(name), Ok(roles)) => {
+                metadata.insert(PRINCIPAL_METADATA, name);
+                metadata.insert(ROLES_METADATA, roles);
             }
-            Err(e) => {
-                error!("Failed to connect to grpc server. Error: {:?}", e);
-                return Err(e);
+            _ => warn!("Principal {} can't be forwarded as metadata.", principal),
+        }
+
+        request
+    }
+
+    // Replicas whose breaker is open are skipped, reads go to the primary
+    // while every replica's is.
+    pub fn reader(&self, consistency: Consistency) -> ReadClient {
+        let available = |replica: &&Replica| !replica.breaker.is_rejecting();
+        let replica = match self.read_balance {
+            _ if consistency == Consistency::Strong => None,
+            ReadBalance::RoundRobin => {
+                let next = self.next_replica.fetch_add(1, Ordering::Relaxed);
+                (0..self.replicas.len())
+                    .map(|offset| &self.replicas[(next + offset) % self.replicas.len()])
+                    .find(available)
             }
+            ReadBalance::LeastOutstanding => self
+                .replicas
+                .iter()
+                .filter(available)
+                .min_by_key(|replica| replica.outstanding.load(Ordering::Relaxed)),
+        };
+
+        let Some(replica) = replica else {
+            return ReadClient {
+                client: self.primary(),
+                _outstanding: None,
+            };
+        };
+
+        replica.outstanding.fetch_add(1, Ordering::Relaxed);
+
+        ReadClient {
+            client: replica.client.clone(),
+            _outstanding: Some(OutstandingGuard(replica.outstanding.clone())),
+        }
+    }
+}
+
+pub async fn get_clients(backend: &config::Backend) -> Result<KvClients, std::io::Error> {
+    let primary = backend.primary().ok_or_else(|| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "Backend configuration should contain a primary endpoint.",
+        )
+    })?;
+
+    let tls = if backend.tls.enabled {
+        Some(tls::client_config(&backend.tls)?)
+    } else {
+        warn!("TLS to the backend is disabled, connecting over plaintext.");
+        None
+    };
+
+    let primary = get_channel(primary.address(tls.is_some()), tls.clone()).await?;
+    // Replicas only serve eventual reads, so they're connected to on first
+    // use rather than holding up startup.
+    let replicas = backend
+        .replicas()
+        .map(|endpoint| {
+            endpoint_of(endpoint.address(tls.is_some()), tls.clone())
+                .map(|endpoint| endpoint.connect_lazy())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    info!(
+        "Connected to primary with {} replica(s), balancing reads with {:?}.",
+        replicas.len(),
+        backend.read_balance
+    );
+
+    Ok(KvClients::new(primary)
+        .with_circuit_breaker(&backend.circuit_breaker)
+        .with_retry(&backend.retry)
+        .with_replicas(replicas, backend.read_balance)
+        .with_api_key(backend.api_key.clone()))
+}
+
+pub async fn get_channel(
+    address: String,
+    tls: Option<ClientTlsConfig>,
+) -> Result<Channel, std::io::Error> {
+    info!("Connecting to grpc server with address: {}", address);
+
+    let endpoint = endpoint_of(address.clone(), tls)?;
+
+    // A backend that's still down is connected to on first use instead, and
+    // the channel reconnects by itself whenever its connection fails.
+    match endpoint.connect().await {
+        Ok(channel) => {
+            info!("Connected to {}.", address);
+            Ok(channel)
+        }
+        Err(e) => {
+            warn!(
+                "Backend {} is unreachable, connecting on first use. Error: {:?}",
+                address, e
+            );
+            Ok(endpoint.connect_lazy())
         }
     }
 }
+
+fn endpoint_of(address: String, tls: Option<ClientTlsConfig>) -> Result<Endpoint, std::io::Error> {
+    let endpoint = Channel::from_shared(address)
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e.to_string()))?
+        .connect_timeout(CONNECT_TIMEOUT);
+
+    match tls {
+        Some(tls) => endpoint
+            .tls_config(tls)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e.to_string())),
+        None => Ok(endpoint),
+    }
+}
@@ -1,22 +1,259 @@
 use config::Config;
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+use crate::tls::TlsVersion;
+
+#[derive(Deserialize, Default)]
 pub struct Settings {
     pub backend: Backend,
     pub frontend: Frontend,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub shutdown: Shutdown,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    #[serde(default)]
+    pub readiness: Readiness,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Readiness {
+    pub recent_rpc_window_ms: u64,
+    pub check_timeout_ms: u64,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness {
+            recent_rpc_window_ms: 10_000,
+            check_timeout_ms: 1_000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Shutdown {
+    pub drain_delay_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown {
+            drain_delay_secs: 0,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
 pub struct Backend {
+    pub endpoints: Vec<BackendEndpoint>,
+    #[serde(default)]
+    pub read_balance: ReadBalance,
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub tls: ClientTls,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreaker,
+    #[serde(default)]
+    pub retry: Retry,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CircuitBreaker {
+    pub enabled: bool,
+    pub failure_threshold: u32,
+    pub open_ms: u64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker {
+            enabled: false,
+            failure_threshold: 5,
+            open_ms: 5000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Retry {
+    pub enabled: bool,
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            enabled: false,
+            max_attempts: 3,
+            base_delay_ms: 50,
+            max_delay_ms: 1000,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ClientTls {
+    pub enabled: bool,
+    pub ca_file: String,
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+    pub domain_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BackendEndpoint {
     pub application_port: u16,
     pub host: String,
+    pub role: EndpointRole,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointRole {
+    Primary,
+    Replica,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadBalance {
+    #[default]
+    RoundRobin,
+    LeastOutstanding,
+}
+
+#[derive(Deserialize, Default)]
 pub struct Frontend {
     pub application_port: u16,
     pub host: String,
+    pub otlp_endpoint: Option<String>,
+    #[serde(default)]
+    pub tls: ServerTls,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ServerTls {
+    pub enabled: bool,
+    pub cert_file: String,
+    pub key_file: String,
+    pub min_version: TlsVersion,
+    pub cipher_list: Option<String>,
+    pub ciphersuites: Option<String>,
+    pub reload_interval_secs: u64,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct RateLimit {
+    pub enabled: bool,
+    pub default: Option<Limit>,
+    pub routes: Vec<RouteLimit>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Limit {
+    pub requests_per_second: f64,
+    pub burst: u32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RouteLimit {
+    pub route: String,
+    pub method: Option<String>,
+    #[serde(flatten)]
+    pub limit: Limit,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Timeouts {
+    pub default_ms: Option<u64>,
+    pub routes: Vec<RouteTimeout>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RouteTimeout {
+    pub route: String,
+    pub method: Option<String>,
+    pub timeout_ms: u64,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Auth {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    pub jwt: Option<Jwt>,
+    pub policy_file: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Jwt {
+    pub issuer: String,
+    pub audience: String,
+    pub jwks_file: Option<String>,
+    pub jwks_url: Option<String>,
+    #[serde(default = "default_principal_claim")]
+    pub principal_claim: String,
+    #[serde(default = "default_namespaces_claim")]
+    pub namespaces_claim: String,
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+}
+
+fn default_principal_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_namespaces_claim() -> String {
+    "kv_namespaces".to_string()
+}
+
+fn default_roles_claim() -> String {
+    "roles".to_string()
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ApiKey {
+    pub name: String,
+    pub key_hash: String,
+}
+
+impl Backend {
+    pub fn primary(&self) -> Option<&BackendEndpoint> {
+        self.endpoints
+            .iter()
+            .find(|endpoint| endpoint.role == EndpointRole::Primary)
+    }
+
+    pub fn replicas(&self) -> impl Iterator<Item = &BackendEndpoint> {
+        self.endpoints
+            .iter()
+            .filter(|endpoint| endpoint.role == EndpointRole::Replica)
+    }
+}
+
+impl BackendEndpoint {
+    pub fn address(&self, tls: bool) -> String {
+        let scheme = if tls { "https" } else { "http" };
+
+        format!("{}://{}:{}", scheme, self.host, self.application_port)
+    }
 }
 
 pub enum Environment {
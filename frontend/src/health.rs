@@ -0,0 +1,308 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use tokio::time::timeout;
+use tokio_stream::Empty;
+use tonic::{server::NamedService, Code, Request, Response, Status};
+use tonic_health::pb::{health_check_response::ServingStatus, HealthCheckRequest};
+use tracing::warn;
+
+use crate::backend_server::{
+    kv_server::{Kv, KvServer},
+    CompareAndSwapRequest, CompareAndSwapResponse, DeleteValueRequest, DeleteValueResponse,
+    GetValueRequest, GetValueResponse, InsertValueRequest, InsertValueResponse, ScanRequest,
+    ScanResponse, WatchEvent, WatchRequest,
+};
+use crate::client::KvClients;
+use crate::config;
+
+// The generated code only names a service for a server of it, this one is
+// never built.
+enum Unserved {}
+
+#[tonic::async_trait]
+impl Kv for Unserved {
+    type WatchStream = Empty<Result<WatchEvent, Status>>;
+
+    async fn insert_value(
+        &self,
+        _: Request<InsertValueRequest>,
+    ) -> Result<Response<InsertValueResponse>, Status> {
+        match *self {}
+    }
+
+    async fn get_value(
+        &self,
+        _: Request<GetValueRequest>,
+    ) -> Result<Response<GetValueResponse>, Status> {
+        match *self {}
+    }
+
+    async fn delete_value(
+        &self,
+        _: Request<DeleteValueRequest>,
+    ) -> Result<Response<DeleteValueResponse>, Status> {
+        match *self {}
+    }
+
+    async fn compare_and_swap(
+        &self,
+        _: Request<CompareAndSwapRequest>,
+    ) -> Result<Response<CompareAndSwapResponse>, Status> {
+        match *self {}
+    }
+
+    async fn scan(&self, _: Request<ScanRequest>) -> Result<Response<ScanResponse>, Status> {
+        match *self {}
+    }
+
+    async fn watch(&self, _: Request<WatchRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        match *self {}
+    }
+}
+
+pub struct Readiness {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    last_rpc: Mutex<Option<RpcOutcome>>,
+    recent_rpc_window: Duration,
+    check_timeout: Duration,
+}
+
+pub struct InFlight(Arc<Readiness>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RpcOutcome {
+    success: bool,
+    latency: Duration,
+    at: Instant,
+}
+
+impl Readiness {
+    pub fn new(settings: &config::Readiness) -> Self {
+        Readiness {
+            draining: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            last_rpc: Mutex::new(None),
+            recent_rpc_window: Duration::from_millis(settings.recent_rpc_window_ms),
+            check_timeout: Duration::from_millis(settings.check_timeout_ms),
+        }
+    }
+
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    // Counts the request as in flight until the guard is dropped.
+    pub fn track(self: Arc<Self>) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn record_rpc<T>(&self, result: &Result<T, Status>, latency: Duration) {
+        let success = match result {
+            Ok(_) => true,
+            Err(status) => !is_backend_failure(status.code()),
+        };
+
+        *self.last_rpc.lock().unwrap() = Some(RpcOutcome {
+            success,
+            latency,
+            at: Instant::now(),
+        });
+    }
+
+    // Outcomes expire, so a failure doesn't keep the instance unready once
+    // no more traffic comes in to replace it.
+    fn recent_rpc(&self) -> Option<RpcOutcome> {
+        self.last_rpc
+            .lock()
+            .unwrap()
+            .filter(|outcome| outcome.at.elapsed() < self.recent_rpc_window)
+    }
+}
+
+pub fn is_backend_failure(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable | Code::Unknown | Code::Internal | Code::DeadlineExceeded
+    )
+}
+
+#[derive(Serialize, Debug)]
+struct LivenessReport {
+    status: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+struct ReadinessReport {
+    status: &'static str,
+    checks: Vec<Check>,
+}
+
+#[derive(Serialize, Debug)]
+struct Check {
+    name: &'static str,
+    status: &'static str,
+    latency_ms: f64,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, passed: bool, latency: Duration, detail: String) -> Self {
+        Check {
+            name,
+            status: if passed { "pass" } else { "fail" },
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            detail,
+        }
+    }
+
+    fn passed(&self) -> bool {
+        self.status == "pass"
+    }
+}
+
+pub async fn livez() -> impl Responder {
+    HttpResponse::Ok().json(LivenessReport { status: "alive" })
+}
+
+#[tracing::instrument(skip(kv_clients, readiness))]
+pub async fn readyz(
+    kv_clients: web::Data<KvClients>,
+    readiness: web::Data<Readiness>,
+) -> impl Responder {
+    let checks = vec![
+        check_backend(&kv_clients, readiness.check_timeout).await,
+        check_recent_rpc(&readiness),
+        check_draining(&readiness),
+        check_circuit_breakers(&kv_clients),
+    ];
+
+    if checks.iter().all(Check::passed) {
+        HttpResponse::Ok().json(ReadinessReport {
+            status: "ready",
+            checks,
+        })
+    } else {
+        warn!("Readiness check failed: {:?}", &checks);
+        HttpResponse::ServiceUnavailable().json(ReadinessReport {
+            status: "not_ready",
+            checks,
+        })
+    }
+}
+
+// Connecting has its own timeout, a hung backend is cut off here.
+async fn check_backend(kv_clients: &KvClients, limit: Duration) -> Check {
+    let mut health_client = kv_clients.health();
+
+    let request = HealthCheckRequest {
+        service: KvServer::<Unserved>::NAME.to_string(),
+    };
+
+    let start = Instant::now();
+    let response = match timeout(limit, health_client.check(request)).await {
+        Ok(response) => response,
+        Err(_) => Err(Status::deadline_exceeded("Health check timed out.")),
+    };
+    let latency = start.elapsed();
+
+    match response {
+        Ok(response) => {
+            let status = ServingStatus::try_from(response.into_inner().status)
+                .unwrap_or(ServingStatus::Unknown);
+
+            Check::new(
+                "backend",
+                status == ServingStatus::Serving,
+                latency,
+                status.as_str_name().to_string(),
+            )
+        }
+        Err(status) => Check::new("backend", false, latency, status.message().to_string()),
+    }
+}
+
+fn check_recent_rpc(readiness: &Readiness) -> Check {
+    match readiness.recent_rpc() {
+        Some(outcome) => Check::new(
+            "recent_rpc",
+            outcome.success,
+            outcome.latency,
+            format!(
+                "Last backend RPC {} {:?} ago.",
+                if outcome.success {
+                    "succeeded"
+                } else {
+                    "failed"
+                },
+                outcome.at.elapsed()
+            ),
+        ),
+        None => Check::new(
+            "recent_rpc",
+            true,
+            Duration::ZERO,
+            format!(
+                "No backend RPC sent in the last {:?}.",
+                readiness.recent_rpc_window
+            ),
+        ),
+    }
+}
+
+// Only the primary's breaker fails the check, reads can still be served by
+// the primary while a replica's is open. The health probe doesn't go through
+// the breaker, so the check passes again once the breaker lets a probe call
+// through, instead of waiting for traffic that an unready instance won't get.
+fn check_circuit_breakers(kv_clients: &KvClients) -> Check {
+    let breakers = kv_clients.breakers();
+    let states: Vec<_> = breakers
+        .iter()
+        .map(|breaker| format!("{} {}", breaker.name(), breaker.state().as_str()))
+        .collect();
+
+    Check::new(
+        "circuit_breaker",
+        !breakers[0].is_rejecting(),
+        Duration::ZERO,
+        states.join(", "),
+    )
+}
+
+fn check_draining(readiness: &Readiness) -> Check {
+    let draining = readiness.is_draining();
+
+    Check::new(
+        "draining",
+        !draining,
+        Duration::ZERO,
+        if draining {
+            "Instance is draining.".to_string()
+        } else {
+            "Instance is accepting traffic.".to_string()
+        },
+    )
+}
@@ -0,0 +1,245 @@
+use std::{
+    io::ErrorKind,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde_json::{Map, Value};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+use crate::auth::{AuthError, Principal};
+use crate::config;
+
+// Keeps the principals of tokens apart from API key names, so a token for a
+// subject named like an API key doesn't get that key's roles.
+const JWT_PRINCIPAL_PREFIX: &str = "jwt:";
+// Tokens with an unknown kid refresh the keys at most this often.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+const JWKS_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum JwksSource {
+    File(String),
+    Url(String, reqwest::Client),
+}
+
+pub struct JwtValidator {
+    settings: config::Jwt,
+    source: JwksSource,
+    jwks: RwLock<JwkSet>,
+    // When the keys were last loaded, held while they are refreshed so
+    // concurrent tokens with an unknown kid share one request.
+    refreshed: Mutex<Instant>,
+}
+
+impl JwtValidator {
+    pub async fn new(settings: &config::Jwt) -> Result<Self, std::io::Error> {
+        let source = match (&settings.jwks_file, &settings.jwks_url) {
+            (Some(path), None) => JwksSource::File(path.clone()),
+            (None, Some(url)) => {
+                let client = reqwest::Client::builder()
+                    .timeout(JWKS_TIMEOUT)
+                    .build()
+                    .map_err(std::io::Error::other)?;
+                JwksSource::Url(url.clone(), client)
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "Exactly one of 'jwks_file' or 'jwks_url' should be configured.",
+                ))
+            }
+        };
+
+        let jwks = load_jwks(&source).await?;
+        info!("Loaded {} JSON web key(s).", jwks.keys.len());
+
+        Ok(JwtValidator {
+            settings: settings.clone(),
+            source,
+            jwks: RwLock::new(jwks),
+            refreshed: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub async fn validate(&self, token: &str) -> Result<Principal, AuthError> {
+        let header = decode_header(token).map_err(|e| {
+            warn!("Invalid token header: {:?}", e);
+            AuthError::InvalidCredentials
+        })?;
+
+        let kid = header.kid.ok_or_else(|| {
+            warn!("Token header has no 'kid'.");
+            AuthError::InvalidCredentials
+        })?;
+
+        let (key, algorithms) = match self.decoding_key(&kid).await {
+            Some(key) => key,
+            None => {
+                self.refresh().await;
+                self.decoding_key(&kid).await.ok_or_else(|| {
+                    warn!("No JSON web key with kid: {}.", &kid);
+                    AuthError::InvalidCredentials
+                })?
+            }
+        };
+
+        // The token's header names its algorithm, but only the ones the key
+        // is meant for are accepted.
+        if !algorithms.contains(&header.alg) {
+            warn!(
+                "Token algorithm {:?} doesn't match JSON web key with kid: {}.",
+                header.alg, &kid
+            );
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = algorithms;
+        validation.set_issuer(&[&self.settings.issuer]);
+        validation.set_audience(&[&self.settings.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
+        let claims = decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|e| {
+                warn!("Token validation failed: {:?}", e);
+                AuthError::InvalidCredentials
+            })?
+            .claims;
+
+        self.principal(&claims)
+    }
+
+    async fn decoding_key(&self, kid: &str) -> Option<(DecodingKey, Vec<Algorithm>)> {
+        let jwks = self.jwks.read().await;
+        let jwk = jwks.find(kid)?;
+
+        // Symmetric keys would let anyone holding the JWKS mint tokens.
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            warn!("Ignoring symmetric JSON web key with kid: {}.", kid);
+            return None;
+        }
+
+        let algorithms = algorithms_of(jwk);
+        if algorithms.is_empty() {
+            warn!(
+                "Ignoring JSON web key with kid: {}, it isn't for signing.",
+                kid
+            );
+            return None;
+        }
+
+        Some((DecodingKey::from_jwk(jwk).ok()?, algorithms))
+    }
+
+    async fn refresh(&self) {
+        if let JwksSource::File(_) = self.source {
+            return;
+        }
+
+        let mut refreshed = self.refreshed.lock().await;
+        if refreshed.elapsed() < MIN_REFRESH_INTERVAL {
+            return;
+        }
+
+        match load_jwks(&self.source).await {
+            Ok(jwks) => {
+                info!("Refreshed {} JSON web key(s).", jwks.keys.len());
+                *self.jwks.write().await = jwks;
+            }
+            Err(e) => warn!("Failed to refresh JSON web keys: {:?}", e),
+        }
+        *refreshed = Instant::now();
+    }
+
+    fn principal(&self, claims: &Map<String, Value>) -> Result<Principal, AuthError> {
+        let name = claims
+            .get(&self.settings.principal_claim)
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                warn!(
+                    "Token has no '{}' claim to identify the principal.",
+                    &self.settings.principal_claim
+                );
+                AuthError::InvalidCredentials
+            })?;
+
+        Ok(Principal {
+            name: format!("{}{}", JWT_PRINCIPAL_PREFIX, name),
+            namespaces: Some(string_list(claims.get(&self.settings.namespaces_claim))),
+            roles: string_list(claims.get(&self.settings.roles_claim)),
+        })
+    }
+}
+
+// The key's own `alg` when it has one, otherwise every signing algorithm
+// for its key type.
+fn algorithms_of(jwk: &Jwk) -> Vec<Algorithm> {
+    if let Some(algorithm) = &jwk.common.key_algorithm {
+        return Algorithm::from_str(&algorithm.to_string())
+            .into_iter()
+            .collect();
+    }
+
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(key) => match key.curve {
+            EllipticCurve::P256 => vec![Algorithm::ES256],
+            EllipticCurve::P384 => vec![Algorithm::ES384],
+            _ => Vec::new(),
+        },
+        AlgorithmParameters::OctetKeyPair(key) if key.curve == EllipticCurve::Ed25519 => {
+            vec![Algorithm::EdDSA]
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn string_list(claim: Option<&Value>) -> Vec<String> {
+    match claim {
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Some(Value::String(value)) => value.split_whitespace().map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+async fn load_jwks(source: &JwksSource) -> Result<JwkSet, std::io::Error> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Failed to load JSON web keys: {}", e),
+        )
+    };
+
+    match source {
+        JwksSource::File(path) => {
+            let jwks = tokio::fs::read_to_string(path).await?;
+            serde_json::from_str(&jwks).map_err(|e| invalid(&e))
+        }
+        JwksSource::Url(url, client) => client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| invalid(&e))?
+            .json()
+            .await
+            .map_err(|e| invalid(&e)),
+    }
+}
@@ -1,49 +1,117 @@
-use std::net::TcpListener;
+use std::{net::TcpListener, sync::Arc, time::Instant};
 
-use actix_web::{dev::Server, web, App, HttpResponse, HttpServer, Responder};
-use openssl::ssl::SslAcceptorBuilder;
-use serde::Deserialize;
+use actix_web::{
+    dev::{Server, Service},
+    error::PayloadError,
+    http::header::RETRY_AFTER,
+    web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use kv_common::policy::{Policy, Right};
+use serde::{Deserialize, Serialize};
 
-use backend_server::kv_client::KvClient;
-use tonic::{transport::Channel, Code};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::{Code, Status};
 use tracing::{error, info, warn};
-use tracing_actix_web::TracingLogger;
+use tracing_actix_web::{RequestId, TracingLogger};
 
-use crate::backend_server::{GetValueRequest, InsertValueRequest};
+use crate::auth::{Authentication, Authenticator, Principal};
+use crate::backend_server::{
+    import_chunk, DeleteValueRequest, ExportRequest, GetQuotaUsageRequest, GetValueRequest,
+    ImportChunk, InsertValueRequest, Quota,
+};
+use crate::client::{Consistency, KvClients};
+use crate::config::Settings;
+use crate::health::{livez, readyz, Readiness};
+use crate::metrics::{metrics, Metrics, RequestMetrics};
+use crate::policy::Authorizer;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::retry::{with_retries, IdempotencyKey};
+use crate::timeout::{Deadline, RequestTimeout, Timeouts};
+use crate::tls::CertificateStore;
+
+pub mod auth;
+pub mod breaker;
+pub mod client;
+pub mod config;
+pub mod health;
+pub mod jwt;
+pub mod metrics;
+pub mod policy;
+pub mod rate_limit;
+pub mod retry;
+pub mod shutdown;
+pub mod telemetry;
+pub mod timeout;
+pub mod tls;
 
 pub mod backend_server {
     tonic::include_proto!("kv");
 }
 
+const CONSISTENCY_HEADER: &str = "X-KV-Consistency";
+
+// Seconds clients are asked to wait before retrying an unavailable backend.
+const RETRY_AFTER_SECS: u64 = 1;
+
+// Request body chunks buffered ahead of the backend during an import.
+const IMPORT_BUFFER: usize = 16;
+
 #[derive(Deserialize, Debug)]
 struct KV {
     key: String,
     value: String,
+    #[serde(default)]
+    ttl_seconds: u64,
 }
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().body("Hello")
+fn requested_consistency(request: &HttpRequest) -> Consistency {
+    match request.headers().get(CONSISTENCY_HEADER) {
+        Some(value) if value.as_bytes().eq_ignore_ascii_case(b"strong") => Consistency::Strong,
+        _ => Consistency::Eventual,
+    }
 }
 
+// Every argument is an extractor.
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
-    skip(path, kv_client)
+    skip(path, kv_clients, readiness, authorizer, http_request, principal, request_id, deadline)
     fields(
-        key = %path.as_str()
+        key = %path.as_str(),
+        principal = %principal,
+        consistency = ?requested_consistency(&http_request)
     )
 )]
 async fn get_value(
     path: web::Path<String>,
-    kv_client: web::Data<KvClient<Channel>>,
+    kv_clients: web::Data<KvClients>,
+    readiness: web::Data<Readiness>,
+    authorizer: web::Data<Authorizer>,
+    http_request: HttpRequest,
+    principal: Principal,
+    request_id: RequestId,
+    deadline: Deadline,
 ) -> impl Responder {
     let key = path.into_inner();
 
-    let mut kv_client = kv_client.get_ref().clone();
+    if !authorizer.authorize(&principal, Right::Read, &key) {
+        return HttpResponse::Forbidden().finish();
+    }
 
+    let consistency = requested_consistency(&http_request);
     let request = GetValueRequest { key };
 
     info!("Sending request to grpc server: {:?}", &request);
 
-    let response = kv_client.get_value(request).await;
+    // A retry may go to another replica.
+    let start = Instant::now();
+    let response = with_retries(kv_clients.retry(), deadline, || {
+        let reader = kv_clients.reader(consistency);
+        let request = kv_clients.request(request.clone(), &principal, &request_id, deadline);
+        reader.get_value(request)
+    })
+    .await;
+    readiness.record_rpc(&response, start.elapsed());
 
     match response {
         Ok(response) => {
@@ -55,24 +123,34 @@ async fn get_value(
         }
         Err(status) => {
             error!("Error returned from backend server: {:?}", &status);
-
-            if status.code() == Code::NotFound {
-                HttpResponse::NotFound().finish()
-            } else {
-                HttpResponse::InternalServerError().finish()
-            }
+            error_response(&status)
         }
     }
 }
 
-#[tracing::instrument(skip(kv_client))]
+// Every argument is an extractor.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    skip(kv_clients, readiness, authorizer, principal, request_id, deadline, idempotency_key)
+    fields(
+        principal = %principal
+    )
+)]
 async fn insert_value(
     json_data: web::Json<KV>,
-    kv_client: web::Data<KvClient<Channel>>,
+    kv_clients: web::Data<KvClients>,
+    readiness: web::Data<Readiness>,
+    authorizer: web::Data<Authorizer>,
+    principal: Principal,
+    request_id: RequestId,
+    deadline: Deadline,
+    idempotency_key: IdempotencyKey,
 ) -> impl Responder {
-    let mut kv_client = kv_client.get_ref().clone();
-
-    let KV { key, value } = json_data.into_inner();
+    let KV {
+        key,
+        value,
+        ttl_seconds,
+    } = json_data.into_inner();
 
     if key.trim().is_empty() {
         warn!("Validation failed: key is empty.");
@@ -84,10 +162,26 @@ async fn insert_value(
         return HttpResponse::BadRequest().body("'value' field can't be empty.");
     }
 
-    let request = InsertValueRequest { key, value };
+    if !authorizer.authorize(&principal, Right::Write, &key) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let request = InsertValueRequest {
+        key,
+        value,
+        ttl_seconds,
+    };
 
     info!("Sending request to grpc server: {:?}", &request);
-    let response = kv_client.insert_value(request).await;
+    let start = Instant::now();
+    let response = with_retries(kv_clients.retry(), deadline, || {
+        let mut kv_client = kv_clients.primary();
+        let request = kv_clients.request(request.clone(), &principal, &request_id, deadline);
+        let request = idempotency_key.attach(request);
+        async move { kv_client.insert_value(request).await }
+    })
+    .await;
+    readiness.record_rpc(&response, start.elapsed());
 
     match response {
         Ok(response) => {
@@ -98,34 +192,414 @@ async fn insert_value(
         }
         Err(status) => {
             error!("Error returned from backend server: {:?}", &status);
-            HttpResponse::InternalServerError().finish()
+            error_response(&status)
         }
     }
 }
 
+// Every argument is an extractor.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    skip(path, kv_clients, readiness, authorizer, principal, request_id, deadline, idempotency_key)
+    fields(
+        key = %path.as_str(),
+        principal = %principal
+    )
+)]
+async fn delete_value(
+    path: web::Path<String>,
+    kv_clients: web::Data<KvClients>,
+    readiness: web::Data<Readiness>,
+    authorizer: web::Data<Authorizer>,
+    principal: Principal,
+    request_id: RequestId,
+    deadline: Deadline,
+    idempotency_key: IdempotencyKey,
+) -> impl Responder {
+    let key = path.into_inner();
+
+    if !authorizer.authorize(&principal, Right::Delete, &key) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let request = DeleteValueRequest { key };
+
+    info!("Sending request to grpc server: {:?}", &request);
+    let start = Instant::now();
+    let response = with_retries(kv_clients.retry(), deadline, || {
+        let mut kv_client = kv_clients.primary();
+        let request = kv_clients.request(request.clone(), &principal, &request_id, deadline);
+        let request = idempotency_key.attach(request);
+        async move { kv_client.delete_value(request).await }
+    })
+    .await;
+    readiness.record_rpc(&response, start.elapsed());
+
+    match response {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(status) => {
+            error!("Error returned from backend server: {:?}", &status);
+            error_response(&status)
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct QuotaUsage {
+    namespace: String,
+    quota: Option<QuotaLimits>,
+    keys: u64,
+    bytes: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct QuotaLimits {
+    max_keys: Option<u64>,
+    max_bytes: Option<u64>,
+    max_value_bytes: Option<u64>,
+}
+
+impl From<Quota> for QuotaLimits {
+    fn from(quota: Quota) -> Self {
+        QuotaLimits {
+            max_keys: quota.max_keys,
+            max_bytes: quota.max_bytes,
+            max_value_bytes: quota.max_value_bytes,
+        }
+    }
+}
+
+#[tracing::instrument(
+    skip(path, kv_clients, authorizer, principal, request_id, deadline)
+    fields(
+        namespace = %path.as_str(),
+        principal = %principal
+    )
+)]
+async fn quota_usage(
+    path: web::Path<String>,
+    kv_clients: web::Data<KvClients>,
+    authorizer: web::Data<Authorizer>,
+    principal: Principal,
+    request_id: RequestId,
+    deadline: Deadline,
+) -> impl Responder {
+    let namespace = path.into_inner();
+
+    // Admin on the namespace is checked against a key inside it.
+    if !authorizer.authorize(&principal, Right::Admin, &format!("{}:", namespace)) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let request = GetQuotaUsageRequest { namespace };
+
+    let response = kv_clients
+        .admin()
+        .get_quota_usage(kv_clients.request(request, &principal, &request_id, deadline))
+        .await;
+
+    match response {
+        Ok(response) => {
+            let response = response.into_inner();
+
+            HttpResponse::Ok().json(QuotaUsage {
+                namespace: response.namespace,
+                quota: response.quota.map(Into::into),
+                keys: response.keys,
+                bytes: response.bytes,
+            })
+        }
+        Err(status) => {
+            error!("Error returned from backend server: {:?}", &status);
+            error_response(&status)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ExportQuery {
+    #[serde(default)]
+    prefix: String,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum ImportMode {
+    #[default]
+    Overwrite,
+    SkipExisting,
+}
+
+impl From<ImportMode> for import_chunk::Mode {
+    fn from(mode: ImportMode) -> Self {
+        match mode {
+            ImportMode::Overwrite => import_chunk::Mode::Overwrite,
+            ImportMode::SkipExisting => import_chunk::Mode::SkipExisting,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ImportQuery {
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    mode: ImportMode,
+}
+
+#[derive(Serialize, Debug)]
+struct ImportSummary {
+    imported: u64,
+    skipped: u64,
+    failed: u64,
+    errors: Vec<ImportLineError>,
+}
+
+#[derive(Serialize, Debug)]
+struct ImportLineError {
+    line: u64,
+    key: String,
+    error: String,
+}
+
+// Exports and imports need admin on the prefix, an empty prefix covers every
+// key.
+#[tracing::instrument(
+    skip(query, kv_clients, authorizer, principal, request_id, deadline)
+    fields(
+        prefix = %query.prefix,
+        principal = %principal
+    )
+)]
+async fn export(
+    query: web::Query<ExportQuery>,
+    kv_clients: web::Data<KvClients>,
+    authorizer: web::Data<Authorizer>,
+    principal: Principal,
+    request_id: RequestId,
+    deadline: Deadline,
+) -> impl Responder {
+    let ExportQuery { prefix } = query.into_inner();
+
+    if !authorizer.authorize(&principal, Right::Admin, &prefix) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let request = ExportRequest { prefix };
+
+    let response = kv_clients
+        .admin()
+        .export(kv_clients.request(request, &principal, &request_id, deadline))
+        .await;
+
+    match response {
+        // A failure after the first chunk can only abort the response.
+        Ok(response) => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .streaming(response.into_inner().map(|chunk| match chunk {
+                Ok(chunk) => Ok(web::Bytes::from(chunk.data)),
+                Err(status) => {
+                    error!("Export failed: {:?}", status);
+                    Err(Box::new(status))
+                }
+            })),
+        Err(status) => {
+            error!("Error returned from backend server: {:?}", &status);
+            error_response(&status)
+        }
+    }
+}
+
+#[tracing::instrument(
+    skip(query, payload, kv_clients, authorizer, principal, request_id, deadline)
+    fields(
+        prefix = %query.prefix,
+        mode = ?query.mode,
+        principal = %principal
+    )
+)]
+async fn import(
+    query: web::Query<ImportQuery>,
+    mut payload: web::Payload,
+    kv_clients: web::Data<KvClients>,
+    authorizer: web::Data<Authorizer>,
+    principal: Principal,
+    request_id: RequestId,
+    deadline: Deadline,
+) -> impl Responder {
+    let ImportQuery { prefix, mode } = query.into_inner();
+
+    if !authorizer.authorize(&principal, Right::Admin, &prefix) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    // The body is forwarded as it arrives. It isn't Send, so it's read on
+    // this worker and handed to the backend call through a channel.
+    let (sender, receiver) = mpsc::channel(IMPORT_BUFFER);
+    let first = ImportChunk {
+        prefix,
+        mode: import_chunk::Mode::from(mode).into(),
+        data: Vec::new(),
+    };
+    let reader = actix_web::rt::spawn(async move {
+        if sender.send(first).await.is_err() {
+            return Ok(());
+        }
+
+        while let Some(bytes) = payload.next().await {
+            let chunk = ImportChunk {
+                data: bytes?.to_vec(),
+                ..ImportChunk::default()
+            };
+            if sender.send(chunk).await.is_err() {
+                break;
+            }
+        }
+
+        Ok::<_, PayloadError>(())
+    });
+
+    let response = kv_clients
+        .admin()
+        .import(kv_clients.request(
+            ReceiverStream::new(receiver),
+            &principal,
+            &request_id,
+            deadline,
+        ))
+        .await;
+
+    if let Ok(Err(error)) = reader.await {
+        warn!("Failed to read import body: {:?}", error);
+        return HttpResponse::BadRequest().body(format!("Failed to read request body: {}", error));
+    }
+
+    match response {
+        Ok(response) => {
+            let response = response.into_inner();
+
+            HttpResponse::Ok().json(ImportSummary {
+                imported: response.imported,
+                skipped: response.skipped,
+                failed: response.failed,
+                errors: response
+                    .errors
+                    .into_iter()
+                    .map(|error| ImportLineError {
+                        line: error.line,
+                        key: error.key,
+                        error: error.message,
+                    })
+                    .collect(),
+            })
+        }
+        Err(status) => {
+            error!("Error returned from backend server: {:?}", &status);
+            error_response(&status)
+        }
+    }
+}
+
+fn error_response(status: &Status) -> HttpResponse {
+    match status.code() {
+        Code::NotFound => HttpResponse::NotFound().finish(),
+        Code::PermissionDenied => HttpResponse::Forbidden().finish(),
+        Code::InvalidArgument => HttpResponse::BadRequest().body(status.message().to_string()),
+        Code::ResourceExhausted => {
+            HttpResponse::InsufficientStorage().body(status.message().to_string())
+        }
+        // Worth retrying later, or right away with the same Idempotency-Key.
+        Code::Unavailable => HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, RETRY_AFTER_SECS))
+            .body(status.message().to_string()),
+        Code::Aborted => HttpResponse::Conflict().body(status.message().to_string()),
+        // tonic reports a call cut off by its grpc-timeout as cancelled.
+        Code::DeadlineExceeded | Code::Cancelled => {
+            HttpResponse::GatewayTimeout().body("Request timed out.")
+        }
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 pub async fn run(
     listener: TcpListener,
-    kv_client: KvClient<Channel>,
-    ssl: Option<SslAcceptorBuilder>,
+    kv_clients: KvClients,
+    settings: &Settings,
 ) -> Result<Server, std::io::Error> {
-    let kv_client = web::Data::new(kv_client);
+    let kv_clients = web::Data::new(kv_clients);
+    let authenticator = Arc::new(Authenticator::new(&settings.auth).await?);
+    let policy = match &settings.auth.policy_file {
+        Some(path) => Some(Policy::load(path).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Failed to load policy: {}", e),
+            )
+        })?),
+        None => None,
+    };
+    let authorizer = web::Data::new(Authorizer::new(policy));
+    let readiness = web::Data::new(Readiness::new(&settings.readiness));
+    let draining = readiness.clone().into_inner();
+    let metrics_registry = Metrics::new();
+    for breaker in kv_clients.breakers() {
+        metrics_registry.register(breaker.collectors());
+    }
+    let rate_limiter = Arc::new(RateLimiter::new(&settings.rate_limit)?);
+    let timeouts = Arc::new(Timeouts::new(&settings.timeouts));
 
     let mut server = HttpServer::new(move || {
         App::new()
+            .wrap(RequestTimeout::new(timeouts.clone()))
+            .wrap(RequestMetrics::new(metrics_registry.clone()))
             .wrap(TracingLogger::default())
-            .route("/health_check", web::get().to(health_check))
-            .route("/{key}", web::get().to(get_value))
-            .route("/", web::post().to(insert_value))
-            .app_data(kv_client.clone())
-    });
+            .wrap_fn({
+                let readiness = readiness.clone().into_inner();
+                move |request, service| {
+                    let in_flight = readiness.clone().track();
+                    let response = service.call(request);
+                    async move {
+                        let response = response.await;
+                        drop(in_flight);
+                        response
+                    }
+                }
+            })
+            .route("/livez", web::get().to(livez))
+            .route("/readyz", web::get().to(readyz))
+            .route("/metrics", web::get().to(metrics))
+            .service(
+                web::scope("")
+                    .wrap(RateLimit::new(
+                        rate_limiter.clone(),
+                        metrics_registry.clone(),
+                    ))
+                    .wrap(Authentication::new(authenticator.clone()))
+                    .route("/admin/quotas/{namespace}", web::get().to(quota_usage))
+                    .route("/admin/export", web::get().to(export))
+                    .route("/admin/import", web::post().to(import))
+                    .route("/{key}", web::get().to(get_value))
+                    .route("/{key}", web::delete().to(delete_value))
+                    .route("/", web::post().to(insert_value)),
+            )
+            .app_data(kv_clients.clone())
+            .app_data(readiness.clone())
+            .app_data(authorizer.clone())
+            .app_data(web::Data::new(metrics_registry.clone()))
+    })
+    .disable_signals()
+    .shutdown_timeout(settings.shutdown.timeout_secs);
 
-    if let Some(builder) = ssl {
-        server = server.listen_openssl(listener, builder)?;
+    if settings.frontend.tls.enabled {
+        let store = CertificateStore::load(&settings.frontend.tls)?;
+        kv_common::tls::watch(store.clone(), settings.frontend.tls.reload_interval_secs)?;
+        server = server.listen_openssl(listener, store.acceptor()?)?;
     } else {
+        warn!("TLS is disabled, serving plaintext HTTP.");
         server = server.listen(listener)?;
     }
 
     let server = server.run();
+    shutdown::on_signal(server.handle(), draining, &settings.shutdown)?;
 
     Ok(server)
 }
@@ -1,41 +1,29 @@
 use std::net::TcpListener;
 
-use client::get_client;
+use frontend::client::get_clients;
+use frontend::config::get_configuration;
 use frontend::run;
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
-
-use tracing_subscriber::{EnvFilter, FmtSubscriber};
-
-use crate::config::get_configuration;
-
-mod client;
-mod config;
+use frontend::telemetry::{get_subscriber, init_subscriber};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    FmtSubscriber::builder().with_env_filter(filter).init();
-
     let configuration = get_configuration().expect("Failed to read configuration.");
 
+    let (subscriber, _tracer_provider) = get_subscriber(
+        "frontend",
+        "info",
+        configuration.frontend.otlp_endpoint.as_deref(),
+    )
+    .expect("Failed to build tracer provider.");
+    init_subscriber(subscriber);
+
     let listener = TcpListener::bind(format!(
         "{}:{}",
         configuration.frontend.host, configuration.frontend.application_port
     ))
     .expect("Should bind to '127.0.0.1:8000'");
 
-    let address = format!(
-        "https://{}:{}",
-        configuration.backend.host, configuration.backend.application_port
-    );
-
-    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
-    builder
-        .set_private_key_file("key1.pem", SslFiletype::PEM)
-        .unwrap();
-    builder.set_certificate_chain_file("cert1.pem").unwrap();
-
-    let kv_client = get_client(address).await?;
+    let kv_clients = get_clients(&configuration.backend).await?;
 
-    run(listener, kv_client, Some(builder)).await?.await
+    run(listener, kv_clients, &configuration).await?.await
 }
@@ -0,0 +1,186 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    time::Instant,
+};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::ContentType,
+    web, Error, HttpResponse, Responder,
+};
+use prometheus::{
+    core::Collector, Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry,
+    TextEncoder,
+};
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    http_requests_throttled: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of handled HTTP requests."),
+            &["method", "route", "status"],
+        )
+        .expect("Metric should be valid.");
+
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Latency of handled HTTP requests.",
+            ),
+            &["method", "route"],
+        )
+        .expect("Metric should be valid.");
+
+        let http_requests_throttled = IntCounterVec::new(
+            Opts::new(
+                "http_requests_throttled_total",
+                "Number of HTTP requests rejected by rate limiting.",
+            ),
+            &["method", "route"],
+        )
+        .expect("Metric should be valid.");
+
+        registry
+            .register(Box::new(http_requests.clone()))
+            .expect("Metric should be registered once.");
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .expect("Metric should be registered once.");
+        registry
+            .register(Box::new(http_requests_throttled.clone()))
+            .expect("Metric should be registered once.");
+
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            http_requests_throttled,
+        }
+    }
+
+    fn observe_request(&self, method: &str, route: &str, status: u16, start: Instant) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(start.elapsed().as_secs_f64());
+    }
+
+    pub fn observe_throttled(&self, method: &str, route: &str) {
+        self.http_requests_throttled
+            .with_label_values(&[method, route])
+            .inc();
+    }
+
+    pub fn register(&self, collectors: Vec<Box<dyn Collector>>) {
+        for collector in collectors {
+            self.registry
+                .register(collector)
+                .expect("Metric should be registered once.");
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Metrics should be encoded.");
+
+        String::from_utf8(buffer).expect("Metrics should be valid utf-8.")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+pub async fn metrics(metrics: web::Data<Metrics>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(metrics.encode())
+}
+
+pub struct RequestMetrics {
+    metrics: Metrics,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: Metrics) -> Self {
+        RequestMetrics { metrics }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service: Rc::new(service),
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: Rc<S>,
+    metrics: Metrics,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let method = request.method().to_string();
+        let route = request
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+
+        let service = self.service.clone();
+        let metrics = self.metrics.clone();
+
+        Box::pin(async move {
+            let response = service.call(request).await;
+
+            let status = match &response {
+                Ok(response) => response.status(),
+                Err(error) => error.as_response_error().status_code(),
+            };
+
+            metrics.observe_request(&method, &route, status.as_u16(), start);
+
+            response
+        })
+    }
+}
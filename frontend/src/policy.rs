@@ -0,0 +1,38 @@
+use kv_common::policy::{Policy, Right};
+use tracing::warn;
+
+use crate::auth::Principal;
+
+#[derive(Default)]
+pub struct Authorizer {
+    policy: Option<Policy>,
+}
+
+impl Authorizer {
+    pub fn new(policy: Option<Policy>) -> Self {
+        if policy.is_none() {
+            warn!("No policy configured, authenticated principals may access every key.");
+        }
+
+        Authorizer { policy }
+    }
+
+    pub fn authorize(&self, principal: &Principal, right: Right, key: &str) -> bool {
+        let permitted = principal.permits(key)
+            && self
+                .policy
+                .as_ref()
+                .is_none_or(|policy| policy.allows(&principal.name, &principal.roles, right, key));
+
+        if !permitted {
+            warn!(
+                principal = %principal,
+                key = %key,
+                right = ?right,
+                "Access denied."
+            );
+        }
+
+        permitted
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    future::{ready, Future, Ready},
+    hash::{Hash, Hasher},
+    io::{self, ErrorKind},
+    num::NonZeroUsize,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::RETRY_AFTER,
+    Error, HttpMessage, HttpResponse,
+};
+use lru::LruCache;
+use tracing::{info, warn};
+
+use crate::auth::Principal;
+use crate::config::{self, Limit};
+use crate::metrics::Metrics;
+
+// The least recently used buckets are dropped once this many are tracked.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+// Buckets are spread over shards so clients rarely wait for each other.
+const SHARDS: usize = 16;
+
+#[derive(Hash, PartialEq, Eq)]
+struct BucketKey {
+    method: String,
+    route: String,
+    client: String,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &Limit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+// Token buckets per client and route. Each bucket holds up to `burst` tokens,
+// refills at `requests_per_second` and every request takes one token.
+pub struct RateLimiter {
+    default: Option<Limit>,
+    routes: Vec<config::RouteLimit>,
+    shards: Vec<Mutex<LruCache<BucketKey, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &config::RateLimit) -> Result<Self, io::Error> {
+        if !settings.enabled {
+            warn!("Rate limiting is disabled.");
+            return Ok(RateLimiter {
+                default: None,
+                routes: Vec::new(),
+                shards: Vec::new(),
+            });
+        }
+
+        let limits = settings
+            .default
+            .iter()
+            .chain(settings.routes.iter().map(|route| &route.limit));
+
+        for limit in limits {
+            if limit.requests_per_second <= 0.0 || limit.burst == 0 {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "Rate limits need a positive requests_per_second and burst.",
+                ));
+            }
+        }
+
+        info!(
+            "Rate limiting {} route(s), default limit: {:?}.",
+            settings.routes.len(),
+            settings.default
+        );
+
+        Ok(RateLimiter {
+            default: settings.default,
+            routes: settings.routes.clone(),
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(LruCache::new(shard_capacity())))
+                .collect(),
+        })
+    }
+
+    fn limit(&self, method: &str, route: &str) -> Option<Limit> {
+        self.routes
+            .iter()
+            .find(|limit| {
+                limit.route == route
+                    && limit
+                        .method
+                        .as_ref()
+                        .is_none_or(|allowed| allowed.eq_ignore_ascii_case(method))
+            })
+            .map(|limit| limit.limit)
+            .or(self.default)
+    }
+
+    fn shard(&self, key: &BucketKey) -> MutexGuard<'_, LruCache<BucketKey, Bucket>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        self.shards[hasher.finish() as usize % self.shards.len()]
+            .lock()
+            .expect("Rate limiter lock should not be poisoned.")
+    }
+
+    // Returns how long the client has to wait when it is over the limit.
+    pub fn check(&self, method: &str, route: &str, client: &str) -> Result<(), Duration> {
+        let Some(limit) = self.limit(method, route) else {
+            return Ok(());
+        };
+
+        let key = BucketKey {
+            method: method.to_string(),
+            route: route.to_string(),
+            client: client.to_string(),
+        };
+        let now = Instant::now();
+        let mut buckets = self.shard(&key);
+        let bucket = buckets.get_or_insert_mut(key, || Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        });
+        bucket.refill(&limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.requests_per_second,
+            ))
+        }
+    }
+}
+
+fn shard_capacity() -> NonZeroUsize {
+    NonZeroUsize::new(MAX_TRACKED_BUCKETS / SHARDS).expect("Shards should hold buckets.")
+}
+
+// Callers are told apart by the principal they authenticated as, anonymous
+// ones by address. Runs after authentication, so a made up token can't get
+// a fresh bucket.
+fn client_of(request: &ServiceRequest) -> String {
+    let principal = request
+        .extensions()
+        .get::<Principal>()
+        .filter(|principal| **principal != Principal::anonymous())
+        .map(|principal| principal.name.clone());
+
+    match (principal, request.peer_addr()) {
+        (Some(name), _) => format!("principal:{}", name),
+        (None, Some(address)) => format!("ip:{}", address.ip()),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+    metrics: Metrics,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>, metrics: Metrics) -> Self {
+        RateLimit { limiter, metrics }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+    metrics: Metrics,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let method = request.method().to_string();
+        let route = request
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+
+        if let Err(wait) = self.limiter.check(&method, &route, &client_of(&request)) {
+            warn!(%method, %route, "Request throttled, retry in {:?}.", wait);
+            self.metrics.observe_throttled(&method, &route);
+
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((RETRY_AFTER, wait.as_secs_f64().ceil().max(1.0).to_string()))
+                .body("Too many requests.");
+
+            return Box::pin(ready(Ok(request
+                .into_response(response)
+                .map_into_right_body())));
+        }
+
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let response = service.call(request).await?;
+            Ok(response.map_into_left_body())
+        })
+    }
+}
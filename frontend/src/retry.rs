@@ -0,0 +1,131 @@
+use std::{
+    future::{ready, Future, Ready},
+    time::Duration,
+};
+
+use actix_web::{dev::Payload, error::ErrorBadRequest, Error, FromRequest, HttpRequest};
+use rand::Rng;
+use tokio::time::sleep;
+use tonic::{metadata::MetadataValue, Code, Request, Status};
+use tracing::warn;
+use tracing_actix_web::RequestId;
+
+use crate::breaker::is_circuit_open;
+use crate::config;
+use crate::timeout::Deadline;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENCY_KEY_METADATA: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+// The backend deduplicates writes sent with the same key, so a write retried
+// after a lost response isn't applied twice. Without the header the request
+// id is used, which covers the frontend's own retries.
+#[derive(Clone, Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    pub fn attach<T>(&self, mut request: Request<T>) -> Request<T> {
+        match MetadataValue::try_from(self.0.as_str()) {
+            Ok(value) => {
+                request
+                    .metadata_mut()
+                    .insert(IDEMPOTENCY_KEY_METADATA, value);
+            }
+            Err(_) => warn!("Idempotency key can't be forwarded as metadata."),
+        }
+
+        request
+    }
+}
+
+impl FromRequest for IdempotencyKey {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let Some(value) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+            let key = RequestId::from_request(request, payload)
+                .into_inner()
+                .map(|request_id| IdempotencyKey(request_id.to_string()));
+            return ready(key.map_err(Into::into));
+        };
+
+        let key = match value.to_str() {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH => {
+                Ok(IdempotencyKey(key.to_string()))
+            }
+            _ => Err(ErrorBadRequest(format!(
+                "{} header should be 1 to {} visible characters.",
+                IDEMPOTENCY_KEY_HEADER, MAX_IDEMPOTENCY_KEY_LENGTH
+            ))),
+        };
+
+        ready(key)
+    }
+}
+
+// Calls failing before the backend got them, or while a write with the same
+// idempotency key was still running. An open circuit breaker fails fast
+// instead.
+pub fn is_retryable(status: &Status) -> bool {
+    match status.code() {
+        Code::Unavailable => !is_circuit_open(status),
+        Code::Aborted => true,
+        _ => false,
+    }
+}
+
+// Makes a new call for every attempt, as long as the request's deadline
+// leaves time for the backoff.
+pub async fn with_retries<T, F, Fut>(
+    settings: &config::Retry,
+    deadline: Deadline,
+    mut call: F,
+) -> Result<T, Status>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Status>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        let status = match call().await {
+            Ok(reply) => return Ok(reply),
+            Err(status) => status,
+        };
+
+        if !settings.enabled || attempt >= settings.max_attempts || !is_retryable(&status) {
+            return Err(status);
+        }
+
+        let delay = backoff(settings, attempt);
+        if deadline
+            .remaining()
+            .is_some_and(|remaining| remaining <= delay)
+        {
+            return Err(status);
+        }
+
+        warn!(
+            "Backend call failed with {:?}, attempt {} of {} in {:?}.",
+            status.code(),
+            attempt + 1,
+            settings.max_attempts,
+            delay
+        );
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+// Full jitter, a random delay up to the exponential backoff so retrying
+// clients spread out.
+fn backoff(settings: &config::Retry, attempt: u32) -> Duration {
+    let ceiling = settings
+        .base_delay_ms
+        .saturating_mul(2_u64.saturating_pow(attempt - 1))
+        .min(settings.max_delay_ms);
+
+    Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling))
+}
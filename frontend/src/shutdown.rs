@@ -0,0 +1,66 @@
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::dev::ServerHandle;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time::sleep,
+};
+use tracing::{info, warn};
+
+use crate::config;
+use crate::health::Readiness;
+
+// On SIGTERM or SIGINT readiness fails first, so load balancers stop sending
+// requests during the drain delay. Then new connections aren't accepted and
+// in-flight requests get the shutdown timeout to finish.
+pub fn on_signal(
+    server: ServerHandle,
+    readiness: Arc<Readiness>,
+    settings: &config::Shutdown,
+) -> Result<(), io::Error> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let drain_delay = Duration::from_secs(settings.drain_delay_secs);
+    let timeout = Duration::from_secs(settings.timeout_secs);
+
+    tokio::spawn(async move {
+        let name = tokio::select! {
+            Some(()) = terminate.recv() => "SIGTERM",
+            Some(()) = interrupt.recv() => "SIGINT",
+            else => return,
+        };
+
+        info!(
+            "Received {}, draining for {:?} before shutting down.",
+            name, drain_delay
+        );
+        readiness.start_draining();
+        sleep(drain_delay).await;
+
+        info!("Closing listener and waiting for in-flight requests.");
+        server.pause().await;
+
+        // Stopping the server closes idle connections and can drop busy ones
+        // with them, so it waits for the requests here.
+        let deadline = Instant::now() + timeout;
+        while readiness.in_flight() > 0 {
+            if Instant::now() >= deadline {
+                warn!(
+                    "Shutting down with {} requests in flight.",
+                    readiness.in_flight()
+                );
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        server.stop(true).await;
+        info!("Shut down.");
+    });
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorGatewayTimeout,
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use tokio::time::timeout_at;
+use tracing::{info, warn};
+
+use crate::config;
+
+// Routes without their own timeout use the default, unset leaves them
+// unbounded.
+pub struct Timeouts {
+    default: Option<Duration>,
+    routes: Vec<config::RouteTimeout>,
+}
+
+impl Timeouts {
+    pub fn new(settings: &config::Timeouts) -> Self {
+        info!(
+            "Timing out {} route(s), default timeout: {:?} ms.",
+            settings.routes.len(),
+            settings.default_ms
+        );
+
+        Timeouts {
+            default: settings.default_ms.map(Duration::from_millis),
+            routes: settings.routes.clone(),
+        }
+    }
+
+    fn timeout(&self, method: &str, route: &str) -> Option<Duration> {
+        self.routes
+            .iter()
+            .find(|timeout| {
+                timeout.route == route
+                    && timeout
+                        .method
+                        .as_ref()
+                        .is_none_or(|allowed| allowed.eq_ignore_ascii_case(method))
+            })
+            .map(|timeout| Duration::from_millis(timeout.timeout_ms))
+            .or(self.default)
+    }
+}
+
+// When the request has to be answered by, handlers hand what's left of it
+// to the backend. Unset for routes without a timeout.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+impl FromRequest for Deadline {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let deadline = request
+            .extensions()
+            .get::<Deadline>()
+            .copied()
+            .unwrap_or_default();
+
+        ready(Ok(deadline))
+    }
+}
+
+pub struct RequestTimeout {
+    timeouts: Arc<Timeouts>,
+}
+
+impl RequestTimeout {
+    pub fn new(timeouts: Arc<Timeouts>) -> Self {
+        RequestTimeout { timeouts }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTimeout
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestTimeoutMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTimeoutMiddleware {
+            service: Rc::new(service),
+            timeouts: self.timeouts.clone(),
+        }))
+    }
+}
+
+pub struct RequestTimeoutMiddleware<S> {
+    service: Rc<S>,
+    timeouts: Arc<Timeouts>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTimeoutMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    // Streamed responses are only timed until their head, the backend ends
+    // the stream itself once the deadline passes.
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let method = request.method().to_string();
+        let route = request
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        let service = self.service.clone();
+
+        let Some(timeout) = self.timeouts.timeout(&method, &route) else {
+            return Box::pin(async move { service.call(request).await });
+        };
+
+        let deadline = Instant::now() + timeout;
+        request.extensions_mut().insert(Deadline(Some(deadline)));
+
+        Box::pin(async move {
+            timeout_at(deadline.into(), service.call(request))
+                .await
+                .unwrap_or_else(|_| {
+                    warn!(%method, %route, "Request timed out after {:?}.", timeout);
+                    Err(ErrorGatewayTimeout("Request timed out."))
+                })
+        })
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    io::{self, ErrorKind},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use openssl::{
+    ssl::{
+        SniError, SslAcceptor, SslAcceptorBuilder, SslContext, SslFiletype, SslMethod, SslVersion,
+    },
+    x509::X509,
+};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+use tracing::{info, warn};
+
+use kv_common::tls::Reload;
+pub use kv_common::tls::TlsVersion;
+
+use crate::config;
+
+fn ssl_version(version: TlsVersion) -> SslVersion {
+    match version {
+        TlsVersion::Tls12 => SslVersion::TLS1_2,
+        TlsVersion::Tls13 => SslVersion::TLS1_3,
+    }
+}
+
+// The acceptor handed to the server switches every new handshake to the
+// current context, so a reload applies to new connections while established
+// ones keep the certificate they started with.
+pub struct CertificateStore {
+    settings: config::ServerTls,
+    context: RwLock<SslContext>,
+}
+
+impl CertificateStore {
+    pub fn load(settings: &config::ServerTls) -> Result<Arc<Self>, io::Error> {
+        let context = acceptor(settings)?.build().into_context();
+        log_expiry(&settings.cert_file);
+
+        Ok(Arc::new(CertificateStore {
+            settings: settings.clone(),
+            context: RwLock::new(context),
+        }))
+    }
+
+    pub fn acceptor(self: &Arc<Self>) -> Result<SslAcceptorBuilder, io::Error> {
+        let mut builder = acceptor(&self.settings)?;
+        let store = self.clone();
+
+        // Called for every ClientHello, with or without SNI.
+        builder.set_servername_callback(move |ssl, _| {
+            let context = store
+                .context
+                .read()
+                .expect("Certificate store lock should not be poisoned.");
+
+            ssl.set_ssl_context(&context)
+                .map_err(|_| SniError::ALERT_FATAL)
+        });
+
+        Ok(builder)
+    }
+}
+
+impl Reload for CertificateStore {
+    fn reload(&self) -> Result<(), io::Error> {
+        let context = acceptor(&self.settings)?.build().into_context();
+
+        *self
+            .context
+            .write()
+            .expect("Certificate store lock should not be poisoned.") = context;
+
+        info!("Reloaded certificate from: {}.", &self.settings.cert_file);
+        log_expiry(&self.settings.cert_file);
+
+        Ok(())
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [&self.settings.cert_file, &self.settings.key_file]
+            .into_iter()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+fn log_expiry(cert_file: &str) {
+    let expiry = std::fs::read(cert_file)
+        .ok()
+        .and_then(|pem| X509::from_pem(&pem).ok())
+        .map(|certificate| certificate.not_after().to_string());
+
+    match expiry {
+        Some(expiry) => info!(
+            "Serving certificate from {} expiring at {}.",
+            cert_file, expiry
+        ),
+        None => warn!("Failed to read expiry of certificate: {}.", cert_file),
+    }
+}
+
+pub fn acceptor(settings: &config::ServerTls) -> Result<SslAcceptorBuilder, io::Error> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+
+    builder.set_min_proto_version(Some(ssl_version(settings.min_version)))?;
+
+    if let Some(cipher_list) = &settings.cipher_list {
+        builder
+            .set_cipher_list(cipher_list)
+            .map_err(|e| invalid_input("cipher_list", e))?;
+    }
+
+    if let Some(ciphersuites) = &settings.ciphersuites {
+        builder
+            .set_ciphersuites(ciphersuites)
+            .map_err(|e| invalid_input("ciphersuites", e))?;
+    }
+
+    builder
+        .set_private_key_file(&settings.key_file, SslFiletype::PEM)
+        .map_err(|e| invalid_input(&settings.key_file, e))?;
+    builder
+        .set_certificate_chain_file(&settings.cert_file)
+        .map_err(|e| invalid_input(&settings.cert_file, e))?;
+    builder.check_private_key()?;
+
+    Ok(builder)
+}
+
+// Read once when the backend channels are created, unlike the server
+// certificate these files aren't reloaded.
+pub fn client_config(settings: &config::ClientTls) -> Result<ClientTlsConfig, io::Error> {
+    let ca = std::fs::read_to_string(&settings.ca_file)?;
+
+    let mut tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca));
+
+    if let Some(domain_name) = &settings.domain_name {
+        tls = tls.domain_name(domain_name);
+    }
+
+    match (&settings.cert_file, &settings.key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            let cert = std::fs::read_to_string(cert_file)?;
+            let key = std::fs::read_to_string(key_file)?;
+
+            tls = tls.identity(Identity::from_pem(cert, key));
+        }
+        (None, None) => {}
+        _ => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "Both 'cert_file' and 'key_file' should be configured for a client identity.",
+            ))
+        }
+    }
+
+    Ok(tls)
+}
+
+fn invalid_input(setting: &str, error: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("Invalid tls setting '{}': {}", setting, error),
+    )
+}
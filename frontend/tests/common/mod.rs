@@ -0,0 +1,194 @@
+// Shared by the test binaries, each of which uses only part of it.
+#![allow(dead_code)]
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use tonic::{metadata::MetadataMap, Request, Response, Status};
+
+use backend_server::{
+    kv_server::Kv, CompareAndSwapRequest, CompareAndSwapResponse, DeleteValueRequest,
+    DeleteValueResponse, GetValueRequest, GetValueResponse, InsertValueRequest,
+    InsertValueResponse, ScanRequest, ScanResponse, WatchEvent, WatchRequest,
+};
+
+pub mod backend_server {
+    tonic::include_proto!("kv");
+}
+
+type WatchStream = tokio_stream::Empty<Result<WatchEvent, Status>>;
+type Reply<T> = Pin<Box<dyn Future<Output = Result<T, Status>> + Send>>;
+type Handler<Req, Res> = Arc<dyn Fn(Request<Req>) -> Reply<Res> + Send + Sync>;
+
+/// Answers each method through its handler, by default like a store holding only `key1`,
+/// and records the metadata of every call it receives.
+#[derive(Clone)]
+pub struct MockBackend {
+    insert: Handler<InsertValueRequest, InsertValueResponse>,
+    get: Handler<GetValueRequest, GetValueResponse>,
+    delete: Handler<DeleteValueRequest, DeleteValueResponse>,
+    calls: Arc<Mutex<Vec<(&'static str, MetadataMap)>>>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        MockBackend {
+            insert: Arc::new(|_| Box::pin(async { Ok(inserted()) })),
+            get: Arc::new(|request| {
+                Box::pin(async move {
+                    match request.get_ref().key.as_str() {
+                        "key1" => Ok(value("value1")),
+                        key => Err(Status::not_found(format!(
+                            "Value for key: {} not found.",
+                            key
+                        ))),
+                    }
+                })
+            }),
+            delete: Arc::new(|request| {
+                Box::pin(async move {
+                    match request.get_ref().key.as_str() {
+                        "key1" => Ok(DeleteValueResponse { success: true }),
+                        key => Err(Status::not_found(format!(
+                            "Value for key: {} not found.",
+                            key
+                        ))),
+                    }
+                })
+            }),
+            calls: Arc::default(),
+        }
+    }
+}
+
+impl MockBackend {
+    pub fn on_insert<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Request<InsertValueRequest>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<InsertValueResponse, Status>> + Send + 'static,
+    {
+        self.insert = Arc::new(move |request| Box::pin(handler(request)));
+        self
+    }
+
+    pub fn on_get<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Request<GetValueRequest>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<GetValueResponse, Status>> + Send + 'static,
+    {
+        self.get = Arc::new(move |request| Box::pin(handler(request)));
+        self
+    }
+
+    pub fn on_delete<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Request<DeleteValueRequest>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<DeleteValueResponse, Status>> + Send + 'static,
+    {
+        self.delete = Arc::new(move |request| Box::pin(handler(request)));
+        self
+    }
+
+    // Reads every key under its own name and deletes every key.
+    pub fn named(name: &'static str) -> Self {
+        MockBackend::default()
+            .on_get(move |_| async move { Ok(value(name)) })
+            .on_delete(|_| async { Ok(DeleteValueResponse { success: true }) })
+    }
+
+    pub fn unavailable() -> Self {
+        MockBackend::default()
+            .on_insert(|_| async { Err(Status::unavailable("Storage is unavailable.")) })
+            .on_get(|_| async { Err(Status::unavailable("Storage is unavailable.")) })
+            .on_delete(|_| async { Err(Status::unavailable("Storage is unavailable.")) })
+    }
+
+    pub fn calls(&self, method: &str) -> usize {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(called, _)| *called == method)
+            .count()
+    }
+
+    // Values of the metadata entry sent with each call of the method.
+    pub fn sent(&self, method: &str, key: &str) -> Vec<String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(called, _)| *called == method)
+            .filter_map(|(_, metadata)| metadata.get(key)?.to_str().ok().map(str::to_string))
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.calls.lock().unwrap().clear();
+    }
+
+    fn record(&self, method: &'static str, metadata: &MetadataMap) {
+        self.calls.lock().unwrap().push((method, metadata.clone()));
+    }
+}
+
+pub fn inserted() -> InsertValueResponse {
+    InsertValueResponse {
+        success: true,
+        version: 1,
+    }
+}
+
+pub fn value(value: &str) -> GetValueResponse {
+    GetValueResponse {
+        value: value.to_string(),
+        version: 1,
+    }
+}
+
+#[tonic::async_trait]
+impl Kv for MockBackend {
+    type WatchStream = WatchStream;
+
+    async fn insert_value(
+        &self,
+        request: Request<InsertValueRequest>,
+    ) -> Result<Response<InsertValueResponse>, Status> {
+        self.record("insert_value", request.metadata());
+        (self.insert)(request).await.map(Response::new)
+    }
+
+    async fn get_value(
+        &self,
+        request: Request<GetValueRequest>,
+    ) -> Result<Response<GetValueResponse>, Status> {
+        self.record("get_value", request.metadata());
+        (self.get)(request).await.map(Response::new)
+    }
+
+    async fn delete_value(
+        &self,
+        request: Request<DeleteValueRequest>,
+    ) -> Result<Response<DeleteValueResponse>, Status> {
+        self.record("delete_value", request.metadata());
+        (self.delete)(request).await.map(Response::new)
+    }
+
+    async fn compare_and_swap(
+        &self,
+        _: Request<CompareAndSwapRequest>,
+    ) -> Result<Response<CompareAndSwapResponse>, Status> {
+        Err(Status::unimplemented("CompareAndSwap is not supported."))
+    }
+
+    async fn scan(&self, _: Request<ScanRequest>) -> Result<Response<ScanResponse>, Status> {
+        Err(Status::unimplemented("Scan is not supported."))
+    }
+
+    async fn watch(&self, _: Request<WatchRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        Err(Status::unimplemented("Watch is not supported."))
+    }
+}
@@ -1,65 +1,219 @@
-use backend_server::{
-    kv_server::Kv, kv_server::KvServer, GetValueRequest, GetValueResponse, InsertValueRequest,
-    InsertValueResponse,
+mod common;
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use common::{
+    backend_server::{
+        admin_server::{Admin, AdminServer},
+        import_chunk,
+        kv_server::Kv,
+        kv_server::KvServer,
+        BackupChunk, BackupRequest, ExportChunk, ExportRequest, GetQuotaUsageRequest,
+        GetQuotaUsageResponse, ImportChunk, ImportError, ImportResponse, QueryAuditRequest,
+        QueryAuditResponse, Quota, SetQuotaRequest, SetQuotaResponse,
+    },
+    inserted, value, MockBackend,
+};
+use frontend::{
+    auth::hash_api_key,
+    client::{get_clients, KvClients},
+    config::{
+        ApiKey, Backend, BackendEndpoint, CircuitBreaker, ClientTls, EndpointRole, Jwt, RateLimit,
+        ReadBalance, Retry, ServerTls, Settings, Timeouts,
+    },
+    tls::TlsVersion,
 };
-use frontend::backend_server::kv_client::KvClient;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use openssl::{base64::encode_block, rsa::Rsa};
 use reqwest::StatusCode;
 use serde_json::json;
-use std::{net::TcpListener, time::Duration};
-use tokio::time::sleep;
-use tonic::{transport::Server, Request, Response, Status};
+use std::{
+    collections::HashSet,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Once,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    transport::{Channel, Identity, Server, ServerTlsConfig},
+    Request, Response, Status,
+};
+use tonic_health::{server::health_reporter, ServingStatus};
 
-pub mod backend_server {
-    tonic::include_proto!("kv");
+static TRACING: Once = Once::new();
+
+fn init_tracing() {
+    TRACING.call_once(|| {
+        let (subscriber, tracer_provider) =
+            frontend::telemetry::get_subscriber("frontend", "info", None).unwrap();
+        frontend::telemetry::init_subscriber(subscriber);
+        // Tracers stop recording once their provider is dropped.
+        std::mem::forget(tracer_provider);
+    });
 }
 
-#[derive(Default)]
-pub struct BackendService {}
+#[tokio::test]
+async fn livez_works() {
+    let address = spawn_app().await;
 
-#[tonic::async_trait]
-impl Kv for BackendService {
-    #[tracing::instrument(skip(self))]
-    async fn insert_value(
-        &self,
-        _: Request<InsertValueRequest>,
-    ) -> Result<Response<InsertValueResponse>, Status> {
-        return Ok(Response::new(InsertValueResponse { success: true }));
-    }
+    let client = reqwest::Client::new();
 
-    async fn get_value(
-        &self,
-        request: Request<GetValueRequest>,
-    ) -> Result<Response<GetValueResponse>, Status> {
-        let request = request.into_inner();
-
-        match request.key.as_str() {
-            "key1" => {
-                return Ok(Response::new(GetValueResponse {
-                    value: "value1".to_string(),
-                }));
-            }
-            _ => {
-                let status =
-                    Status::not_found(format!("Value for key: {} not found.", &request.key));
-                return Err(status);
-            }
-        }
-    }
+    let response = client
+        .get(format!("{}/livez", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert!(response.status().is_success());
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "alive");
 }
 
 #[tokio::test]
-async fn health_check_works() {
+async fn readyz_should_list_passing_checks() {
     let address = spawn_app().await;
 
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/health_check", address))
+        .get(format!("{}/readyz", address))
         .send()
         .await
         .expect("Request should be sent.");
 
-    assert!(response.status().is_success());
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ready");
+
+    let checks = body["checks"].as_array().unwrap();
+    let names: Vec<_> = checks.iter().map(|check| &check["name"]).collect();
+    assert_eq!(
+        names,
+        ["backend", "recent_rpc", "draining", "circuit_breaker"]
+    );
+    assert!(checks.iter().all(|check| check["status"] == "pass"));
+    assert!(checks.iter().all(|check| check["latency_ms"].is_f64()));
+}
+
+#[tokio::test]
+async fn readyz_should_return_503_when_backend_not_serving() {
+    let channel =
+        spawn_backend_with_status(MockBackend::default(), ServingStatus::NotServing).await;
+    let address = spawn_frontend(KvClients::new(channel)).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/readyz", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"][0]["status"], "fail");
+    assert_eq!(body["checks"][0]["detail"], "NOT_SERVING");
+}
+
+#[tokio::test]
+async fn readyz_should_return_503_when_backend_health_check_hangs() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Should bind to random port.");
+    let addr = listener.local_addr().unwrap();
+
+    // Accepts connections and never answers on them.
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect_lazy();
+    let mut settings = Settings::default();
+    settings.readiness.check_timeout_ms = 200;
+    let address = spawn_frontend_with_settings(KvClients::new(channel), settings).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/readyz", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"][0]["status"], "fail");
+    assert_eq!(body["checks"][0]["detail"], "Health check timed out.");
+}
+
+#[tokio::test]
+async fn readyz_should_return_503_after_failed_rpc() {
+    let channel = spawn_backend(MockBackend::unavailable()).await;
+    let address = spawn_frontend(KvClients::new(channel)).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()["retry-after"], "1");
+
+    let response = client
+        .get(format!("{}/readyz", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"][1]["name"], "recent_rpc");
+    assert_eq!(body["checks"][1]["status"], "fail");
+}
+
+#[tokio::test]
+async fn readyz_should_recover_without_traffic_once_failed_rpc_expires() {
+    let channel = spawn_backend(MockBackend::unavailable()).await;
+    let mut settings = Settings::default();
+    settings.readiness.recent_rpc_window_ms = 200;
+    let address = spawn_frontend_with_settings(KvClients::new(channel), settings).await;
+
+    let client = reqwest::Client::new();
+    let readyz = || client.get(format!("{}/readyz", address)).send();
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        readyz().await.unwrap().status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let response = readyz().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"][1]["name"], "recent_rpc");
+    assert_eq!(body["checks"][1]["status"], "pass");
 }
 
 #[tokio::test]
@@ -69,7 +223,7 @@ async fn insert_value_should_return_200() {
     let client = reqwest::Client::new();
 
     let response = client
-        .post(&format!("{}/", address))
+        .post(format!("{}/", address))
         .json(&json!({"key": "key1", "value": "value1"}))
         .send()
         .await
@@ -85,7 +239,7 @@ async fn insert_value_with_empty_key_should_return_400() {
     let client = reqwest::Client::new();
 
     let response = client
-        .post(&format!("{}/", address))
+        .post(format!("{}/", address))
         .json(&json!({"key": "", "value": "value1"}))
         .send()
         .await
@@ -104,7 +258,7 @@ async fn get_value_should_return_ok() {
     let value = "value1";
 
     let response = client
-        .get(&format!("{}/{}", address, key))
+        .get(format!("{}/{}", address, key))
         .send()
         .await
         .expect("Request should be sent.");
@@ -120,7 +274,7 @@ async fn get_value_with_invalid_key_should_return_404() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/invalid_key", address))
+        .get(format!("{}/invalid_key", address))
         .send()
         .await
         .expect("Request should be sent.");
@@ -128,24 +282,1528 @@ async fn get_value_with_invalid_key_should_return_404() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
-async fn spawn_app() -> String {
-    let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind to random port.");
-    let port = listener.local_addr().unwrap().port();
+#[tokio::test]
+async fn get_value_should_be_served_by_replica() {
+    let primary = MockBackend::named("primary");
+    let replica = MockBackend::named("replica");
+    let address = spawn_app_with_replicas(primary, vec![replica], ReadBalance::RoundRobin).await;
 
-    let addr = "[::1]:50052".parse().unwrap();
-    let svc = BackendService::default();
+    let client = reqwest::Client::new();
 
-    tokio::spawn(
-        Server::builder()
-            .add_service(KvServer::new(svc))
-            .serve(addr),
+    let response = client
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "replica");
+}
+
+#[tokio::test]
+async fn get_value_with_strong_consistency_should_be_served_by_primary() {
+    let primary = MockBackend::named("primary");
+    let replica = MockBackend::named("replica");
+    let address = spawn_app_with_replicas(primary, vec![replica], ReadBalance::RoundRobin).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .header("X-KV-Consistency", "strong")
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "primary");
+}
+
+#[tokio::test]
+async fn get_value_should_round_robin_between_replicas() {
+    let primary = MockBackend::named("primary");
+    let replicas = vec![
+        MockBackend::named("replica1"),
+        MockBackend::named("replica2"),
+    ];
+    let address = spawn_app_with_replicas(primary, replicas, ReadBalance::RoundRobin).await;
+
+    let client = reqwest::Client::new();
+
+    let mut values = Vec::new();
+    for _ in 0..4 {
+        let response = client
+            .get(format!("{}/key1", address))
+            .send()
+            .await
+            .expect("Request should be sent.");
+
+        values.push(response.text().await.unwrap());
+    }
+
+    assert_eq!(values, ["replica1", "replica2", "replica1", "replica2"]);
+}
+
+#[tokio::test]
+async fn get_value_should_skip_replica_with_open_breaker() {
+    let primary = spawn_backend(MockBackend::named("primary")).await;
+    let down = MockBackend::default()
+        .on_get(|_| async { Err(Status::unavailable("Storage is unavailable.")) });
+    let replicas = vec![
+        spawn_backend(down).await,
+        spawn_backend(MockBackend::named("replica2")).await,
+    ];
+    let kv_clients = KvClients::new(primary)
+        .with_replicas(replicas, ReadBalance::RoundRobin)
+        .with_circuit_breaker(&CircuitBreaker {
+            enabled: true,
+            failure_threshold: 1,
+            open_ms: 10_000,
+        });
+    let address = spawn_frontend(kv_clients).await;
+
+    let client = reqwest::Client::new();
+    let get = || async {
+        let response = client
+            .get(format!("{}/key1", address))
+            .send()
+            .await
+            .expect("Request should be sent.");
+        response.text().await.unwrap()
+    };
+
+    // Opens the first replica's breaker.
+    get().await;
+
+    let mut values = Vec::new();
+    for _ in 0..3 {
+        values.push(get().await);
+    }
+    assert_eq!(values, ["replica2", "replica2", "replica2"]);
+}
+
+#[tokio::test]
+async fn get_value_should_go_to_replica_with_least_outstanding_reads() {
+    let primary = MockBackend::named("primary");
+    let slow = MockBackend::default().on_get(|_| async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        Ok(value("slow"))
+    });
+    let fast = MockBackend::named("fast");
+    let address = spawn_app_with_replicas(
+        primary,
+        vec![slow.clone(), fast],
+        ReadBalance::LeastOutstanding,
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+
+    // Both replicas are idle, so the first read goes to the first one.
+    let pending = tokio::spawn(client.get(format!("{}/key1", address)).send());
+    let started = Instant::now();
+    while slow.calls("get_value") == 0 {
+        assert!(started.elapsed() < Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let mut values = Vec::new();
+    for _ in 0..3 {
+        let response = client
+            .get(format!("{}/key1", address))
+            .send()
+            .await
+            .expect("Request should be sent.");
+
+        values.push(response.text().await.unwrap());
+    }
+
+    assert_eq!(values, ["fast", "fast", "fast"]);
+    let response = pending.await.unwrap().expect("Request should be sent.");
+    assert_eq!(response.text().await.unwrap(), "slow");
+    assert_eq!(slow.calls("get_value"), 1);
+}
+
+#[tokio::test]
+async fn insert_value_should_only_be_sent_to_primary() {
+    let primary = MockBackend::named("primary");
+    let replica = MockBackend::named("replica");
+    let address = spawn_app_with_replicas(
+        primary.clone(),
+        vec![replica.clone()],
+        ReadBalance::LeastOutstanding,
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/", address))
+        .json(&json!({"key": "key1", "value": "value1"}))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert!(response.status().is_success());
+    assert_eq!(primary.calls("insert_value"), 1);
+    assert_eq!(replica.calls("insert_value"), 0);
+}
+
+#[tokio::test]
+async fn metrics_should_expose_request_counts_per_route() {
+    let address = spawn_app().await;
+
+    let client = reqwest::Client::new();
+
+    client
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    client
+        .get(format!("{}/invalid_key", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    let response = client
+        .get(format!("{}/metrics", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/{key}",status="200"} 1"#));
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/{key}",status="404"} 1"#));
+    assert!(body.contains(r#"http_request_duration_seconds_count{method="GET",route="/{key}"} 2"#));
+}
+
+#[tokio::test]
+async fn requests_over_route_limit_should_return_429() {
+    let channel = spawn_backend(MockBackend::default()).await;
+
+    let rate_limit = config::Config::builder()
+        .add_source(config::File::from_str(
+            r#"
+            enabled: true
+            routes:
+              - route: "/{key}"
+                method: GET
+                requests_per_second: 0.5
+                burst: 2
+            "#,
+            config::FileFormat::Yaml,
+        ))
+        .build()
+        .unwrap()
+        .try_deserialize::<RateLimit>()
+        .unwrap();
+    let mut settings = Settings {
+        rate_limit,
+        ..Settings::default()
+    };
+    settings.auth.enabled = true;
+    settings.auth.api_keys = [
+        ("job-a", "key-a"),
+        ("job-a", "rotated-key-a"),
+        ("job-b", "key-b"),
+    ]
+    .into_iter()
+    .map(|(name, key)| ApiKey {
+        name: name.to_string(),
+        key_hash: hash_api_key(key),
+    })
+    .collect();
+    let address = spawn_frontend_with_settings(KvClients::new(channel), settings).await;
+
+    let client = reqwest::Client::new();
+    let get = |token: &str| {
+        client
+            .get(format!("{}/key1", address))
+            .bearer_auth(token)
+            .send()
+    };
+
+    for _ in 0..2 {
+        assert_eq!(get("key-a").await.unwrap().status(), StatusCode::OK);
+    }
+
+    // Buckets belong to the principal, not to the token it was sent with.
+    for token in ["key-a", "rotated-key-a"] {
+        let response = get(token).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "2");
+    }
+
+    assert_eq!(get("key-b").await.unwrap().status(), StatusCode::OK);
+    assert_eq!(
+        get("made-up").await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    let response = client
+        .post(format!("{}/", address))
+        .bearer_auth("key-a")
+        .json(&json!({"key": "key1", "value": "value1"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = client
+        .get(format!("{}/metrics", address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains(r#"http_requests_throttled_total{method="GET",route="/{key}"} 2"#));
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/{key}",status="429"} 2"#));
+}
+
+#[tokio::test]
+async fn requests_over_their_timeout_should_return_504() {
+    let backend = MockBackend::default()
+        .on_get(|_| async {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            Ok(value("value1"))
+        })
+        .on_delete(|_| async { Err(Status::deadline_exceeded("Deadline exceeded.")) });
+    let channel = spawn_backend(backend.clone()).await;
+
+    let timeouts = config::Config::builder()
+        .add_source(config::File::from_str(
+            r#"
+            default_ms: 5000
+            routes:
+              - route: "/{key}"
+                method: GET
+                timeout_ms: 200
+            "#,
+            config::FileFormat::Yaml,
+        ))
+        .build()
+        .unwrap()
+        .try_deserialize::<Timeouts>()
+        .unwrap();
+    let settings = Settings {
+        timeouts,
+        ..Settings::default()
+    };
+    let address = spawn_frontend_with_settings(KvClients::new(channel), settings).await;
+
+    let client = reqwest::Client::new();
+    let start = Instant::now();
+    let response = client
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // The backend gets what's left of the route's timeout.
+    let grpc_timeout = backend
+        .sent("get_value", "grpc-timeout")
+        .pop()
+        .expect("Backend should receive a grpc-timeout.");
+    let micros: u64 = grpc_timeout.strip_suffix('u').unwrap().parse().unwrap();
+    assert!(micros > 0 && micros <= 200_000, "{}", grpc_timeout);
+
+    // Deadlines the backend reports as exceeded are timeouts too.
+    let response = client
+        .delete(format!("{}/key1", address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[tokio::test]
+async fn circuit_breaker_should_fail_fast_until_backend_recovers() {
+    let down = Arc::new(AtomicBool::new(true));
+    let backend = MockBackend::default().on_get({
+        let down = down.clone();
+        move |_| {
+            let unavailable = down.load(Ordering::SeqCst);
+            async move {
+                if unavailable {
+                    return Err(Status::unavailable("Storage is unavailable."));
+                }
+                Ok(value("value1"))
+            }
+        }
+    });
+    let channel = spawn_backend(backend.clone()).await;
+    let kv_clients = KvClients::new(channel).with_circuit_breaker(&CircuitBreaker {
+        enabled: true,
+        failure_threshold: 2,
+        open_ms: 300,
+    });
+    let address = spawn_frontend(kv_clients).await;
+
+    let client = reqwest::Client::new();
+    let get = || client.get(format!("{}/key1", address)).send();
+    let breaker_check = || async {
+        let report: serde_json::Value = client
+            .get(format!("{}/readyz", address))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        report["checks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|check| check["name"] == "circuit_breaker")
+            .unwrap()
+            .clone()
+    };
+
+    for _ in 0..2 {
+        let response = get().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    // Open, the backend isn't called.
+    let response = get().await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(2, backend.calls("get_value"));
+    let check = breaker_check().await;
+    assert_eq!("fail", check["status"]);
+    assert_eq!("primary open", check["detail"]);
+
+    let metrics = client
+        .get(format!("{}/metrics", address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains(r#"backend_circuit_state{backend="primary"} 2"#));
+    assert!(metrics.contains(r#"backend_circuit_rejected_total{backend="primary"} 1"#));
+
+    // Past `open_ms` the instance is ready again without any traffic, so it
+    // gets the calls that probe the backend.
+    down.store(false, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    let check = breaker_check().await;
+    assert_eq!("pass", check["status"]);
+    assert_eq!("primary open", check["detail"]);
+
+    // Half open, the probe finds the backend back and closes the breaker.
+    let response = get().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(3, backend.calls("get_value"));
+    assert_eq!("pass", breaker_check().await["status"]);
+}
+
+#[tokio::test]
+async fn unavailable_writes_should_be_retried_with_same_idempotency_key() {
+    // Fails the first insert with each idempotency key.
+    let backend = MockBackend::default().on_insert({
+        let seen = Arc::new(Mutex::new(HashSet::new()));
+        move |request| {
+            let key = request
+                .metadata()
+                .get("idempotency-key")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let retried = !seen.lock().unwrap().insert(key);
+            async move {
+                if !retried {
+                    return Err(Status::unavailable("Storage is unavailable."));
+                }
+                Ok(inserted())
+            }
+        }
+    });
+    let channel = spawn_backend(backend.clone()).await;
+    let retry = Retry {
+        enabled: true,
+        max_attempts: 3,
+        base_delay_ms: 10,
+        max_delay_ms: 50,
+    };
+    let address = spawn_frontend(KvClients::new(channel.clone()).with_retry(&retry)).await;
+
+    let client = reqwest::Client::new();
+    let insert = || {
+        client
+            .post(format!("{}/", address))
+            .json(&json!({"key": "key1", "value": "value1"}))
+    };
+
+    let response = insert()
+        .header("Idempotency-Key", "insert-1")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        backend.sent("insert_value", "idempotency-key"),
+        ["insert-1", "insert-1"]
     );
 
-    sleep(Duration::from_secs(1)).await;
+    // Without the header the request id keeps the frontend's retries apart
+    // from other requests.
+    backend.clear();
+    let response = insert().send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let sent = backend.sent("insert_value", "idempotency-key");
+    assert_eq!(2, sent.len());
+    assert!(!sent[0].is_empty());
+    assert_eq!(sent[0], sent[1]);
+
+    let response = insert().header("Idempotency-Key", "").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Without retries the failure is passed on.
+    let address = spawn_frontend(KvClients::new(channel)).await;
+    let response = client
+        .post(format!("{}/", address))
+        .header("Idempotency-Key", "insert-2")
+        .json(&json!({"key": "key1", "value": "value1"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()["retry-after"], "1");
+}
+
+#[tokio::test]
+async fn get_value_should_propagate_traceparent_to_backend() {
+    init_tracing();
+
+    let backend = MockBackend::default();
+    let channel = spawn_backend(backend.clone()).await;
+    let address = spawn_frontend(KvClients::new(channel)).await;
+
+    let client = reqwest::Client::new();
 
-    let kv_client = KvClient::connect("http://[::1]:50052").await.unwrap();
+    let response = client
+        .get(format!("{}/key1", address))
+        .header(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let traceparent = backend
+        .sent("get_value", "traceparent")
+        .pop()
+        .expect("Backend should receive traceparent.");
+
+    assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    assert!(!traceparent.contains("00f067aa0ba902b7"));
+
+    let request_id = backend
+        .sent("get_value", "x-request-id")
+        .pop()
+        .expect("Backend should receive the request id.");
+
+    assert!(uuid::Uuid::parse_str(&request_id).is_ok());
+}
+
+#[tokio::test]
+async fn get_value_without_api_key_should_return_401() {
+    let address = spawn_app_with_api_key("ci-job", "secret-key").await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+}
+
+#[tokio::test]
+async fn get_value_with_invalid_api_key_should_return_401() {
+    let address = spawn_app_with_api_key("ci-job", "secret-key").await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .bearer_auth("wrong-key")
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Bearer error="invalid_token""#
+    );
+}
+
+#[tokio::test]
+async fn requests_with_valid_api_key_should_be_served() {
+    let address = spawn_app_with_api_key("ci-job", "secret-key").await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .bearer_auth("secret-key")
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "value1");
+
+    let response = client
+        .post(format!("{}/", address))
+        .bearer_auth("secret-key")
+        .json(&json!({"key": "key1", "value": "value1"}))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn probes_and_metrics_should_not_require_api_key() {
+    let address = spawn_app_with_api_key("ci-job", "secret-key").await;
+
+    let client = reqwest::Client::new();
+
+    for path in ["livez", "readyz", "metrics"] {
+        let response = client
+            .get(format!("{}/{}", address, path))
+            .send()
+            .await
+            .expect("Request should be sent.");
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn get_value_with_valid_jwt_should_be_served() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_jwt(&idp).await;
+
+    let client = reqwest::Client::new();
+
+    let token = idp.token(json!({
+        "sub": "alice",
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "exp": now() + 300,
+        "kv_namespaces": ["team-a"],
+    }));
+
+    let response = client
+        .get(format!("{}/team-a:key1", address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn get_value_outside_permitted_namespaces_should_return_403() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_jwt(&idp).await;
+
+    let client = reqwest::Client::new();
+
+    let token = idp.token(json!({
+        "sub": "alice",
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "exp": now() + 300,
+        "kv_namespaces": ["team-a"],
+    }));
+
+    let response = client
+        .get(format!("{}/team-b:key1", address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = client
+        .post(format!("{}/", address))
+        .bearer_auth(&token)
+        .json(&json!({"key": "team-b:key1", "value": "value1"}))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn jwt_with_invalid_claims_should_return_401() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_jwt(&idp).await;
+
+    let client = reqwest::Client::new();
+
+    let test_cases = [
+        (
+            json!({"sub": "alice", "iss": ISSUER, "aud": "other", "exp": now() + 300}),
+            "wrong audience",
+        ),
+        (
+            json!({"sub": "alice", "iss": "https://other", "aud": AUDIENCE, "exp": now() + 300}),
+            "wrong issuer",
+        ),
+        (
+            json!({"sub": "alice", "iss": ISSUER, "aud": AUDIENCE, "exp": now() - 300}),
+            "expired",
+        ),
+    ];
+
+    for (claims, description) in test_cases {
+        let response = client
+            .get(format!("{}/key1", address))
+            .bearer_auth(idp.token(claims))
+            .send()
+            .await
+            .expect("Request should be sent.");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::UNAUTHORIZED,
+            "Token with {} should be rejected.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn jwt_signed_by_unknown_key_should_return_401() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_jwt(&idp).await;
+    let other_idp = TestIdentityProvider::new();
+
+    let client = reqwest::Client::new();
+
+    let token = other_idp.token(json!({
+        "sub": "mallory",
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "exp": now() + 300,
+    }));
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn jwt_signed_with_other_algorithm_than_its_key_should_return_401() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_jwt(&idp).await;
+
+    let token = idp.token_with_algorithm(
+        Algorithm::PS256,
+        json!({
+            "sub": "alice",
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": now() + 300,
+        }),
+    );
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/key1", address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn delete_value_should_return_204() {
+    let address = spawn_app().await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .delete(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn delete_value_with_invalid_key_should_return_404() {
+    let address = spawn_app().await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .delete(format!("{}/key2", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn policy_should_only_grant_rights_of_assigned_roles() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_policy(&idp, Some(POLICY_FILE)).await;
+
+    let client = reqwest::Client::new();
+
+    let token = idp.token(json!({
+        "sub": "alice",
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "exp": now() + 300,
+        "kv_namespaces": ["*"],
+        "roles": ["team-a-reader"],
+    }));
+
+    let response = client
+        .get(format!("{}/team-a:key1", address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let test_cases = [
+        (
+            client.get(format!("{}/team-b:key1", address)),
+            "read outside role",
+        ),
+        (
+            client
+                .post(format!("{}/", address))
+                .json(&json!({"key": "team-a:key1", "value": "value1"})),
+            "write without right",
+        ),
+        (
+            client.delete(format!("{}/team-a:key1", address)),
+            "delete without right",
+        ),
+    ];
+
+    for (request, description) in test_cases {
+        let response = request
+            .bearer_auth(&token)
+            .send()
+            .await
+            .expect("Request should be sent.");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::FORBIDDEN,
+            "Request with {} should be denied.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn jwt_principal_should_not_get_roles_bound_to_api_key_of_same_name() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_policy(&idp, Some(POLICY_FILE)).await;
+
+    let token = idp.token(json!({
+        "sub": "ci-job",
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "exp": now() + 300,
+        "kv_namespaces": ["*"],
+    }));
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/", address))
+        .bearer_auth(&token)
+        .json(&json!({"key": "team-a:key1", "value": "value1"}))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn admin_right_on_prefix_should_grant_every_right_below_it() {
+    let idp = TestIdentityProvider::new();
+    let address = spawn_app_with_policy(&idp, Some(POLICY_FILE)).await;
+
+    let client = reqwest::Client::new();
+
+    let token = idp.token(json!({
+        "sub": "bob",
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "exp": now() + 300,
+        "kv_namespaces": ["team-a"],
+        "roles": ["reports-admin"],
+    }));
+
+    let response = client
+        .delete(format!("{}/team-a:reports-q1", address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = client
+        .delete(format!("{}/team-a:key1", address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn requests_should_forward_principal_to_backend() {
+    let backend = MockBackend::named("primary");
+    let channel = spawn_backend(backend.clone()).await;
+
+    let mut settings = Settings::default();
+    settings.auth.enabled = true;
+    settings.auth.policy_file = Some(POLICY_FILE.to_string());
+    settings.auth.api_keys = vec![ApiKey {
+        name: "ci-job".to_string(),
+        key_hash: hash_api_key("secret-key"),
+    }];
+    let address = spawn_frontend_with_settings(KvClients::new(channel), settings).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/team-a:key1", address))
+        .bearer_auth("secret-key")
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(backend.sent("get_value", "x-kv-principal"), ["ci-job"]);
+}
+
+// Records the options and data of the last import.
+#[derive(Clone, Default)]
+pub struct AdminBackendService {
+    imported: Arc<Mutex<Option<ImportChunk>>>,
+}
+
+#[tonic::async_trait]
+impl Admin for AdminBackendService {
+    type ExportStream = tokio_stream::Iter<std::vec::IntoIter<Result<ExportChunk, Status>>>;
+    type BackupStream = tokio_stream::Iter<std::vec::IntoIter<Result<BackupChunk, Status>>>;
+
+    async fn query_audit(
+        &self,
+        _: Request<QueryAuditRequest>,
+    ) -> Result<Response<QueryAuditResponse>, Status> {
+        Err(Status::unimplemented("Not used in tests."))
+    }
+
+    async fn set_quota(
+        &self,
+        _: Request<SetQuotaRequest>,
+    ) -> Result<Response<SetQuotaResponse>, Status> {
+        Err(Status::unimplemented("Not used in tests."))
+    }
+
+    async fn get_quota_usage(
+        &self,
+        request: Request<GetQuotaUsageRequest>,
+    ) -> Result<Response<GetQuotaUsageResponse>, Status> {
+        Ok(Response::new(GetQuotaUsageResponse {
+            namespace: request.into_inner().namespace,
+            quota: Some(Quota {
+                max_keys: Some(100),
+                max_bytes: None,
+                max_value_bytes: Some(1024),
+            }),
+            keys: 3,
+            bytes: 42,
+        }))
+    }
+
+    async fn export(
+        &self,
+        request: Request<ExportRequest>,
+    ) -> Result<Response<Self::ExportStream>, Status> {
+        let prefix = request.into_inner().prefix;
+        let chunks = (1..=2)
+            .map(|index| ExportChunk {
+                data: format!(
+                    "{{\"key\":\"{}key{}\",\"value\":\"value\",\"version\":1}}\n",
+                    prefix, index
+                )
+                .into_bytes(),
+            })
+            .map(Ok)
+            .collect::<Vec<_>>();
+
+        Ok(Response::new(tokio_stream::iter(chunks)))
+    }
+
+    async fn import(
+        &self,
+        request: Request<tonic::Streaming<ImportChunk>>,
+    ) -> Result<Response<ImportResponse>, Status> {
+        let mut chunks = request.into_inner();
+        let mut imported = chunks.message().await?.unwrap_or_default();
+        while let Some(chunk) = chunks.message().await? {
+            imported.data.extend(chunk.data);
+        }
+
+        // Every line but the second is imported.
+        let lines = imported
+            .data
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .count() as u64;
+        let response = ImportResponse {
+            imported: lines - 1,
+            skipped: 0,
+            failed: 1,
+            errors: vec![ImportError {
+                line: 2,
+                key: "team-a:key2".to_string(),
+                message: "Quota exceeded.".to_string(),
+            }],
+        };
+        *self.imported.lock().unwrap() = Some(imported);
+
+        Ok(Response::new(response))
+    }
+
+    async fn backup(
+        &self,
+        _: Request<BackupRequest>,
+    ) -> Result<Response<Self::BackupStream>, Status> {
+        Err(Status::unimplemented("Not used in tests."))
+    }
+}
+
+#[tokio::test]
+async fn quota_usage_should_be_reported_to_namespace_admins() {
+    let address = spawn_app_with_admin(AdminBackendService::default()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/admin/quotas/team-a", address))
+        .bearer_auth("lead-key")
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap(),
+        json!({
+            "namespace": "team-a",
+            "quota": {"max_keys": 100, "max_bytes": null, "max_value_bytes": 1024},
+            "keys": 3,
+            "bytes": 42
+        })
+    );
+
+    let response = client
+        .get(format!("{}/admin/quotas/team-a", address))
+        .bearer_auth("ci-key")
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn export_and_import_should_stream_json_lines_for_namespace_admins() {
+    let admin = AdminBackendService::default();
+    let address = spawn_app_with_admin(admin.clone()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/admin/export?prefix=team-a:", address))
+        .bearer_auth("lead-key")
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    assert_eq!(
+        response.text().await.unwrap(),
+        "{\"key\":\"team-a:key1\",\"value\":\"value\",\"version\":1}\n\
+         {\"key\":\"team-a:key2\",\"value\":\"value\",\"version\":1}\n"
+    );
+
+    let body = "{\"key\": \"team-a:key1\", \"value\": \"value1\"}\n\
+                {\"key\": \"team-a:key2\", \"value\": \"value2\"}\n";
+    let response = client
+        .post(format!(
+            "{}/admin/import?prefix=team-a:&mode=skip-existing",
+            address
+        ))
+        .bearer_auth("lead-key")
+        .body(body)
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap(),
+        json!({
+            "imported": 1,
+            "skipped": 0,
+            "failed": 1,
+            "errors": [{"line": 2, "key": "team-a:key2", "error": "Quota exceeded."}]
+        })
+    );
+    let imported = admin.imported.lock().unwrap().take().unwrap();
+    assert_eq!("team-a:", imported.prefix);
+    assert_eq!(import_chunk::Mode::SkipExisting, imported.mode());
+    assert_eq!(body.as_bytes(), imported.data);
+
+    for request in [
+        client.get(format!("{}/admin/export?prefix=team-a:", address)),
+        client.post(format!("{}/admin/import", address)).body(body),
+    ] {
+        let response = request
+            .bearer_auth("ci-key")
+            .send()
+            .await
+            .expect("Request should be sent.");
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+async fn spawn_app_with_admin(admin: AdminBackendService) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Should bind to random port.");
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(KvServer::new(MockBackend::default()))
+            .add_service(AdminServer::new(admin))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let mut settings = Settings::default();
+    settings.auth.enabled = true;
+    settings.auth.policy_file = Some(POLICY_FILE.to_string());
+    settings.auth.api_keys = [("team-a-lead", "lead-key"), ("ci-job", "ci-key")]
+        .into_iter()
+        .map(|(name, key)| ApiKey {
+            name: name.to_string(),
+            key_hash: hash_api_key(key),
+        })
+        .collect();
+
+    spawn_frontend_with_settings(KvClients::new(channel), settings).await
+}
+
+#[tokio::test]
+async fn frontend_should_serve_https_with_configured_certificate() {
+    let channel = spawn_backend(MockBackend::default()).await;
+
+    let mut settings = Settings::default();
+    settings.frontend.tls = ServerTls {
+        enabled: true,
+        cert_file: "tests/fixtures/server.pem".to_string(),
+        key_file: "tests/fixtures/server-key.pem".to_string(),
+        min_version: TlsVersion::Tls13,
+        ..ServerTls::default()
+    };
+    let address = spawn_frontend_with_settings(KvClients::new(channel), settings).await;
+    let address = address.replace("http://127.0.0.1", "https://localhost");
+
+    let root = reqwest::Certificate::from_pem(&std::fs::read("tests/fixtures/server.pem").unwrap())
+        .unwrap();
+
+    let client = reqwest::Client::builder()
+        .add_root_certificate(root.clone())
+        .build()
+        .unwrap();
+
+    let response = client
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent over TLS 1.3.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let client = reqwest::Client::builder()
+        .add_root_certificate(root)
+        .max_tls_version(reqwest::tls::Version::TLS_1_2)
+        .build()
+        .unwrap();
+
+    let response = client.get(format!("{}/key1", address)).send().await;
+
+    assert!(response.is_err(), "TLS 1.2 handshake should be refused.");
+}
+
+#[tokio::test]
+async fn get_clients_should_connect_with_configured_client_identity() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind to random port.");
+    let port = listener.local_addr().unwrap().port();
+    listener.set_nonblocking(true).unwrap();
+    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+
+    let tls = ServerTlsConfig::new()
+        .identity(Identity::from_pem(
+            std::fs::read_to_string("tests/fixtures/server.pem").unwrap(),
+            std::fs::read_to_string("tests/fixtures/server-key.pem").unwrap(),
+        ))
+        .client_ca_root(tonic::transport::Certificate::from_pem(
+            std::fs::read_to_string("tests/fixtures/ca.pem").unwrap(),
+        ));
+
+    tokio::spawn(async move {
+        Server::builder()
+            .tls_config(tls)
+            .unwrap()
+            .add_service(KvServer::new(MockBackend::default()))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    let backend = Backend {
+        endpoints: vec![BackendEndpoint {
+            application_port: port,
+            host: "127.0.0.1".to_string(),
+            role: EndpointRole::Primary,
+        }],
+        tls: ClientTls {
+            enabled: true,
+            ca_file: "tests/fixtures/server.pem".to_string(),
+            cert_file: Some("tests/fixtures/client.pem".to_string()),
+            key_file: Some("tests/fixtures/client-key.pem".to_string()),
+            domain_name: Some("localhost".to_string()),
+        },
+        ..Backend::default()
+    };
+
+    let kv_clients = get_clients(&backend)
+        .await
+        .expect("Frontend should connect with its client certificate.");
+    let address = spawn_frontend(kv_clients).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rotated_certificate_should_be_served_to_new_connections() {
+    let directory = std::env::temp_dir().join(format!("kv-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let cert_file = directory.join("cert.pem");
+    let key_file = directory.join("key.pem");
+    std::fs::copy("tests/fixtures/server.pem", &cert_file).unwrap();
+    std::fs::copy("tests/fixtures/server-key.pem", &key_file).unwrap();
+
+    let channel = spawn_backend(MockBackend::default()).await;
+
+    let mut settings = Settings::default();
+    settings.frontend.tls = ServerTls {
+        enabled: true,
+        cert_file: cert_file.to_string_lossy().to_string(),
+        key_file: key_file.to_string_lossy().to_string(),
+        reload_interval_secs: 1,
+        ..ServerTls::default()
+    };
+    let address = spawn_frontend_with_settings(KvClients::new(channel), settings).await;
+    let address = address.replace("http://127.0.0.1", "https://localhost");
+
+    let client_trusting = |path: &str| {
+        reqwest::Client::builder()
+            .add_root_certificate(
+                reqwest::Certificate::from_pem(&std::fs::read(path).unwrap()).unwrap(),
+            )
+            .build()
+            .unwrap()
+    };
+
+    let established = client_trusting("tests/fixtures/server.pem");
+    let response = established
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Request should be sent.");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::copy("tests/fixtures/server-rotated-key.pem", &key_file).unwrap();
+    std::fs::copy("tests/fixtures/server-rotated.pem", &cert_file).unwrap();
+
+    let rotated = client_trusting("tests/fixtures/server-rotated.pem");
+    let mut served = false;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        if let Ok(response) = rotated.get(format!("{}/key1", address)).send().await {
+            assert_eq!(response.status(), StatusCode::OK);
+            served = true;
+            break;
+        }
+    }
+
+    assert!(
+        served,
+        "New connections should be served the rotated certificate."
+    );
+
+    let response = established
+        .get(format!("{}/key1", address))
+        .send()
+        .await
+        .expect("Established connection should be kept alive.");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+async fn spawn_backend<S>(service: S) -> Channel
+where
+    S: Kv,
+{
+    spawn_backend_with_status(service, ServingStatus::Serving).await
+}
+
+async fn spawn_backend_with_status<S>(service: S, status: ServingStatus) -> Channel
+where
+    S: Kv,
+{
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Should bind to random port.");
+    let addr = listener.local_addr().unwrap();
+
+    let (mut health_reporter, health_service) = health_reporter();
+    health_reporter.set_service_status("kv.KV", status).await;
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(health_service)
+            .add_service(KvServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+async fn spawn_app() -> String {
+    let channel = spawn_backend(MockBackend::default()).await;
+
+    spawn_frontend(KvClients::new(channel)).await
+}
+
+async fn spawn_app_with_api_key(name: &str, key: &str) -> String {
+    let channel = spawn_backend(MockBackend::default()).await;
+
+    let mut settings = Settings::default();
+    settings.auth.enabled = true;
+    settings.auth.api_keys = vec![ApiKey {
+        name: name.to_string(),
+        key_hash: hash_api_key(key),
+    }];
+
+    spawn_frontend_with_settings(KvClients::new(channel), settings).await
+}
+
+const ISSUER: &str = "https://idp.test";
+const POLICY_FILE: &str = "tests/fixtures/policy.yml";
+const AUDIENCE: &str = "kv-system";
+
+struct TestIdentityProvider {
+    kid: String,
+    private_key: Vec<u8>,
+    jwks: serde_json::Value,
+}
+
+impl TestIdentityProvider {
+    fn new() -> Self {
+        let rsa = Rsa::generate(2048).unwrap();
+        let kid = hex::encode(&rsa.n().to_vec()[..8]);
+
+        let jwks = json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": kid,
+                "alg": "RS256",
+                "use": "sig",
+                "n": base64_url(&rsa.n().to_vec()),
+                "e": base64_url(&rsa.e().to_vec()),
+            }]
+        });
+
+        TestIdentityProvider {
+            kid,
+            private_key: rsa.private_key_to_pem().unwrap(),
+            jwks,
+        }
+    }
+
+    fn token(&self, claims: serde_json::Value) -> String {
+        self.token_with_algorithm(Algorithm::RS256, claims)
+    }
+
+    fn token_with_algorithm(&self, algorithm: Algorithm, claims: serde_json::Value) -> String {
+        let mut header = Header::new(algorithm);
+        header.kid = Some(self.kid.clone());
+
+        encode(
+            &header,
+            &claims,
+            &EncodingKey::from_rsa_pem(&self.private_key).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn serve_jwks(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind to random port.");
+        let port = listener.local_addr().unwrap().port();
+        let jwks = self.jwks.clone();
+
+        let server = HttpServer::new(move || {
+            let jwks = jwks.clone();
+            App::new().route(
+                "/jwks.json",
+                web::get().to(move || {
+                    let jwks = jwks.clone();
+                    async move { HttpResponse::Ok().json(jwks) }
+                }),
+            )
+        })
+        .listen(listener)
+        .unwrap()
+        .run();
+
+        tokio::spawn(server);
+
+        format!("http://127.0.0.1:{}/jwks.json", port)
+    }
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    encode_block(bytes)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn spawn_app_with_jwt(idp: &TestIdentityProvider) -> String {
+    spawn_app_with_policy(idp, None).await
+}
+
+async fn spawn_app_with_policy(idp: &TestIdentityProvider, policy_file: Option<&str>) -> String {
+    let channel = spawn_backend(MockBackend::named("primary")).await;
+
+    let mut settings = Settings::default();
+    settings.auth.enabled = true;
+    settings.auth.policy_file = policy_file.map(str::to_string);
+    settings.auth.jwt = Some(Jwt {
+        issuer: ISSUER.to_string(),
+        audience: AUDIENCE.to_string(),
+        jwks_file: None,
+        jwks_url: Some(idp.serve_jwks()),
+        principal_claim: "sub".to_string(),
+        namespaces_claim: "kv_namespaces".to_string(),
+        roles_claim: "roles".to_string(),
+    });
+
+    spawn_frontend_with_settings(KvClients::new(channel), settings).await
+}
+
+async fn spawn_app_with_replicas(
+    primary: MockBackend,
+    replicas: Vec<MockBackend>,
+    read_balance: ReadBalance,
+) -> String {
+    let primary = spawn_backend(primary).await;
+
+    let mut replica_clients = Vec::new();
+    for replica in replicas {
+        replica_clients.push(spawn_backend(replica).await);
+    }
+
+    spawn_frontend(KvClients::new(primary).with_replicas(replica_clients, read_balance)).await
+}
+
+async fn spawn_frontend(kv_clients: KvClients) -> String {
+    spawn_frontend_with_settings(kv_clients, Settings::default()).await
+}
+
+async fn spawn_frontend_with_settings(kv_clients: KvClients, settings: Settings) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind to random port.");
+    let port = listener.local_addr().unwrap().port();
 
-    let server = frontend::run(listener, kv_client, None)
+    let server = frontend::run(listener, kv_clients, &settings)
         .await
         .expect("Frontend server should be initialized.");
 
@@ -0,0 +1,113 @@
+// Signals reach the whole test process, so shutdown tests live in their own
+// test binary where no other servers run.
+mod common;
+
+use common::{backend_server::kv_server::KvServer, MockBackend};
+use frontend::{
+    client::KvClients,
+    config::{Settings, Shutdown},
+};
+use reqwest::StatusCode;
+use std::{net::TcpListener, process::Command, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::sleep,
+};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+
+#[tokio::test]
+async fn sigterm_should_fail_readiness_and_drain_in_flight_requests() {
+    let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_url = format!("http://{}", backend.local_addr().unwrap());
+    tokio::spawn(
+        Server::builder()
+            .add_service(KvServer::new(MockBackend::default()))
+            .serve_with_incoming(TcpListenerStream::new(backend)),
+    );
+    let channel = Channel::from_shared(backend_url)
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let settings = Settings {
+        shutdown: Shutdown {
+            drain_delay_secs: 1,
+            timeout_secs: 10,
+        },
+        ..Settings::default()
+    };
+    let server = frontend::run(listener, KvClients::new(channel), &settings)
+        .await
+        .unwrap();
+    let server = tokio::spawn(server);
+
+    // The request is in flight until the rest of its body arrives.
+    let body = r#"{"key": "key1", "value": "value1"}"#;
+    let (head, rest) = body.split_at(10);
+    let mut request = TcpStream::connect(address).await.unwrap();
+    let headers = format!(
+        "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        address,
+        body.len()
+    );
+    request.write_all(headers.as_bytes()).await.unwrap();
+    request.write_all(head.as_bytes()).await.unwrap();
+    sleep(Duration::from_millis(200)).await;
+
+    terminate();
+    sleep(Duration::from_millis(200)).await;
+
+    // During the drain delay readiness fails but requests are still served.
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(0)
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("http://{}/readyz", address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    let report: serde_json::Value = response.json().await.unwrap();
+    let draining = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|check| check["name"] == "draining")
+        .unwrap();
+    assert_eq!("fail", draining["status"]);
+
+    // Afterwards new connections aren't served while the request goes on.
+    sleep(Duration::from_millis(1200)).await;
+    let refused = client
+        .get(format!("http://{}/livez", address))
+        .timeout(Duration::from_millis(500))
+        .send()
+        .await;
+    assert!(refused.is_err());
+
+    request.write_all(rest.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    request.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("Frontend should exit after draining.")
+        .unwrap()
+        .unwrap();
+}
+
+fn terminate() {
+    let status = Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
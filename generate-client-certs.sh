@@ -0,0 +1,16 @@
+#!/usr/bin/env bash
+# Creates a CA and the frontend's client certificate signed by it. None of
+# the files written here are committed, the CA key stays on this machine.
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+openssl req -newkey rsa:2048 -nodes -keyout ca-key.pem -x509 -days 365 -out ca.pem \
+    -subj "/C=PL/CN=kv-system CA" \
+    -addext "basicConstraints=critical,CA:TRUE" \
+    -addext "keyUsage=critical,keyCertSign,cRLSign"
+openssl req -newkey rsa:2048 -nodes -keyout client-key.pem -out client.csr -config client.conf
+openssl x509 -req -in client.csr -CA ca.pem -CAkey ca-key.pem -CAcreateserial -days 365 \
+    -out client.pem -extfile client.conf -extensions v3_client
+rm client.csr ca.srl
+chmod 600 ca-key.pem client-key.pem
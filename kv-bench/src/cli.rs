@@ -0,0 +1,155 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Api {
+    // Frontend HTTP API.
+    Http,
+    // Backend gRPC API.
+    Grpc,
+}
+
+impl Api {
+    fn default_url(&self) -> &'static str {
+        match self {
+            Api::Http => "https://localhost:8000",
+            Api::Grpc => "http://localhost:50051",
+        }
+    }
+}
+
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyDistribution {
+    Uniform,
+    Zipfian,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+/// Drives the frontend HTTP API or the backend gRPC API and reports
+/// throughput and latency percentiles.
+#[derive(Parser, Clone, Debug)]
+#[command(name = "kv-bench", version)]
+pub struct Args {
+    /// API to drive.
+    #[arg(long, value_enum, default_value_t = Api::Grpc)]
+    pub api: Api,
+
+    /// Address of the service, https://localhost:8000 for http and
+    /// http://localhost:50051 for grpc by default. An https URL enables TLS.
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// API key sent as bearer token.
+    #[arg(long, env = "KV_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+
+    /// CA certificate used to verify the service.
+    #[arg(long)]
+    pub ca_file: Option<PathBuf>,
+
+    /// Name expected in the service's certificate, for grpc.
+    #[arg(long)]
+    pub domain_name: Option<String>,
+
+    /// Client certificate for mutual TLS, for grpc.
+    #[arg(long, requires = "key_file")]
+    pub cert_file: Option<PathBuf>,
+
+    /// Key of the client certificate.
+    #[arg(long, requires = "cert_file")]
+    pub key_file: Option<PathBuf>,
+
+    /// Skip verifying the service's certificate, for http.
+    #[arg(long)]
+    pub insecure: bool,
+
+    /// Number of requests in flight.
+    #[arg(long, default_value_t = 16, value_parser = at_least_one)]
+    pub concurrency: usize,
+
+    /// Number of gRPC connections the requests are spread over.
+    #[arg(long, default_value_t = 1, value_parser = at_least_one)]
+    pub connections: usize,
+
+    /// How long to measure, e.g. 30s or 5m.
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    pub duration: Duration,
+
+    /// How long to run before measuring.
+    #[arg(long, default_value = "0s", value_parser = humantime::parse_duration)]
+    pub warmup: Duration,
+
+    /// Number of distinct keys.
+    #[arg(long, default_value_t = 10_000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub keys: u64,
+
+    /// Prefix of the keys, which also puts them in a namespace.
+    #[arg(long, default_value = "bench:")]
+    pub key_prefix: String,
+
+    /// How keys are picked.
+    #[arg(long, value_enum, default_value_t = KeyDistribution::Uniform)]
+    pub distribution: KeyDistribution,
+
+    /// Skew of the zipfian distribution, higher concentrates on fewer keys.
+    #[arg(long, default_value_t = 0.99, value_parser = positive)]
+    pub zipf_exponent: f64,
+
+    /// Size of written values in bytes.
+    #[arg(long, default_value_t = 100, value_parser = at_least_one)]
+    pub value_size: usize,
+
+    /// Fraction of requests that are reads, the rest are writes.
+    #[arg(long, default_value_t = 0.9, value_parser = ratio)]
+    pub read_ratio: f64,
+
+    /// Write every key once before measuring, so reads don't miss.
+    #[arg(long)]
+    pub preload: bool,
+
+    /// Report format.
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    pub output: Output,
+}
+
+impl Args {
+    pub fn url(&self) -> &str {
+        self.url
+            .as_deref()
+            .unwrap_or_else(|| self.api.default_url())
+            .trim_end_matches('/')
+    }
+}
+
+fn at_least_one(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(error) => Err(format!("{}", error)),
+    }
+}
+
+fn positive(value: &str) -> Result<f64, String> {
+    match value.parse() {
+        Ok(value) if value > 0.0 => Ok(value),
+        Ok(_) => Err("must be positive".to_string()),
+        Err(error) => Err(format!("{}", error)),
+    }
+}
+
+fn ratio(value: &str) -> Result<f64, String> {
+    match value.parse() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        Ok(_) => Err("must be between 0 and 1".to_string()),
+        Err(error) => Err(format!("{}", error)),
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+use tokio::task::JoinSet;
+
+use crate::cli::Args;
+use crate::report::{Recorder, Report};
+use crate::target::Target;
+use crate::workload::{Operation, Workload};
+
+pub mod cli;
+pub mod report;
+pub mod target;
+pub mod workload;
+
+pub mod backend_server {
+    tonic::include_proto!("kv");
+}
+
+pub async fn run(args: &Args) -> Result<Report, Box<dyn std::error::Error>> {
+    let workload = Arc::new(Workload::new(args)?);
+    let target = Target::connect(args).await?;
+
+    if args.preload {
+        eprintln!("Preloading {} keys.", args.keys);
+        preload(&target, &workload, args.concurrency).await?;
+    }
+
+    if !args.warmup.is_zero() {
+        eprintln!("Warming up for {:?}.", args.warmup);
+        drive(&target, &workload, args.concurrency, args.warmup).await;
+    }
+
+    eprintln!("Running for {:?}.", args.duration);
+    let start = Instant::now();
+    let (reads, writes) = drive(&target, &workload, args.concurrency, args.duration).await;
+
+    Ok(Report::new(args, start.elapsed(), &reads, &writes))
+}
+
+// Writes every key once, task n writing keys n, n + concurrency and so on.
+async fn preload(
+    target: &Target,
+    workload: &Arc<Workload>,
+    concurrency: usize,
+) -> Result<(), String> {
+    let keys = workload.keys();
+    let mut tasks = JoinSet::new();
+
+    for task in 0..concurrency {
+        let target = target.clone();
+        let workload = workload.clone();
+
+        tasks.spawn(async move {
+            for index in (task as u64..keys).step_by(concurrency) {
+                target
+                    .execute(task, workload.write(index))
+                    .await
+                    .map_err(|error| format!("Preloading key {} failed: {}", index, error))?;
+            }
+
+            Ok::<_, String>(())
+        });
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        result.map_err(|error| error.to_string())??;
+    }
+
+    Ok(())
+}
+
+// Runs `concurrency` tasks that each send one request after another until
+// the duration is over, returning the merged read and write results.
+async fn drive(
+    target: &Target,
+    workload: &Arc<Workload>,
+    concurrency: usize,
+    duration: Duration,
+) -> (Recorder, Recorder) {
+    let deadline = Instant::now() + duration;
+    let mut tasks = JoinSet::new();
+
+    for task in 0..concurrency {
+        let target = target.clone();
+        let workload = workload.clone();
+
+        tasks.spawn(async move {
+            let mut rng = StdRng::from_entropy();
+            let mut reads = Recorder::default();
+            let mut writes = Recorder::default();
+
+            while Instant::now() < deadline {
+                let operation = workload.next(&mut rng);
+                let recorder = match operation {
+                    Operation::Read(_) => &mut reads,
+                    Operation::Write(_, _) => &mut writes,
+                };
+
+                let start = Instant::now();
+                let result = target.execute(task, operation).await;
+                recorder.record(start.elapsed(), result);
+            }
+
+            (reads, writes)
+        });
+    }
+
+    let mut reads = Recorder::default();
+    let mut writes = Recorder::default();
+    while let Some(result) = tasks.join_next().await {
+        let (task_reads, task_writes) = result.expect("Benchmark task should not panic.");
+        reads.merge(task_reads);
+        writes.merge(task_writes);
+    }
+
+    (reads, writes)
+}
@@ -0,0 +1,17 @@
+use clap::Parser;
+
+use kv_bench::cli::{Args, Output};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let report = kv_bench::run(&args).await?;
+
+    match args.output {
+        Output::Text => print!("{}", report),
+        Output::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+
+use crate::cli::{Api, Args, KeyDistribution};
+use crate::target::Outcome;
+
+// Latencies are recorded in microseconds with 3 significant digits.
+fn histogram() -> Histogram<u64> {
+    Histogram::new(3).expect("Histogram precision should be valid.")
+}
+
+// Results of one kind of operation, merged over all tasks at the end.
+pub struct Recorder {
+    latency: Histogram<u64>,
+    not_found: u64,
+    errors: BTreeMap<String, u64>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder {
+            latency: histogram(),
+            not_found: 0,
+            errors: BTreeMap::new(),
+        }
+    }
+}
+
+impl Recorder {
+    // Failed requests are counted but kept out of the latency histogram, a
+    // fast rejection would otherwise look like a fast response.
+    pub fn record(&mut self, elapsed: Duration, result: Result<Outcome, String>) {
+        match result {
+            Ok(outcome) => {
+                self.latency.saturating_record(elapsed.as_micros() as u64);
+                if outcome == Outcome::NotFound {
+                    self.not_found += 1;
+                }
+            }
+            Err(error) => *self.errors.entry(error).or_default() += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: Recorder) {
+        self.latency
+            .add(&other.latency)
+            .expect("Histograms should auto resize.");
+        self.not_found += other.not_found;
+        for (error, count) in other.errors {
+            *self.errors.entry(error).or_default() += count;
+        }
+    }
+
+    fn summary(&self, elapsed: Duration) -> Summary {
+        let latency = &self.latency;
+        let errors = self.errors.values().sum();
+        let operations = latency.len() + errors;
+
+        Summary {
+            operations,
+            throughput: operations as f64 / elapsed.as_secs_f64(),
+            not_found: self.not_found,
+            errors,
+            error_kinds: self.errors.clone(),
+            latency_us: Latency {
+                min: latency.min(),
+                mean: latency.mean(),
+                p50: latency.value_at_quantile(0.5),
+                p90: latency.value_at_quantile(0.9),
+                p99: latency.value_at_quantile(0.99),
+                p999: latency.value_at_quantile(0.999),
+                max: latency.max(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Latency {
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Summary {
+    pub operations: u64,
+    pub throughput: f64,
+    pub not_found: u64,
+    pub errors: u64,
+    pub error_kinds: BTreeMap<String, u64>,
+    pub latency_us: Latency,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Workload {
+    pub api: Api,
+    pub url: String,
+    pub concurrency: usize,
+    pub connections: usize,
+    pub keys: u64,
+    pub distribution: KeyDistribution,
+    pub value_size: usize,
+    pub read_ratio: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub workload: Workload,
+    pub duration_seconds: f64,
+    pub operations: u64,
+    pub throughput: f64,
+    pub reads: Summary,
+    pub writes: Summary,
+}
+
+impl Report {
+    pub fn new(args: &Args, elapsed: Duration, reads: &Recorder, writes: &Recorder) -> Self {
+        let reads = reads.summary(elapsed);
+        let writes = writes.summary(elapsed);
+        let operations = reads.operations + writes.operations;
+
+        Report {
+            workload: Workload {
+                api: args.api,
+                url: args.url().to_string(),
+                concurrency: args.concurrency,
+                connections: args.connections,
+                keys: args.keys,
+                distribution: args.distribution,
+                value_size: args.value_size,
+                read_ratio: args.read_ratio,
+            },
+            duration_seconds: elapsed.as_secs_f64(),
+            operations,
+            throughput: operations as f64 / elapsed.as_secs_f64(),
+            reads,
+            writes,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let workload = &self.workload;
+
+        writeln!(
+            f,
+            "{:?} {}, concurrency {}, {} keys ({:?}), {} byte values, {:.0}% reads",
+            workload.api,
+            workload.url,
+            workload.concurrency,
+            workload.keys,
+            workload.distribution,
+            workload.value_size,
+            workload.read_ratio * 100.0
+        )?;
+        writeln!(
+            f,
+            "{} operations in {:.2}s, {:.1} ops/sec",
+            self.operations, self.duration_seconds, self.throughput
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<7} {:>10} {:>12} {:>8} {:>8} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            "",
+            "ops",
+            "ops/sec",
+            "errors",
+            "misses",
+            "p50 us",
+            "p90 us",
+            "p99 us",
+            "p99.9 us",
+            "max us"
+        )?;
+
+        for (name, summary) in [("reads", &self.reads), ("writes", &self.writes)] {
+            let latency = &summary.latency_us;
+            writeln!(
+                f,
+                "{:<7} {:>10} {:>12.1} {:>8} {:>8} {:>9} {:>9} {:>9} {:>9} {:>9}",
+                name,
+                summary.operations,
+                summary.throughput,
+                summary.errors,
+                summary.not_found,
+                latency.p50,
+                latency.p90,
+                latency.p99,
+                latency.p999,
+                latency.max
+            )?;
+        }
+
+        for (name, summary) in [("reads", &self.reads), ("writes", &self.writes)] {
+            for (error, count) in &summary.error_kinds {
+                writeln!(f, "{} failed with {}: {}", name, error, count)?;
+            }
+        }
+
+        Ok(())
+    }
+}
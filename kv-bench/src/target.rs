@@ -0,0 +1,188 @@
+use std::{fs, io};
+
+use reqwest::StatusCode;
+use serde::Serialize;
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity},
+    Code, Request,
+};
+
+use crate::backend_server::{kv_client::KvClient, GetValueRequest, InsertValueRequest};
+use crate::cli::{Api, Args};
+use crate::workload::Operation;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    NotFound,
+}
+
+#[derive(Serialize)]
+struct KV<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+#[derive(Clone)]
+pub enum Target {
+    Grpc {
+        clients: Vec<KvClient<Channel>>,
+        authorization: Option<MetadataValue<Ascii>>,
+    },
+    Http {
+        client: reqwest::Client,
+        url: String,
+        api_key: Option<String>,
+    },
+}
+
+impl Target {
+    pub async fn connect(args: &Args) -> Result<Self, Box<dyn std::error::Error>> {
+        match args.api {
+            Api::Grpc => {
+                let mut endpoint = Endpoint::from_shared(args.url().to_string())?;
+                if args.url().starts_with("https://") {
+                    endpoint = endpoint.tls_config(grpc_tls(args)?)?;
+                }
+
+                // Each connect opens a separate HTTP/2 connection.
+                let mut clients = Vec::with_capacity(args.connections);
+                for _ in 0..args.connections {
+                    clients.push(KvClient::new(endpoint.connect().await?));
+                }
+
+                let authorization = args
+                    .api_key
+                    .as_ref()
+                    .map(|api_key| format!("Bearer {}", api_key).parse())
+                    .transpose()?;
+
+                Ok(Target::Grpc {
+                    clients,
+                    authorization,
+                })
+            }
+            Api::Http => {
+                let mut builder = reqwest::Client::builder()
+                    .danger_accept_invalid_certs(args.insecure)
+                    .pool_max_idle_per_host(args.concurrency);
+
+                if let Some(ca_file) = &args.ca_file {
+                    builder = builder
+                        .add_root_certificate(reqwest::Certificate::from_pem(&fs::read(ca_file)?)?);
+                }
+
+                Ok(Target::Http {
+                    client: builder.build()?,
+                    url: args.url().to_string(),
+                    api_key: args.api_key.clone(),
+                })
+            }
+        }
+    }
+
+    // Errors are short descriptions used to group failures in the report,
+    // such as a gRPC code or an HTTP status.
+    pub async fn execute(
+        &self,
+        connection: usize,
+        operation: Operation,
+    ) -> Result<Outcome, String> {
+        match self {
+            Target::Grpc {
+                clients,
+                authorization,
+            } => {
+                let mut client = clients[connection % clients.len()].clone();
+
+                let result = match operation {
+                    Operation::Read(key) => client
+                        .get_value(authorized(GetValueRequest { key }, authorization))
+                        .await
+                        .map(|_| ()),
+                    Operation::Write(key, value) => client
+                        .insert_value(authorized(
+                            InsertValueRequest {
+                                key,
+                                value,
+                                ..Default::default()
+                            },
+                            authorization,
+                        ))
+                        .await
+                        .map(|_| ()),
+                };
+
+                match result {
+                    Ok(()) => Ok(Outcome::Ok),
+                    Err(status) if status.code() == Code::NotFound => Ok(Outcome::NotFound),
+                    Err(status) => Err(format!("grpc {:?}", status.code())),
+                }
+            }
+            Target::Http {
+                client,
+                url,
+                api_key,
+            } => {
+                let request = match &operation {
+                    Operation::Read(key) => client.get(format!("{}/{}", url, key)),
+                    Operation::Write(key, value) => {
+                        client.post(format!("{}/", url)).json(&KV { key, value })
+                    }
+                };
+                let request = match api_key {
+                    Some(api_key) => request.bearer_auth(api_key),
+                    None => request,
+                };
+
+                let response = request.send().await.map_err(|error| {
+                    if error.is_timeout() {
+                        "http timeout".to_string()
+                    } else {
+                        "http connection error".to_string()
+                    }
+                })?;
+                let status = response.status();
+                // Drain the body so the connection can be reused.
+                let _ = response.bytes().await;
+
+                match status {
+                    status if status.is_success() => Ok(Outcome::Ok),
+                    StatusCode::NOT_FOUND => Ok(Outcome::NotFound),
+                    status => Err(format!("http {}", status.as_u16())),
+                }
+            }
+        }
+    }
+}
+
+fn authorized<T>(message: T, authorization: &Option<MetadataValue<Ascii>>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(authorization) = authorization {
+        request
+            .metadata_mut()
+            .insert("authorization", authorization.clone());
+    }
+
+    request
+}
+
+fn grpc_tls(args: &Args) -> Result<ClientTlsConfig, io::Error> {
+    let mut tls = ClientTlsConfig::new();
+
+    if let Some(ca_file) = &args.ca_file {
+        tls = tls.ca_certificate(Certificate::from_pem(fs::read_to_string(ca_file)?));
+    }
+    if let Some(domain_name) = &args.domain_name {
+        tls = tls.domain_name(domain_name);
+    }
+    if let (Some(cert_file), Some(key_file)) = (&args.cert_file, &args.key_file) {
+        tls = tls.identity(Identity::from_pem(
+            fs::read_to_string(cert_file)?,
+            fs::read_to_string(key_file)?,
+        ));
+    }
+
+    Ok(tls)
+}
@@ -0,0 +1,77 @@
+use rand::{distributions::Alphanumeric, Rng};
+use rand_distr::{Distribution, Uniform, Zipf};
+
+use crate::cli::{Args, KeyDistribution};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Read(String),
+    Write(String, String),
+}
+
+enum Keys {
+    Uniform(Uniform<u64>),
+    // Rank 1 is the most popular key.
+    Zipfian(Zipf<f64>),
+}
+
+pub struct Workload {
+    distribution: Keys,
+    keys: u64,
+    key_prefix: String,
+    read_ratio: f64,
+    value: String,
+}
+
+impl Workload {
+    pub fn new(args: &Args) -> Result<Self, String> {
+        let distribution = match args.distribution {
+            KeyDistribution::Uniform => Keys::Uniform(Uniform::new(0, args.keys)),
+            KeyDistribution::Zipfian => Keys::Zipfian(
+                Zipf::new(args.keys, args.zipf_exponent)
+                    .map_err(|error| format!("Invalid zipfian distribution: {}", error))?,
+            ),
+        };
+
+        // Every write sends the same value, generating one per request would
+        // only measure the random number generator.
+        let value = rand::thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(args.value_size)
+            .map(char::from)
+            .collect();
+
+        Ok(Workload {
+            distribution,
+            keys: args.keys,
+            key_prefix: args.key_prefix.clone(),
+            read_ratio: args.read_ratio,
+            value,
+        })
+    }
+
+    pub fn keys(&self) -> u64 {
+        self.keys
+    }
+
+    pub fn key(&self, index: u64) -> String {
+        format!("{}{}", self.key_prefix, index)
+    }
+
+    pub fn write(&self, index: u64) -> Operation {
+        Operation::Write(self.key(index), self.value.clone())
+    }
+
+    pub fn next(&self, rng: &mut impl Rng) -> Operation {
+        let index = match &self.distribution {
+            Keys::Uniform(uniform) => uniform.sample(rng),
+            Keys::Zipfian(zipf) => zipf.sample(rng) as u64 - 1,
+        };
+
+        if rng.gen_bool(self.read_ratio) {
+            Operation::Read(self.key(index))
+        } else {
+            self.write(index)
+        }
+    }
+}
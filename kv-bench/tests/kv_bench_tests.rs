@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use backend::{
+    backend_server::kv_server::KvServer, config::Memory, eviction::EvictionPolicy, BackendService,
+};
+use clap::Parser;
+use kv_bench::{
+    cli::Args,
+    workload::{Operation, Workload},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+
+#[tokio::test]
+async fn grpc_benchmark_should_report_throughput_and_latency() {
+    let url = spawn_backend(BackendService::new()).await;
+    let args = Args::parse_from([
+        "kv-bench",
+        "--url",
+        &url,
+        "--duration",
+        "300ms",
+        "--concurrency",
+        "4",
+        "--connections",
+        "2",
+        "--keys",
+        "100",
+        "--distribution",
+        "zipfian",
+        "--read-ratio",
+        "0.5",
+        "--preload",
+    ]);
+
+    let report = kv_bench::run(&args).await.unwrap();
+
+    assert!(report.reads.operations > 0);
+    assert!(report.writes.operations > 0);
+    assert_eq!(
+        report.operations,
+        report.reads.operations + report.writes.operations
+    );
+    assert_eq!(0, report.reads.errors + report.writes.errors);
+    // Every key was written before measuring.
+    assert_eq!(0, report.reads.not_found);
+    assert!(report.reads.latency_us.p50 <= report.reads.latency_us.p99);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!("grpc", json["workload"]["api"]);
+    assert_eq!("zipfian", json["workload"]["distribution"]);
+    assert!(json["reads"]["latency_us"]["p999"].is_u64());
+    assert!(report.to_string().contains("ops/sec"));
+}
+
+#[tokio::test]
+async fn failed_requests_should_be_counted_by_kind() {
+    // Too small for a single 100 byte value.
+    let service = BackendService::new().with_memory_limit(&Memory {
+        max_bytes: Some(50),
+        eviction_policy: EvictionPolicy::None,
+    });
+    let url = spawn_backend(service).await;
+    let args = Args::parse_from([
+        "kv-bench",
+        "--url",
+        &url,
+        "--duration",
+        "200ms",
+        "--concurrency",
+        "2",
+        "--read-ratio",
+        "0.5",
+    ]);
+
+    let report = kv_bench::run(&args).await.unwrap();
+
+    assert!(report.writes.errors > 0);
+    assert_eq!(report.writes.operations, report.writes.errors);
+    assert_eq!(
+        Some(&report.writes.errors),
+        report.writes.error_kinds.get("grpc ResourceExhausted")
+    );
+    // Reads of keys that were never written are misses, not errors.
+    assert_eq!(0, report.reads.errors);
+    assert_eq!(report.reads.operations, report.reads.not_found);
+    assert!(report
+        .to_string()
+        .contains("writes failed with grpc ResourceExhausted"));
+}
+
+#[test]
+fn zipfian_keys_should_favour_low_ranks() {
+    let args = Args::parse_from([
+        "kv-bench",
+        "--keys",
+        "1000",
+        "--distribution",
+        "zipfian",
+        "--read-ratio",
+        "1",
+    ]);
+    let workload = Workload::new(&args).unwrap();
+    let mut rng = StdRng::seed_from_u64(7);
+
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    for _ in 0..10_000 {
+        match workload.next(&mut rng) {
+            Operation::Read(key) => *counts.entry(key).or_default() += 1,
+            Operation::Write(_, _) => panic!("Read ratio 1 should only read."),
+        }
+    }
+
+    let hottest = counts.iter().max_by_key(|(_, count)| **count).unwrap();
+    assert_eq!("bench:0", hottest.0);
+    assert!(*hottest.1 > 10_000 / 1000 * 10);
+    assert!(counts.keys().all(|key| {
+        let index: u64 = key.strip_prefix("bench:").unwrap().parse().unwrap();
+        index < 1000
+    }));
+}
+
+#[test]
+fn writes_should_carry_values_of_configured_size() {
+    let args = Args::parse_from(["kv-bench", "--value-size", "64", "--read-ratio", "0"]);
+    let workload = Workload::new(&args).unwrap();
+
+    match workload.next(&mut StdRng::seed_from_u64(7)) {
+        Operation::Write(_, value) => assert_eq!(64, value.len()),
+        Operation::Read(_) => panic!("Read ratio 0 should only write."),
+    }
+}
+
+#[test]
+fn invalid_arguments_should_be_rejected() {
+    assert!(Args::try_parse_from(["kv-bench", "--read-ratio", "1.5"]).is_err());
+    assert!(Args::try_parse_from(["kv-bench", "--concurrency", "0"]).is_err());
+    assert!(Args::try_parse_from(["kv-bench", "--keys", "0"]).is_err());
+    assert!(Args::try_parse_from(["kv-bench", "--cert-file", "client.pem"]).is_err());
+}
+
+async fn spawn_backend(service: BackendService) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(KvServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    format!("http://{}", addr)
+}
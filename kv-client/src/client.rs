@@ -0,0 +1,222 @@
+use std::{future::Future, time::Duration};
+
+use tokio::time::{sleep, timeout, Instant};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    transport::Channel,
+    Request, Response, Status,
+};
+
+use crate::backend_server::{
+    kv_client::KvClient, CompareAndSwapRequest, DeleteValueRequest, GetValueRequest,
+    InsertValueRequest, ScanRequest,
+};
+use crate::{ClientBuilder, Error, RetryPolicy};
+
+// Keys requested per Scan call, the backend caps pages at this size too.
+const SCAN_PAGE: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub version: u64,
+}
+
+// Cheap to clone, clones share the connection.
+#[derive(Clone, Debug)]
+pub struct Client {
+    kv: KvClient<Channel>,
+    authorization: Option<MetadataValue<Ascii>>,
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    pub fn builder(url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder::new(url.into())
+    }
+
+    pub(crate) fn new(channel: Channel, builder: ClientBuilder) -> Result<Self, Error> {
+        let authorization = builder
+            .api_key
+            .map(|api_key| format!("Bearer {}", api_key).parse())
+            .transpose()
+            .map_err(|_| Error::Config("API key is not valid metadata.".to_string()))?;
+
+        Ok(Client {
+            kv: KvClient::new(channel),
+            authorization,
+            timeout: builder.timeout,
+            retry: builder.retry,
+        })
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Entry>, Error> {
+        let request = GetValueRequest {
+            key: key.to_string(),
+        };
+        let reply = self
+            .call(request, |mut kv, request| async move {
+                kv.get_value(request).await
+            })
+            .await;
+
+        match reply {
+            Ok(response) => Ok(Some(Entry {
+                key: key.to_string(),
+                value: response.value,
+                version: response.version,
+            })),
+            Err(Error::NotFound(_)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Returns the version written.
+    pub async fn put(&self, key: &str, value: &str) -> Result<u64, Error> {
+        self.insert(key, value, 0).await
+    }
+
+    // Expires the value after `ttl`, rounded to seconds.
+    pub async fn put_with_ttl(&self, key: &str, value: &str, ttl: Duration) -> Result<u64, Error> {
+        self.insert(key, value, ttl.as_secs().max(1)).await
+    }
+
+    async fn insert(&self, key: &str, value: &str, ttl_seconds: u64) -> Result<u64, Error> {
+        let request = InsertValueRequest {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl_seconds,
+        };
+        let response = self
+            .call(request, |mut kv, request| async move {
+                kv.insert_value(request).await
+            })
+            .await?;
+
+        Ok(response.version)
+    }
+
+    // Returns whether the key existed.
+    pub async fn delete(&self, key: &str) -> Result<bool, Error> {
+        let request = DeleteValueRequest {
+            key: key.to_string(),
+        };
+        let reply = self
+            .call(request, |mut kv, request| async move {
+                kv.delete_value(request).await
+            })
+            .await;
+
+        match reply {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound(_)) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Writes `value` only if the key is at the `expected` version, or absent
+    // for None, and returns the version written. Fails with
+    // Error::VersionMismatch otherwise.
+    pub async fn cas(&self, key: &str, expected: Option<u64>, value: &str) -> Result<u64, Error> {
+        let request = CompareAndSwapRequest {
+            key: key.to_string(),
+            expected_version: expected.unwrap_or_default(),
+            value: value.to_string(),
+            ttl_seconds: 0,
+        };
+        let response = self
+            .call(request, |mut kv, request| async move {
+                kv.compare_and_swap(request).await
+            })
+            .await?;
+
+        Ok(response.version)
+    }
+
+    // Returns the keys starting with `prefix` the caller may read, in order
+    // and at most `limit` of them. Every page is a separate call with its own
+    // deadline.
+    pub async fn scan(&self, prefix: &str, limit: Option<usize>) -> Result<Vec<Entry>, Error> {
+        let mut entries = Vec::new();
+        let mut start_after = String::new();
+
+        loop {
+            let remaining = limit.map_or(SCAN_PAGE, |limit| (limit - entries.len()).min(SCAN_PAGE));
+            if remaining == 0 {
+                return Ok(entries);
+            }
+
+            let request = ScanRequest {
+                prefix: prefix.to_string(),
+                start_after,
+                limit: remaining as u32,
+            };
+            let response = self
+                .call(
+                    request,
+                    |mut kv, request| async move { kv.scan(request).await },
+                )
+                .await?;
+
+            entries.extend(
+                response
+                    .entries
+                    .into_iter()
+                    .take(remaining)
+                    .map(|entry| Entry {
+                        key: entry.key,
+                        value: entry.value,
+                        version: entry.version,
+                    }),
+            );
+
+            if response.next_start_after.is_empty() {
+                return Ok(entries);
+            }
+            start_after = response.next_start_after;
+        }
+    }
+
+    // Attempts share the client's timeout, so retries never extend a call
+    // past its deadline.
+    async fn call<T, R, F, Fut>(&self, message: T, rpc: F) -> Result<R, Error>
+    where
+        T: Clone,
+        F: Fn(KvClient<Channel>, Request<T>) -> Fut,
+        Fut: Future<Output = Result<Response<R>, Status>>,
+    {
+        let deadline = Instant::now() + self.timeout;
+        let mut attempt = 0;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::DeadlineExceeded);
+            }
+
+            let mut request = Request::new(message.clone());
+            request.set_timeout(remaining);
+            if let Some(authorization) = &self.authorization {
+                request
+                    .metadata_mut()
+                    .insert("authorization", authorization.clone());
+            }
+
+            let error = match timeout(remaining, rpc(self.kv.clone(), request)).await {
+                Ok(Ok(response)) => return Ok(response.into_inner()),
+                Ok(Err(status)) => Error::from(status),
+                Err(_) => Error::DeadlineExceeded,
+            };
+
+            attempt += 1;
+            if !error.is_retryable() || attempt >= self.retry.max_attempts {
+                return Err(error);
+            }
+
+            let backoff = self.retry.backoff(attempt - 1);
+            sleep(backoff.min(deadline.saturating_duration_since(Instant::now()))).await;
+        }
+    }
+}
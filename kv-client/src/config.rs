@@ -0,0 +1,172 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use rand::Rng;
+use tokio::time::sleep;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+
+use crate::{Client, Error};
+
+// Calls failing as unavailable are attempted again after a random delay of
+// up to initial_backoff, doubled for every further attempt up to max_backoff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    // Jittered so clients that failed together don't retry together.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+
+        rand::thread_rng().gen_range(Duration::ZERO..=ceiling)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    pub(crate) url: String,
+    pub(crate) api_key: Option<String>,
+    ca_file: Option<PathBuf>,
+    domain_name: Option<String>,
+    identity: Option<(PathBuf, PathBuf)>,
+    pub(crate) timeout: Duration,
+    connect_timeout: Duration,
+    pub(crate) retry: RetryPolicy,
+}
+
+impl ClientBuilder {
+    pub(crate) fn new(url: String) -> Self {
+        ClientBuilder {
+            url,
+            api_key: None,
+            ca_file: None,
+            domain_name: None,
+            identity: None,
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    // Sent as bearer token with every call.
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    // CA certificate verifying the backend, only used for https URLs.
+    pub fn ca_file(mut self, ca_file: impl Into<PathBuf>) -> Self {
+        self.ca_file = Some(ca_file.into());
+        self
+    }
+
+    pub fn domain_name(mut self, domain_name: impl Into<String>) -> Self {
+        self.domain_name = Some(domain_name.into());
+        self
+    }
+
+    // Client certificate and key for mutual TLS.
+    pub fn identity(mut self, cert_file: impl Into<PathBuf>, key_file: impl Into<PathBuf>) -> Self {
+        self.identity = Some((cert_file.into(), key_file.into()));
+        self
+    }
+
+    // Deadline of a call including its retries, sent to the backend as
+    // grpc-timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    // Connects right away, retrying by the retry policy.
+    pub async fn connect(self) -> Result<Client, Error> {
+        let endpoint = self.endpoint()?;
+        let mut attempt = 0;
+
+        loop {
+            match endpoint.connect().await {
+                Ok(channel) => return Client::new(channel, self),
+                Err(_) if attempt + 1 < self.retry.max_attempts => {
+                    sleep(self.retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                Err(error) => return Err(Error::Connect(error)),
+            }
+        }
+    }
+
+    // Connects on the first call instead, which fails as unavailable while
+    // the backend can't be reached.
+    pub fn connect_lazy(self) -> Result<Client, Error> {
+        let channel = self.endpoint()?.connect_lazy();
+
+        Client::new(channel, self)
+    }
+
+    fn endpoint(&self) -> Result<Endpoint, Error> {
+        let mut endpoint = Channel::from_shared(self.url.clone())
+            .map_err(|error| Error::Config(format!("Invalid URL {}: {}", self.url, error)))?
+            .connect_timeout(self.connect_timeout);
+
+        if self.url.starts_with("https://") {
+            endpoint = endpoint
+                .tls_config(self.tls()?)
+                .map_err(|error| Error::Config(error.to_string()))?;
+        }
+
+        Ok(endpoint)
+    }
+
+    fn tls(&self) -> Result<ClientTlsConfig, Error> {
+        let mut tls = ClientTlsConfig::new();
+
+        if let Some(ca_file) = &self.ca_file {
+            tls = tls.ca_certificate(Certificate::from_pem(read(ca_file)?));
+        }
+        if let Some(domain_name) = &self.domain_name {
+            tls = tls.domain_name(domain_name);
+        }
+        if let Some((cert_file, key_file)) = &self.identity {
+            tls = tls.identity(Identity::from_pem(read(cert_file)?, read(key_file)?));
+        }
+
+        Ok(tls)
+    }
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, Error> {
+    fs::read(path)
+        .map_err(|error| Error::Config(format!("Failed to read {}: {}", path.display(), error)))
+}
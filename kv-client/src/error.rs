@@ -0,0 +1,95 @@
+use std::fmt;
+
+use tonic::{Code, Status};
+use tonic_types::StatusExt;
+
+// Error info the backend attaches to failed compare-and-swaps.
+const VERSION_MISMATCH: &str = "VERSION_MISMATCH";
+const CURRENT_VERSION: &str = "current_version";
+
+#[derive(Debug)]
+pub enum Error {
+    Config(String),
+    Connect(tonic::transport::Error),
+    Unauthenticated(String),
+    PermissionDenied(String),
+    InvalidArgument(String),
+    NotFound(String),
+    // The key is at another version than expected, or absent for None.
+    VersionMismatch { current: Option<u64> },
+    // Over a namespace quota, the memory budget or a rate limit.
+    ResourceExhausted(String),
+    DeadlineExceeded,
+    Unavailable(String),
+    Other(Box<Status>),
+}
+
+impl Error {
+    // Unavailable calls usually never reached the backend, anything else
+    // would fail the same way again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::Unavailable(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message) => write!(f, "Invalid client configuration: {}", message),
+            Error::Connect(error) => match std::error::Error::source(error) {
+                Some(source) => write!(f, "Failed to connect: {}: {}", error, source),
+                None => write!(f, "Failed to connect: {}", error),
+            },
+            Error::Unauthenticated(message) => write!(f, "Unauthenticated: {}", message),
+            Error::PermissionDenied(message) => write!(f, "Permission denied: {}", message),
+            Error::InvalidArgument(message) => write!(f, "Invalid argument: {}", message),
+            Error::NotFound(message) => write!(f, "Not found: {}", message),
+            Error::VersionMismatch {
+                current: Some(version),
+            } => write!(f, "Version mismatch, the key is at version {}.", version),
+            Error::VersionMismatch { current: None } => {
+                write!(f, "Version mismatch, the key doesn't exist.")
+            }
+            Error::ResourceExhausted(message) => write!(f, "Resource exhausted: {}", message),
+            Error::DeadlineExceeded => write!(f, "Deadline exceeded."),
+            Error::Unavailable(message) => write!(f, "Unavailable: {}", message),
+            Error::Other(status) => write!(f, "{:?}: {}", status.code(), status.message()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Connect(error) => Some(error),
+            Error::Other(status) => Some(status.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        let message = status.message().to_string();
+
+        match status.code() {
+            Code::Unauthenticated => Error::Unauthenticated(message),
+            Code::PermissionDenied => Error::PermissionDenied(message),
+            Code::InvalidArgument => Error::InvalidArgument(message),
+            Code::NotFound => Error::NotFound(message),
+            Code::ResourceExhausted => Error::ResourceExhausted(message),
+            Code::DeadlineExceeded => Error::DeadlineExceeded,
+            Code::Unavailable => Error::Unavailable(message),
+            Code::FailedPrecondition => match status.get_details_error_info() {
+                Some(info) if info.reason == VERSION_MISMATCH => Error::VersionMismatch {
+                    current: info
+                        .metadata
+                        .get(CURRENT_VERSION)
+                        .and_then(|version| version.parse().ok()),
+                },
+                _ => Error::Other(Box::new(status)),
+            },
+            _ => Error::Other(Box::new(status)),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+pub mod backend_server {
+    tonic::include_proto!("kv");
+}
+
+mod client;
+mod config;
+mod error;
+
+pub use client::{Client, Entry};
+pub use config::{ClientBuilder, RetryPolicy};
+pub use error::Error;
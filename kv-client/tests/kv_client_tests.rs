@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use backend::{
+    auth::hash_api_key,
+    config::{ApiKey, Settings},
+};
+use kv_client::{Client, Entry, Error, RetryPolicy};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn put_get_and_delete_should_round_trip_with_versions() {
+    let url = spawn_backend(Settings::default()).await;
+    let client = Client::builder(url).connect().await.unwrap();
+
+    assert_eq!(None, client.get("key1").await.unwrap());
+    assert_eq!(1, client.put("key1", "value1").await.unwrap());
+    assert_eq!(2, client.put("key1", "value2").await.unwrap());
+    assert_eq!(
+        Some(Entry {
+            key: "key1".to_string(),
+            value: "value2".to_string(),
+            version: 2,
+        }),
+        client.get("key1").await.unwrap()
+    );
+
+    assert!(client.delete("key1").await.unwrap());
+    assert!(!client.delete("key1").await.unwrap());
+    assert_eq!(None, client.get("key1").await.unwrap());
+}
+
+#[tokio::test]
+async fn cas_should_report_current_version_on_mismatch() {
+    let url = spawn_backend(Settings::default()).await;
+    let client = Client::builder(url).connect().await.unwrap();
+
+    assert_eq!(1, client.cas("key1", None, "value1").await.unwrap());
+    assert!(matches!(
+        client.cas("key1", None, "value2").await.unwrap_err(),
+        Error::VersionMismatch { current: Some(1) }
+    ));
+    assert_eq!(2, client.cas("key1", Some(1), "value2").await.unwrap());
+
+    client.delete("key1").await.unwrap();
+    assert!(matches!(
+        client.cas("key1", Some(2), "value3").await.unwrap_err(),
+        Error::VersionMismatch { current: None }
+    ));
+}
+
+#[tokio::test]
+async fn scan_should_return_keys_under_prefix_in_order() {
+    let url = spawn_backend(Settings::default()).await;
+    let client = Client::builder(url).connect().await.unwrap();
+    for key in ["app:c", "app:a", "other:b", "app:b"] {
+        client.put(key, "value").await.unwrap();
+    }
+
+    let keys = |entries: Vec<Entry>| -> Vec<String> {
+        entries.into_iter().map(|entry| entry.key).collect()
+    };
+    assert_eq!(
+        vec!["app:a", "app:b", "app:c"],
+        keys(client.scan("app:", None).await.unwrap())
+    );
+    assert_eq!(
+        vec!["app:a", "app:b"],
+        keys(client.scan("app:", Some(2)).await.unwrap())
+    );
+}
+
+#[tokio::test]
+async fn failed_calls_should_map_to_typed_errors() {
+    let mut settings = Settings::default();
+    settings.auth.policy_file = Some("../backend/tests/fixtures/policy.yml".to_string());
+    settings.auth.api_keys = vec![ApiKey {
+        name: "batch-job".to_string(),
+        key_hash: hash_api_key("batch-key"),
+    }];
+    let url = spawn_backend(settings).await;
+
+    let client = Client::builder(url.clone()).connect().await.unwrap();
+    assert!(matches!(
+        client.get("team-a:key1").await.unwrap_err(),
+        Error::Unauthenticated(_)
+    ));
+
+    let client = Client::builder(url)
+        .api_key("batch-key")
+        .connect()
+        .await
+        .unwrap();
+    client.put("team-a:key1", "value1").await.unwrap();
+    assert!(matches!(
+        client.put("team-b:key1", "value1").await.unwrap_err(),
+        Error::PermissionDenied(_)
+    ));
+}
+
+#[tokio::test]
+async fn calls_should_be_retried_until_backend_is_up() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let client = Client::builder(format!("http://{}", addr))
+        .retry_policy(RetryPolicy {
+            max_attempts: 20,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(200),
+        })
+        .connect_lazy()
+        .unwrap();
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let listener = TcpListener::bind(addr).await.unwrap();
+        backend::run(listener, None, &Settings::default())
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(1, client.put("key1", "value1").await.unwrap());
+}
+
+#[tokio::test]
+async fn retries_should_stop_at_deadline() {
+    let url = unreachable().await;
+    let client = Client::builder(url.clone())
+        .timeout(Duration::from_millis(300))
+        .retry_policy(RetryPolicy {
+            max_attempts: 100,
+            ..RetryPolicy::default()
+        })
+        .connect_lazy()
+        .unwrap();
+
+    let start = Instant::now();
+    assert!(matches!(
+        client.get("key1").await.unwrap_err(),
+        Error::DeadlineExceeded
+    ));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let client = Client::builder(url)
+        .retry_policy(RetryPolicy::none())
+        .connect_lazy()
+        .unwrap();
+    assert!(matches!(
+        client.get("key1").await.unwrap_err(),
+        Error::Unavailable(_)
+    ));
+}
+
+#[tokio::test]
+async fn connect_should_fail_on_invalid_configuration() {
+    let url = unreachable().await;
+    let error = Client::builder(url)
+        .retry_policy(RetryPolicy::none())
+        .connect()
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Connect(_)));
+
+    let error = Client::builder("https://localhost:50051")
+        .ca_file("missing.pem")
+        .connect()
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Config(_)));
+}
+
+async fn spawn_backend(settings: Settings) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &settings).await.unwrap();
+    });
+
+    format!("http://{}", addr)
+}
+
+// Address nothing listens on.
+async fn unreachable() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    format!("http://{}", addr)
+}
@@ -0,0 +1,7 @@
+use sha2::{Digest, Sha256};
+
+// API keys are configured by their hash, so both services can check them
+// without keeping the keys themselves.
+pub fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
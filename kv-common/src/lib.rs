@@ -0,0 +1,4 @@
+pub mod auth;
+pub mod policy;
+pub mod telemetry;
+pub mod tls;
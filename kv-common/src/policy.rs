@@ -0,0 +1,73 @@
+use std::collections::{HashMap, HashSet};
+
+use config::{Config, ConfigError, File};
+use serde::Deserialize;
+use tracing::info;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Right {
+    Read,
+    Write,
+    Delete,
+    Admin,
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct Policy {
+    #[serde(default)]
+    roles: HashMap<String, Vec<Grant>>,
+    #[serde(default)]
+    bindings: HashMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Grant {
+    prefix: Option<String>,
+    namespace: Option<String>,
+    rights: HashSet<Right>,
+}
+
+impl Grant {
+    fn covers(&self, key: &str) -> bool {
+        match (&self.prefix, &self.namespace) {
+            (Some(prefix), _) => key.starts_with(prefix.as_str()),
+            (None, Some(namespace)) => key
+                .strip_prefix(namespace.as_str())
+                .is_some_and(|rest| rest.starts_with(':')),
+            (None, None) => true,
+        }
+    }
+
+    fn grants(&self, right: Right) -> bool {
+        self.rights.contains(&right) || self.rights.contains(&Right::Admin)
+    }
+}
+
+impl Policy {
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let policy: Policy = Config::builder()
+            .add_source(File::with_name(path))
+            .build()?
+            .try_deserialize()?;
+
+        info!(
+            "Loaded policy with {} role(s) and {} binding(s).",
+            policy.roles.len(),
+            policy.bindings.len()
+        );
+
+        Ok(policy)
+    }
+
+    pub fn allows(&self, principal: &str, roles: &[String], right: Right, key: &str) -> bool {
+        self.bindings
+            .get(principal)
+            .into_iter()
+            .flatten()
+            .chain(roles)
+            .filter_map(|role| self.roles.get(role))
+            .flatten()
+            .any(|grant| grant.covers(key) && grant.grants(right))
+    }
+}
@@ -0,0 +1,52 @@
+use opentelemetry::{
+    global,
+    trace::{TraceError, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Config, TracerProvider},
+    Resource,
+};
+use tracing::{subscriber::set_global_default, Subscriber};
+use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
+
+pub fn get_subscriber(
+    name: &str,
+    env_filter: &str,
+    otlp_endpoint: Option<&str>,
+) -> Result<(impl Subscriber + Send + Sync, TracerProvider), TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+
+    let mut provider = TracerProvider::builder().with_config(Config::default().with_resource(
+        Resource::new(vec![KeyValue::new("service.name", name.to_string())]),
+    ));
+
+    if let Some(endpoint) = otlp_endpoint {
+        let exporter = opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(endpoint)
+            .build_span_exporter()?;
+
+        provider = provider.with_batch_exporter(exporter, runtime::TokioCurrentThread);
+    }
+
+    let provider = provider.build();
+    let tracer = provider.tracer(name.to_string());
+
+    let subscriber = Registry::default()
+        .with(env_filter)
+        .with(fmt::layer())
+        .with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+    Ok((subscriber, provider))
+}
+
+pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
+    set_global_default(subscriber).expect("Failed to set subscriber.");
+}
@@ -0,0 +1,70 @@
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time::interval,
+};
+use tracing::{error, info};
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+// The certificate of a server, reloaded from the files it was loaded from.
+pub trait Reload: Send + Sync + 'static {
+    fn reload(&self) -> Result<(), io::Error>;
+
+    // Modification times of the files, a change triggers a reload.
+    fn modified(&self) -> Vec<Option<SystemTime>>;
+}
+
+// Reloads the certificate when the watched files change or on SIGHUP. A failed
+// reload keeps serving the previous certificate and is retried on the next change.
+pub fn watch(store: Arc<impl Reload>, reload_interval_secs: u64) -> Result<(), io::Error> {
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    tokio::spawn(async move {
+        let mut ticker = interval(Duration::from_secs(reload_interval_secs.max(1)));
+        let mut modified = store.modified();
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick(), if reload_interval_secs > 0 => {
+                    let current = store.modified();
+                    if current == modified {
+                        continue;
+                    }
+
+                    info!("Certificate files changed, reloading.");
+                    match store.reload() {
+                        Ok(()) => modified = current,
+                        Err(e) => error!("Failed to reload certificate: {:?}", e),
+                    }
+                }
+                signal = hangup.recv() => {
+                    if signal.is_none() {
+                        break;
+                    }
+
+                    info!("Received SIGHUP, reloading certificate.");
+                    match store.reload() {
+                        Ok(()) => modified = store.modified(),
+                        Err(e) => error!("Failed to reload certificate: {:?}", e),
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    Table,
+    Json,
+}
+
+/// Command-line client for the KV backend's gRPC API.
+#[derive(Parser, Clone, Debug)]
+#[command(name = "kvctl", version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub connection: Connection,
+
+    /// Output format, json output is meant for scripts.
+    #[arg(long, global = true, value_enum, default_value_t = Output::Table)]
+    pub output: Output,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct Connection {
+    /// Address of the backend, an https URL enables TLS.
+    #[arg(
+        long,
+        global = true,
+        env = "KVCTL_URL",
+        default_value = "http://localhost:50051"
+    )]
+    pub url: String,
+
+    /// API key sent as bearer token.
+    #[arg(long, global = true, env = "KV_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+
+    /// CA certificate used to verify the backend.
+    #[arg(long, global = true)]
+    pub ca_file: Option<PathBuf>,
+
+    /// Name expected in the backend's certificate.
+    #[arg(long, global = true)]
+    pub domain_name: Option<String>,
+
+    /// Client certificate for mutual TLS.
+    #[arg(long, global = true, requires = "key_file")]
+    pub cert_file: Option<PathBuf>,
+
+    /// Key of the client certificate.
+    #[arg(long, global = true, requires = "cert_file")]
+    pub key_file: Option<PathBuf>,
+
+    /// Deadline of every request, watches excluded.
+    #[arg(long, global = true, default_value = "10s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Print the value of a key.
+    Get { key: String },
+
+    /// Set the value of a key.
+    Put {
+        key: String,
+        value: String,
+        /// Expire the value after this long, e.g. 30s or 1h.
+        #[arg(long, value_parser = humantime::parse_duration)]
+        ttl: Option<Duration>,
+    },
+
+    /// Delete a key.
+    Delete { key: String },
+
+    /// List keys starting with a prefix, in order.
+    Scan {
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Stop after this many keys.
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// Print changes of keys starting with a prefix as they happen.
+    Watch {
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Stop after this many changes.
+        #[arg(long)]
+        count: Option<usize>,
+    },
+
+    /// Put key-value pairs from JSON Lines, one {"key": ..., "value": ...}
+    /// object per line.
+    Import {
+        /// File to read, standard input if not given.
+        file: Option<PathBuf>,
+    },
+
+    /// Write keys starting with a prefix as JSON Lines.
+    Export {
+        /// File to write, standard output if not given.
+        file: Option<PathBuf>,
+        #[arg(long, default_value = "")]
+        prefix: String,
+    },
+
+    /// Administrative commands.
+    #[command(subcommand)]
+    Admin(Admin),
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Admin {
+    /// Query the audit log.
+    Audit {
+        /// Only changes of this key.
+        #[arg(long, default_value = "")]
+        key: String,
+        /// Only changes at or after this RFC 3339 time.
+        #[arg(long, default_value = "")]
+        from: String,
+        /// Only changes at or before this RFC 3339 time.
+        #[arg(long, default_value = "")]
+        to: String,
+        /// Only the most recent changes.
+        #[arg(long, default_value_t = 0)]
+        limit: u32,
+    },
+
+    /// Show or change namespace quotas.
+    #[command(subcommand)]
+    Quota(Quota),
+
+    /// Write a consistent snapshot of the whole store, for the backend's
+    /// restore mode.
+    Backup {
+        /// Archive to write, only created once the backup is complete.
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Quota {
+    /// Show the quota and usage of a namespace.
+    Get { namespace: String },
+
+    /// Replace the quota of a namespace, unset limits are removed.
+    Set {
+        namespace: String,
+        #[arg(long)]
+        max_keys: Option<u64>,
+        #[arg(long)]
+        max_bytes: Option<u64>,
+        #[arg(long)]
+        max_value_bytes: Option<u64>,
+    },
+}
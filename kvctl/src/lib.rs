@@ -0,0 +1,670 @@
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity},
+    Request, Status,
+};
+
+use crate::backend_server::{
+    admin_client::AdminClient, kv_client::KvClient, watch_event::Kind, BackupRequest,
+    DeleteValueRequest, GetQuotaUsageRequest, GetValueRequest, InsertValueRequest,
+    QueryAuditRequest, ScanRequest, SetQuotaRequest, WatchRequest,
+};
+use crate::cli::{Admin, Cli, Command, Connection, Output, Quota};
+
+pub mod cli;
+pub mod output;
+
+pub mod backend_server {
+    tonic::include_proto!("kv");
+}
+
+// Keys requested per Scan call, the backend caps pages at this size too.
+const SCAN_PAGE: usize = 1000;
+
+#[derive(Debug)]
+pub enum Error {
+    Connect(tonic::transport::Error),
+    Status(Status),
+    Io(io::Error),
+    Input(String),
+    // Number of lines that failed, each was already reported.
+    Import(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connect(error) => match std::error::Error::source(error) {
+                Some(source) => write!(f, "Failed to connect: {}: {}", error, source),
+                None => write!(f, "Failed to connect: {}", error),
+            },
+            Error::Status(status) => write!(f, "{:?}: {}", status.code(), status.message()),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Input(message) => write!(f, "{}", message),
+            Error::Import(failed) => write!(f, "{} line(s) failed to import.", failed),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<tonic::transport::Error> for Error {
+    fn from(error: tonic::transport::Error) -> Self {
+        Error::Connect(error)
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        Error::Status(status)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+pub struct Client {
+    kv: KvClient<Channel>,
+    admin: AdminClient<Channel>,
+    authorization: Option<MetadataValue<Ascii>>,
+    timeout: Duration,
+}
+
+impl Client {
+    pub async fn connect(connection: &Connection) -> Result<Self, Error> {
+        let mut endpoint =
+            Endpoint::from_shared(connection.url.clone())?.connect_timeout(connection.timeout);
+        if connection.url.starts_with("https://") {
+            endpoint = endpoint.tls_config(tls(connection)?)?;
+        }
+
+        let channel = endpoint.connect().await?;
+        let authorization = connection
+            .api_key
+            .as_ref()
+            .map(|api_key| format!("Bearer {}", api_key).parse())
+            .transpose()
+            .map_err(|_| Error::Input("API key is not valid metadata.".to_string()))?;
+
+        Ok(Client {
+            kv: KvClient::new(channel.clone()),
+            admin: AdminClient::new(channel),
+            authorization,
+            timeout: connection.timeout,
+        })
+    }
+
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = self.stream_request(message);
+        request.set_timeout(self.timeout);
+
+        request
+    }
+
+    // Streams run until the caller stops them, so they get no deadline.
+    fn stream_request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(authorization) = &self.authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", authorization.clone());
+        }
+
+        request
+    }
+
+    pub async fn get(&mut self, key: &str) -> Result<String, Status> {
+        let request = self.request(GetValueRequest {
+            key: key.to_string(),
+        });
+
+        Ok(self.kv.get_value(request).await?.into_inner().value)
+    }
+
+    pub async fn put(
+        &mut self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), Status> {
+        let request = self.request(InsertValueRequest {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl_seconds: ttl.map_or(0, |ttl| ttl.as_secs().max(1)),
+        });
+        self.kv.insert_value(request).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&mut self, key: &str) -> Result<(), Status> {
+        let request = self.request(DeleteValueRequest {
+            key: key.to_string(),
+        });
+        self.kv.delete_value(request).await?;
+
+        Ok(())
+    }
+
+    // Calls `page` with every page of the scan until it's done or `limit`
+    // keys were returned.
+    pub async fn scan(
+        &mut self,
+        prefix: &str,
+        limit: Option<usize>,
+        mut page: impl FnMut(Vec<Entry>) -> io::Result<()>,
+    ) -> Result<usize, Error> {
+        let mut start_after = String::new();
+        let mut scanned = 0;
+
+        loop {
+            let remaining = limit.map_or(SCAN_PAGE, |limit| (limit - scanned).min(SCAN_PAGE));
+            if remaining == 0 {
+                return Ok(scanned);
+            }
+
+            let request = self.request(ScanRequest {
+                prefix: prefix.to_string(),
+                start_after,
+                limit: remaining as u32,
+            });
+            let response = self.kv.scan(request).await?.into_inner();
+
+            let entries: Vec<Entry> = response
+                .entries
+                .into_iter()
+                .take(remaining)
+                .map(|entry| Entry {
+                    key: entry.key,
+                    value: entry.value,
+                    version: entry.version,
+                })
+                .collect();
+            scanned += entries.len();
+            page(entries)?;
+
+            if response.next_start_after.is_empty() {
+                return Ok(scanned);
+            }
+            start_after = response.next_start_after;
+        }
+    }
+}
+
+fn tls(connection: &Connection) -> Result<ClientTlsConfig, io::Error> {
+    let mut tls = ClientTlsConfig::new();
+
+    if let Some(ca_file) = &connection.ca_file {
+        tls = tls.ca_certificate(Certificate::from_pem(fs::read_to_string(ca_file)?));
+    }
+    if let Some(domain_name) = &connection.domain_name {
+        tls = tls.domain_name(domain_name);
+    }
+    if let (Some(cert_file), Some(key_file)) = (&connection.cert_file, &connection.key_file) {
+        tls = tls.identity(Identity::from_pem(
+            fs::read_to_string(cert_file)?,
+            fs::read_to_string(key_file)?,
+        ));
+    }
+
+    Ok(tls)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub version: u64,
+}
+
+// Lines of an import, the version of exported entries is ignored.
+#[derive(Deserialize)]
+struct ImportEntry {
+    key: String,
+    value: String,
+    #[serde(default)]
+    ttl_seconds: u64,
+}
+
+#[derive(Serialize)]
+struct ImportError {
+    line: usize,
+    error: String,
+}
+
+#[derive(Serialize)]
+struct ImportSummary {
+    imported: usize,
+    failed: usize,
+    errors: Vec<ImportError>,
+}
+
+#[derive(Serialize)]
+struct Change {
+    kind: &'static str,
+    key: String,
+    value: String,
+    version: u64,
+}
+
+#[derive(Serialize)]
+struct AuditEntry {
+    timestamp: String,
+    principal: String,
+    action: String,
+    key: String,
+    old_version: Option<u64>,
+    new_version: Option<u64>,
+    request_id: String,
+}
+
+#[derive(Serialize)]
+struct QuotaLimits {
+    max_keys: Option<u64>,
+    max_bytes: Option<u64>,
+    max_value_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct QuotaUsage {
+    namespace: String,
+    quota: Option<QuotaLimits>,
+    keys: u64,
+    bytes: u64,
+}
+
+fn optional(value: Option<u64>) -> String {
+    value.map_or("-".to_string(), |value| value.to_string())
+}
+
+pub async fn run(cli: &Cli, out: &mut impl Write) -> Result<(), Error> {
+    let mut client = Client::connect(&cli.connection).await?;
+    let output = cli.output;
+
+    match &cli.command {
+        Command::Get { key } => {
+            let value = client.get(key).await?;
+            match output {
+                Output::Table => writeln!(out, "{}", value)?,
+                Output::Json => {
+                    output::json(out, &serde_json::json!({ "key": key, "value": value }))?
+                }
+            }
+        }
+        Command::Put { key, value, ttl } => {
+            client.put(key, value, *ttl).await?;
+            done(out, output, key)?;
+        }
+        Command::Delete { key } => {
+            client.delete(key).await?;
+            done(out, output, key)?;
+        }
+        Command::Scan { prefix, limit } => {
+            let mut entries = Vec::new();
+            client
+                .scan(prefix, *limit, |page| {
+                    entries.extend(page);
+                    Ok(())
+                })
+                .await?;
+
+            output::rows(
+                out,
+                output,
+                &["KEY", "VERSION", "VALUE"],
+                &entries,
+                |entry| {
+                    vec![
+                        entry.key.clone(),
+                        entry.version.to_string(),
+                        entry.value.clone(),
+                    ]
+                },
+            )?;
+        }
+        Command::Watch { prefix, count } => watch(&mut client, out, output, prefix, *count).await?,
+        Command::Import { file } => {
+            let input: Box<dyn BufRead + Send> = match file {
+                Some(file) => Box::new(BufReader::new(fs::File::open(file)?)),
+                None => Box::new(BufReader::new(io::stdin())),
+            };
+            import(&mut client, out, output, input).await?;
+        }
+        Command::Export { file, prefix } => match file {
+            Some(file) => {
+                let mut writer = io::BufWriter::new(fs::File::create(file)?);
+                let exported = export(&mut client, &mut writer, prefix).await?;
+                writer.flush()?;
+
+                match output {
+                    Output::Table => writeln!(out, "Exported {} keys.", exported)?,
+                    Output::Json => {
+                        output::json(out, &serde_json::json!({ "exported": exported }))?
+                    }
+                }
+            }
+            None => {
+                export(&mut client, out, prefix).await?;
+            }
+        },
+        Command::Admin(Admin::Audit {
+            key,
+            from,
+            to,
+            limit,
+        }) => {
+            let request = client.request(QueryAuditRequest {
+                key: key.clone(),
+                from: from.clone(),
+                to: to.clone(),
+                limit: *limit,
+            });
+            let entries: Vec<AuditEntry> = client
+                .admin
+                .query_audit(request)
+                .await?
+                .into_inner()
+                .entries
+                .into_iter()
+                .map(|entry| AuditEntry {
+                    timestamp: entry.timestamp,
+                    principal: entry.principal,
+                    action: entry.action,
+                    key: entry.key,
+                    old_version: entry.old_version,
+                    new_version: entry.new_version,
+                    request_id: entry.request_id,
+                })
+                .collect();
+
+            output::rows(
+                out,
+                output,
+                &[
+                    "TIMESTAMP",
+                    "PRINCIPAL",
+                    "ACTION",
+                    "KEY",
+                    "OLD",
+                    "NEW",
+                    "REQUEST ID",
+                ],
+                &entries,
+                |entry| {
+                    vec![
+                        entry.timestamp.clone(),
+                        entry.principal.clone(),
+                        entry.action.clone(),
+                        entry.key.clone(),
+                        optional(entry.old_version),
+                        optional(entry.new_version),
+                        entry.request_id.clone(),
+                    ]
+                },
+            )?;
+        }
+        Command::Admin(Admin::Quota(Quota::Get { namespace })) => {
+            let request = client.request(GetQuotaUsageRequest {
+                namespace: namespace.clone(),
+            });
+            let response = client.admin.get_quota_usage(request).await?.into_inner();
+            let usage = QuotaUsage {
+                namespace: response.namespace,
+                quota: response.quota.map(|quota| QuotaLimits {
+                    max_keys: quota.max_keys,
+                    max_bytes: quota.max_bytes,
+                    max_value_bytes: quota.max_value_bytes,
+                }),
+                keys: response.keys,
+                bytes: response.bytes,
+            };
+
+            match output {
+                Output::Json => output::json(out, &usage)?,
+                Output::Table => {
+                    let limit = |limit: fn(&QuotaLimits) -> Option<u64>| {
+                        optional(usage.quota.as_ref().and_then(limit))
+                    };
+
+                    output::table(
+                        out,
+                        &[
+                            "NAMESPACE",
+                            "KEYS",
+                            "MAX KEYS",
+                            "BYTES",
+                            "MAX BYTES",
+                            "MAX VALUE BYTES",
+                        ],
+                        vec![vec![
+                            usage.namespace.clone(),
+                            usage.keys.to_string(),
+                            limit(|quota| quota.max_keys),
+                            usage.bytes.to_string(),
+                            limit(|quota| quota.max_bytes),
+                            limit(|quota| quota.max_value_bytes),
+                        ]],
+                    )?;
+                }
+            }
+        }
+        Command::Admin(Admin::Quota(Quota::Set {
+            namespace,
+            max_keys,
+            max_bytes,
+            max_value_bytes,
+        })) => {
+            let request = client.request(SetQuotaRequest {
+                namespace: namespace.clone(),
+                quota: Some(backend_server::Quota {
+                    max_keys: *max_keys,
+                    max_bytes: *max_bytes,
+                    max_value_bytes: *max_value_bytes,
+                }),
+            });
+            client.admin.set_quota(request).await?;
+            done(out, output, namespace)?;
+        }
+        Command::Admin(Admin::Backup { file }) => {
+            let revision = backup(&mut client, file).await?;
+
+            match output {
+                Output::Table => writeln!(
+                    out,
+                    "Backed up to {} at revision {}.",
+                    file.display(),
+                    revision
+                )?,
+                Output::Json => output::json(
+                    out,
+                    &serde_json::json!({ "file": file, "revision": revision }),
+                )?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn done(out: &mut impl Write, output: Output, key: &str) -> io::Result<()> {
+    match output {
+        Output::Table => writeln!(out, "OK"),
+        Output::Json => output::json(out, &serde_json::json!({ "key": key, "success": true })),
+    }
+}
+
+// Changes are printed one per line as they arrive, as JSON Lines in json
+// output so they can be piped into other tools.
+async fn watch(
+    client: &mut Client,
+    out: &mut impl Write,
+    output: Output,
+    prefix: &str,
+    count: Option<usize>,
+) -> Result<(), Error> {
+    let request = client.stream_request(WatchRequest {
+        prefix: prefix.to_string(),
+    });
+    let mut changes = client.kv.watch(request).await?.into_inner();
+    let mut seen = 0;
+
+    while count.is_none_or(|count| seen < count) {
+        let Some(event) = changes.message().await? else {
+            break;
+        };
+
+        let change = Change {
+            kind: match event.kind() {
+                Kind::Put => "put",
+                Kind::Delete => "delete",
+            },
+            key: event.key,
+            value: event.value,
+            version: event.version,
+        };
+
+        match output {
+            Output::Table => writeln!(
+                out,
+                "{:<6}  {}  {}  {}",
+                change.kind.to_uppercase(),
+                change.key,
+                change.version,
+                change.value
+            )?,
+            Output::Json => {
+                serde_json::to_writer(&mut *out, &change).map_err(io::Error::from)?;
+                writeln!(out)?;
+            }
+        }
+        out.flush()?;
+        seen += 1;
+    }
+
+    Ok(())
+}
+
+async fn import(
+    client: &mut Client,
+    out: &mut impl Write,
+    output: Output,
+    input: impl BufRead,
+) -> Result<(), Error> {
+    let mut summary = ImportSummary {
+        imported: 0,
+        failed: 0,
+        errors: Vec::new(),
+    };
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match serde_json::from_str::<ImportEntry>(&line) {
+            Ok(entry) => {
+                let ttl = (entry.ttl_seconds > 0).then(|| Duration::from_secs(entry.ttl_seconds));
+                client
+                    .put(&entry.key, &entry.value, ttl)
+                    .await
+                    .map_err(|status| Error::Status(status).to_string())
+            }
+            Err(error) => Err(format!("Invalid line: {}", error)),
+        };
+
+        match result {
+            Ok(()) => summary.imported += 1,
+            Err(error) => {
+                summary.failed += 1;
+                summary.errors.push(ImportError {
+                    line: index + 1,
+                    error,
+                });
+            }
+        }
+    }
+
+    match output {
+        Output::Json => output::json(out, &summary)?,
+        Output::Table => {
+            writeln!(
+                out,
+                "Imported {} keys, {} failed.",
+                summary.imported, summary.failed
+            )?;
+            for error in &summary.errors {
+                writeln!(out, "line {}: {}", error.line, error.error)?;
+            }
+        }
+    }
+
+    match summary.failed {
+        0 => Ok(()),
+        failed => Err(Error::Import(failed)),
+    }
+}
+
+async fn export(client: &mut Client, out: &mut impl Write, prefix: &str) -> Result<usize, Error> {
+    client
+        .scan(prefix, None, |page| {
+            for entry in page {
+                serde_json::to_writer(&mut *out, &entry)?;
+                writeln!(out)?;
+            }
+
+            Ok(())
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct Footer {
+    #[serde(rename = "type")]
+    kind: String,
+    revision: u64,
+}
+
+// The archive is written next to `file` and only moved there once its footer
+// arrived, so an interrupted backup never looks complete. Returns the
+// revision of the backup.
+async fn backup(client: &mut Client, file: &Path) -> Result<u64, Error> {
+    let mut partial = file.as_os_str().to_owned();
+    partial.push(".partial");
+
+    let request = client.stream_request(BackupRequest {});
+    let mut chunks = client.admin.backup(request).await?.into_inner();
+    let mut writer = io::BufWriter::new(fs::File::create(&partial)?);
+    let mut last = Vec::new();
+
+    while let Some(chunk) = chunks.message().await? {
+        writer.write_all(&chunk.data)?;
+        last = chunk.data;
+    }
+    writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()?;
+
+    let footer = last
+        .trim_ascii_end()
+        .rsplit(|byte| *byte == b'\n')
+        .next()
+        .and_then(|line| serde_json::from_slice::<Footer>(line).ok())
+        .filter(|footer| footer.kind == "footer")
+        .ok_or_else(|| Error::Input("Backup ended without a footer.".to_string()))?;
+    fs::rename(&partial, file)?;
+
+    Ok(footer.revision)
+}
@@ -0,0 +1,18 @@
+use std::{io, process::ExitCode};
+
+use clap::Parser;
+
+use kvctl::cli::Cli;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match kvctl::run(&cli, &mut io::stdout().lock()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
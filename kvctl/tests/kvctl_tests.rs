@@ -0,0 +1,211 @@
+use backend::config::Settings;
+use clap::Parser;
+use kvctl::{cli::Cli, Error};
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tonic::Code;
+
+#[tokio::test]
+async fn put_get_and_delete_should_round_trip() {
+    let url = spawn_backend().await;
+
+    assert_eq!(
+        "OK\n",
+        kvctl(&url, &["put", "key1", "value1"]).await.unwrap()
+    );
+    assert_eq!("value1\n", kvctl(&url, &["get", "key1"]).await.unwrap());
+
+    let json: Value = serde_json::from_str(
+        &kvctl(&url, &["get", "key1", "--output", "json"])
+            .await
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        serde_json::json!({ "key": "key1", "value": "value1" }),
+        json
+    );
+
+    assert_eq!("OK\n", kvctl(&url, &["delete", "key1"]).await.unwrap());
+    match kvctl(&url, &["get", "key1"]).await.unwrap_err() {
+        Error::Status(status) => assert_eq!(Code::NotFound, status.code()),
+        error => panic!("Expected NotFound, got {}.", error),
+    }
+}
+
+#[tokio::test]
+async fn scan_should_list_keys_under_prefix_in_order() {
+    let url = spawn_backend().await;
+    for key in ["app:b", "app:a", "other:c", "app:c"] {
+        kvctl(&url, &["put", key, "value"]).await.unwrap();
+    }
+
+    let table = kvctl(&url, &["scan", "--prefix", "app:"]).await.unwrap();
+    assert_eq!(
+        "KEY    VERSION  VALUE\n\
+         app:a  1        value\n\
+         app:b  1        value\n\
+         app:c  1        value\n",
+        table
+    );
+
+    let json = kvctl(
+        &url,
+        &[
+            "scan", "--prefix", "app:", "--limit", "2", "--output", "json",
+        ],
+    )
+    .await
+    .unwrap();
+    let entries: Vec<Value> = serde_json::from_str(&json).unwrap();
+    let keys: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(vec!["app:a", "app:b"], keys);
+}
+
+#[tokio::test]
+async fn exported_keys_should_import_into_another_backend() {
+    let source = spawn_backend().await;
+    let target = spawn_backend().await;
+    for (key, value) in [("app:a", "1"), ("app:b", "2"), ("other:c", "3")] {
+        kvctl(&source, &["put", key, value]).await.unwrap();
+    }
+
+    let file = std::env::temp_dir().join(format!("kvctl-export-{}.jsonl", std::process::id()));
+    let path = file.display().to_string();
+    let exported = kvctl(&source, &["export", &path, "--prefix", "app:"])
+        .await
+        .unwrap();
+    assert_eq!("Exported 2 keys.\n", exported);
+
+    let imported = kvctl(&target, &["import", &path]).await.unwrap();
+    assert_eq!("Imported 2 keys, 0 failed.\n", imported);
+    assert_eq!("2\n", kvctl(&target, &["get", "app:b"]).await.unwrap());
+
+    std::fs::write(&file, "{\"key\": \"app:d\", \"value\": \"4\"}\nnot json\n").unwrap();
+    let mut out = Vec::new();
+    let error = run(&target, &["import", &path], &mut out)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Import(1)));
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("Imported 1 keys, 1 failed.\nline 2: Invalid line"));
+
+    std::fs::remove_file(file).unwrap();
+}
+
+#[tokio::test]
+async fn watch_should_print_changes_until_count() {
+    let url = spawn_backend().await;
+
+    let watch = tokio::spawn({
+        let url = url.clone();
+        async move {
+            kvctl(
+                &url,
+                &[
+                    "watch", "--prefix", "app:", "--count", "2", "--output", "json",
+                ],
+            )
+            .await
+        }
+    });
+    // Give the watch time to subscribe before changing keys.
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    kvctl(&url, &["put", "other:a", "ignored"]).await.unwrap();
+    kvctl(&url, &["put", "app:a", "value1"]).await.unwrap();
+    kvctl(&url, &["delete", "app:a"]).await.unwrap();
+
+    let changes: Vec<Value> = watch
+        .await
+        .unwrap()
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(2, changes.len());
+    assert_eq!("put", changes[0]["kind"]);
+    assert_eq!("value1", changes[0]["value"]);
+    assert_eq!("delete", changes[1]["kind"]);
+    assert_eq!("app:a", changes[1]["key"]);
+}
+
+#[tokio::test]
+async fn admin_should_set_and_show_namespace_quota() {
+    let url = spawn_backend().await;
+    kvctl(&url, &["put", "team-a:key1", "value1"])
+        .await
+        .unwrap();
+
+    let set = ["admin", "quota", "set", "team-a", "--max-keys", "10"];
+    assert_eq!("OK\n", kvctl(&url, &set).await.unwrap());
+
+    let json = kvctl(
+        &url,
+        &["admin", "quota", "get", "team-a", "--output", "json"],
+    )
+    .await
+    .unwrap();
+    let usage: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!("team-a", usage["namespace"]);
+    assert_eq!(10, usage["quota"]["max_keys"]);
+    assert_eq!(Value::Null, usage["quota"]["max_bytes"]);
+    assert_eq!(1, usage["keys"]);
+}
+
+#[tokio::test]
+async fn admin_backup_should_write_archive_with_revision() {
+    let url = spawn_backend().await;
+    for key in ["key1", "key2"] {
+        kvctl(&url, &["put", key, "value"]).await.unwrap();
+    }
+
+    let file = std::env::temp_dir().join(format!("kvctl-backup-{}.jsonl", std::process::id()));
+    let path = file.display().to_string();
+    let backed_up = kvctl(&url, &["admin", "backup", &path]).await.unwrap();
+    assert_eq!(format!("Backed up to {} at revision 2.\n", path), backed_up);
+
+    let archive = std::fs::read_to_string(&file).unwrap();
+    let types: Vec<_> = archive
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["type"].clone())
+        .collect();
+    assert_eq!(vec!["header", "entry", "entry", "footer"], types);
+
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn invalid_arguments_should_be_rejected() {
+    assert!(Cli::try_parse_from(["kvctl", "put", "key1"]).is_err());
+    assert!(Cli::try_parse_from(["kvctl", "put", "key1", "value1", "--ttl", "soon"]).is_err());
+    assert!(Cli::try_parse_from(["kvctl", "--cert-file", "client.pem", "get", "key1"]).is_err());
+}
+
+async fn kvctl(url: &str, args: &[&str]) -> Result<String, Error> {
+    let mut out = Vec::new();
+    run(url, args, &mut out).await?;
+
+    Ok(String::from_utf8(out).unwrap())
+}
+
+async fn run(url: &str, args: &[&str], out: &mut Vec<u8>) -> Result<(), Error> {
+    let cli = Cli::parse_from(["kvctl", "--url", url].iter().chain(args));
+
+    kvctl::run(&cli, out).await
+}
+
+async fn spawn_backend() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        backend::run(listener, None, &Settings::default())
+            .await
+            .unwrap();
+    });
+
+    format!("http://{}", addr)
+}